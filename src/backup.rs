@@ -0,0 +1,285 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*
+ * How backup file names are chosen, for file.txt with the default "~" extension
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupStyle {
+    Simple,      // file.txt~, replaced on every write
+    Numbered,    // file.txt.1~, file.txt.2~, ...
+    Timestamped, // file.txt.20250101-120000~ (UTC)
+}
+
+impl BackupStyle {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "simple" => Some(BackupStyle::Simple),
+            "numbered" => Some(BackupStyle::Numbered),
+            "timestamped" => Some(BackupStyle::Timestamped),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackupStyle::Simple => "simple",
+            BackupStyle::Numbered => "numbered",
+            BackupStyle::Timestamped => "timestamped",
+        }
+    }
+}
+
+/*
+ * Settings for copies of a file's previous contents, made before it is overwritten
+ */
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    pub backup: bool,      // Keep the backup after writing
+    pub writebackup: bool, // Make a backup while writing, deleted once the write succeeds (unless backup is set)
+    pub dir: String,       // Where backups go, "." being beside the file
+    pub ext: String,       // Added to the end of backup file names
+    pub style: BackupStyle,
+    pub keep: usize, // Generations kept by the numbered and timestamped styles
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            backup: false,
+            writebackup: true,
+            dir: String::from("."),
+            ext: String::from("~"),
+            style: BackupStyle::Simple,
+            keep: 5,
+        }
+    }
+}
+
+/*
+ * Copy a file's current contents to a new backup, returning where it went.
+ * Nothing is copied for files which don't exist yet, or when backups are turned off
+ */
+pub fn create(file_path: &Path, options: &BackupOptions) -> io::Result<Option<PathBuf>> {
+    if !(options.backup || options.writebackup) || !file_path.is_file() {
+        return Ok(None);
+    }
+    let (dir, base_name) = location(file_path, options)?;
+    fs::create_dir_all(&dir)?;
+    // Backups only made while writing don't start a new generation
+    let style = if options.backup {
+        options.style
+    } else {
+        BackupStyle::Simple
+    };
+    let backup_name = match style {
+        BackupStyle::Simple => format!("{}{}", base_name, options.ext),
+        BackupStyle::Numbered => {
+            let last = generations(&dir, &base_name, &options.ext)
+                .iter()
+                .filter_map(|generation| generation.parse::<u64>().ok())
+                .max()
+                .unwrap_or(0);
+            format!("{}.{}{}", base_name, last + 1, options.ext)
+        }
+        BackupStyle::Timestamped => {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            let stamp = timestamp(secs);
+            // Backups made within the same second are counted, so they don't overwrite each other
+            let mut backup_name = format!("{}.{}{}", base_name, stamp, options.ext);
+            let mut count = 1;
+            while dir.join(&backup_name).exists() {
+                count += 1;
+                backup_name = format!("{}.{}-{}{}", base_name, stamp, count, options.ext);
+            }
+            backup_name
+        }
+    };
+    let backup_path = dir.join(backup_name);
+    fs::copy(file_path, &backup_path)?;
+    if style != BackupStyle::Simple {
+        prune(&dir, &base_name, options);
+    }
+    Ok(Some(backup_path))
+}
+
+/*
+ * Directory holding a file's backups, and the name they start with.
+ * Backups kept in a shared directory are named after the file's whole path (ex. %home%user%notes.txt~),
+ * so files with the same name from different directories don't overwrite each other's backups
+ */
+fn location(file_path: &Path, options: &BackupOptions) -> io::Result<(PathBuf, String)> {
+    let file_path = file_path.canonicalize()?;
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if options.dir == "." {
+        let dir = file_path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        return Ok((dir, file_name));
+    }
+    let dir = match options.dir.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(rest))
+            .unwrap_or_else(|| PathBuf::from(&options.dir)),
+        None => PathBuf::from(&options.dir),
+    };
+    Ok((dir, file_path.to_string_lossy().replace('/', "%")))
+}
+
+// Generation part of every numbered or timestamped backup of a file (ex. "3" for file.txt.3~)
+fn generations(dir: &Path, base_name: &str, ext: &str) -> Vec<String> {
+    let prefix = format!("{}.", base_name);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let generation = name.strip_prefix(&prefix)?.strip_suffix(ext)?;
+            generation
+                .chars()
+                .all(|ch| ch.is_ascii_digit() || ch == '-')
+                .then(|| String::from(generation))
+        })
+        .collect()
+}
+
+/*
+ * Delete the oldest backups beyond the number of generations to keep
+ */
+fn prune(dir: &Path, base_name: &str, options: &BackupOptions) {
+    let mut generations = generations(dir, base_name, &options.ext);
+    // Numbers, and each part of a timestamp (with the count of backups made in the same second), sort by value
+    generations.sort_by_key(|generation| {
+        generation
+            .split('-')
+            .map(|part| part.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    });
+    let excess = generations.len().saturating_sub(options.keep.max(1));
+    for generation in &generations[..excess] {
+        let _ = fs::remove_file(dir.join(format!("{}.{}{}", base_name, generation, options.ext)));
+    }
+}
+
+// Seconds since 1970-01-01 as a UTC time, YYYYMMDD-HHMMSS
+fn timestamp(secs: u64) -> String {
+    let (days, day_secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        day_secs / 3_600,
+        day_secs % 3_600 / 60,
+        day_secs % 60
+    )
+}
+
+// Convert days since 1970-01-01 to a calendar date, as (year, month, day) (Howard Hinnant's civil_from_days)
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    // A file with some contents in a directory of its own, to back up
+    fn test_file(name: &str) -> (PathBuf, PathBuf) {
+        let dir =
+            std::env::temp_dir().join(format!("rust-vim-backup-test-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("notes.txt");
+        fs::write(&file_path, "first\n").unwrap();
+        (dir, file_path)
+    }
+
+    fn backup_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name != "notes.txt")
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn options(style: BackupStyle) -> BackupOptions {
+        BackupOptions {
+            backup: true,
+            style,
+            keep: 3,
+            ..BackupOptions::default()
+        }
+    }
+
+    #[test]
+    fn replaces_simple_backups() {
+        let (dir, file_path) = test_file("simple");
+        let options = options(BackupStyle::Simple);
+        create(&file_path, &options).unwrap();
+        fs::write(&file_path, "second\n").unwrap();
+        let backup_path = create(&file_path, &options).unwrap().unwrap();
+        assert_eq!(backup_names(&dir), ["notes.txt~"]);
+        assert_eq!(fs::read_to_string(backup_path).unwrap(), "second\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn numbers_backups_and_prunes_old_ones() {
+        let (dir, file_path) = test_file("numbered");
+        let options = options(BackupStyle::Numbered);
+        for _ in 0..11 {
+            create(&file_path, &options).unwrap();
+        }
+        // Only the newest 3 generations are kept, counting on past 9 by value
+        assert_eq!(
+            backup_names(&dir),
+            ["notes.txt.10~", "notes.txt.11~", "notes.txt.9~"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timestamps_backups_made_in_the_same_second_apart() {
+        let (dir, file_path) = test_file("timestamped");
+        let options = options(BackupStyle::Timestamped);
+        let backups: Vec<PathBuf> = (0..4)
+            .map(|_| create(&file_path, &options).unwrap().unwrap())
+            .collect();
+        let names = backup_names(&dir);
+        assert_eq!(names.len(), 3);
+        // The oldest backup was pruned, and those left are the newest
+        assert!(!backups[0].exists());
+        assert!(backups[1..].iter().all(|backup| backup.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(59), (1970, 3, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19_358), (2023, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(timestamp(951_782_400 + 45_296), "20000229-123456");
+    }
+}
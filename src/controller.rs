@@ -1,13 +1,41 @@
-use crate::model::EditorModel;
+use crate::backup::{BackupOptions, BackupStyle};
+use crate::encoding::Encoding;
+use crate::explorer;
+use crate::fileformat::FileFormat;
+use crate::finder::FileFinder;
+use crate::grep::{self, GrepJob};
+use crate::job::CommandJob;
+use crate::model::{self, EditorModel, OpenError};
+use crate::pattern::Pattern;
+use crate::quickfix::{self, ListSource, Location, QuickfixList};
+use crate::search::{self, SearchJob, SearchOptions, Substitute};
+use crate::session::{self, Session, TabState, WindowState};
+use crate::swap::{self, ExistingSwap};
+use crate::testrunner::{self, TestRun};
 use crate::view::MAX_HELP_SCROLL;
+use crate::watcher::FileWatcher;
+use crate::window::{LayoutNode, MIN_WINDOW_HEIGHT, MIN_WINDOW_WIDTH, TabPage, Window};
 use core::ops::Range;
-use count_digits::CountDigits;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Direction, Rect};
 use std::cmp;
+use std::env;
+use std::fs;
 use std::io;
-use unicode_display_width::width;
-
-const TAB_SIZE: u16 = 4;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Height (including border) of the quickfix window
+const LIST_WINDOW_HEIGHT: u16 = 10;
+// Read by :cfile when no file is given
+const ERROR_FILE: &str = "errors.err";
+// Command run by :make unless the makeprg option is changed
+const DEFAULT_MAKEPRG: &str = "cargo build --message-format=short";
+// How long to wait for input before checking on background work again
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long to wait for input before checking whether other programs changed open files
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Debug)]
 pub enum Mode {
@@ -18,195 +46,151 @@ pub enum Mode {
     Minimized, //Used to prevent cursor out of bounds crash when terminal is shrunk to <=4 lines tall
     Help,      // Used to display the help screen
     Quit,      // Used for :q quit popup
+    Prompt,    // Used to ask the user for a file name or confirmation in the message bar
+    Finder,    // Used for the fuzzy file finder popup
+    TestResults, // Used for the popup showing the output of a test run
+    Swap,      // Used for the popup asking what to do with another process's swap file
+    FileChanged, // Used for the popup asking what to do when another program changes an open file
 }
 
+// Questions asked in Prompt Mode, used by the directory browser
 #[derive(Debug)]
-pub enum QuitSelection {
-    SaveAndQuit,
-    NoSaveQuit,
-    Cancel,
+pub enum Prompt {
+    NewFile,
+    NewDirectory,
+    Rename(PathBuf),
+    Delete(PathBuf),
 }
 
-fn string_to_lines(
-    string: &str,
-    term_width: u16,
-    show_lines: bool,
-    highlight_ranges: &[Range<usize>],
-) -> Vec<DisplayLine> {
-    let chars = string.chars();
-    let max_line_len = term_width - 2; // to accomodate the two borders
-    let mut highlight_ranges = highlight_ranges.iter(); // typecast to iterator for easier use
-    let mut highlight_range = highlight_ranges.next();
-    let mut highlight_start_idx: Option<usize> = None;
-
-    // Start constructing vector of wrapped display lines
-    let mut lines: Vec<DisplayLine> = Vec::new();
-    let mut line = DisplayLine::new(1, 0, 0); // First (1) line has infile and inline char indices of 0
-    let mut num_chars = 0; // Used for calculating file indexing
-    let mut total_char_width = 0; // Used to track how many characters can fit on a line
-
-    // Add line numbers to first line
-    if show_lines {
-        line.line_content = String::from("1|");
-        total_char_width = 2;
-    }
-
-    // Start wrapping lines
-    for character in chars {
-        // if character is a newline, stop building line and append it to lines
-        if character == '\n' {
-            // First check if we are still highlighting
-            if let Some(start) = highlight_start_idx {
-                if show_lines {
-                    line.highlight_ranges.push(Range {
-                        start: start + line.line_num.count_digits() + 1,
-                        end: num_chars + line.line_num.count_digits() + 1,
-                    })
-                } else {
-                    line.highlight_ranges.push(Range {
-                        start,
-                        end: num_chars,
-                    });
-                }
-
-                if highlight_range.unwrap().end > line.infile_index + num_chars {
-                    highlight_start_idx = Some(0);
-                } else {
-                    highlight_start_idx = None;
-                    highlight_range = highlight_ranges.next();
-                }
-            }
+impl Prompt {
+    // Text shown in the message bar before the user's input
+    fn label(&self) -> String {
+        match self {
+            Prompt::NewFile => String::from("New file name: "),
+            Prompt::NewDirectory => String::from("New directory name: "),
+            Prompt::Rename(path) => format!("Rename {} to: ", explorer::display_path(path)),
+            Prompt::Delete(path) => format!("Delete {}? (y/n): ", explorer::display_path(path)),
+        }
+    }
+}
 
-            // Add current line and build next one
-            lines.push(line);
-            let last_line = &lines[lines.len() - 1];
-            line = DisplayLine::new(
-                last_line.line_num + 1,
-                last_line.infile_index + num_chars + 1,
-                0,
-            );
-            num_chars = 0;
-            total_char_width = 0;
+// Normal Mode commands made up of two keys
+#[derive(Debug)]
+pub enum PendingCommand {
+    Window, // [Ctrl-W] was pressed
+    Goto,   // [g] was pressed
+}
 
-            // Line Numbers for new line
-            if show_lines {
-                line.line_content = line.line_num.to_string();
-                line.line_content.push('|');
-                total_char_width = line.line_num.count_digits() as u16 + 1;
-            }
-            continue;
-        }
+/*
+ * Commands shared by the quickfix list and location lists (ex. :cnext and :lnext)
+ */
+#[derive(Debug, Clone, Copy)]
+enum ListCommand {
+    Open,
+    Close,
+    Next,
+    Prev,
+    Jump,
+    Expr,
+    File,
+    Grep,
+    Make,
+}
 
-        // Get the number of columns required to display this character (ex. some emoticons take 2 columns)
-        let char_width = if character == '\t' {
-            // Handle tab spaces seperately due to dynamic sizing
-            TAB_SIZE - (total_char_width % TAB_SIZE)
-        } else {
-            width(&character.to_string()) as u16
+impl ListCommand {
+    // Which command a name refers to, and whether it applies to the window's location list
+    fn parse(name: &str) -> Option<(Self, bool)> {
+        let parsed = match name {
+            ":copen" | ":cope" => (ListCommand::Open, false),
+            ":lopen" | ":lop" => (ListCommand::Open, true),
+            ":cclose" | ":ccl" => (ListCommand::Close, false),
+            ":lclose" | ":lcl" => (ListCommand::Close, true),
+            ":cnext" | ":cn" => (ListCommand::Next, false),
+            ":lnext" | ":lne" => (ListCommand::Next, true),
+            ":cprevious" | ":cprev" | ":cp" | ":cN" => (ListCommand::Prev, false),
+            ":lprevious" | ":lprev" | ":lp" | ":lN" => (ListCommand::Prev, true),
+            ":cc" => (ListCommand::Jump, false),
+            ":ll" => (ListCommand::Jump, true),
+            ":cexpr" | ":cex" => (ListCommand::Expr, false),
+            ":lexpr" | ":lex" => (ListCommand::Expr, true),
+            ":cfile" | ":cf" => (ListCommand::File, false),
+            ":lfile" | ":lf" => (ListCommand::File, true),
+            ":grep" | ":gr" => (ListCommand::Grep, false),
+            ":lgrep" | ":lgr" => (ListCommand::Grep, true),
+            ":make" | ":mak" => (ListCommand::Make, false),
+            ":lmake" | ":lmak" => (ListCommand::Make, true),
+            _ => return None,
         };
+        Some(parsed)
+    }
+}
 
-        // Check if character needs to be rendered on a new line
-        if total_char_width + char_width > max_line_len {
-            // Character doesn't fit, so start a new line
+fn no_list_error(source: ListSource) -> String {
+    match source {
+        ListSource::Quickfix => String::from("Error: No quickfix list"),
+        ListSource::Location(_) => String::from("Error: No location list"),
+    }
+}
 
-            // First check if we are still highlighting
-            if let Some(start) = highlight_start_idx {
-                if show_lines {
-                    line.highlight_ranges.push(Range {
-                        start: start + line.line_num.count_digits() + 1,
-                        end: num_chars + line.line_num.count_digits() + 1,
-                    })
-                } else {
-                    line.highlight_ranges.push(Range {
-                        start,
-                        end: num_chars,
-                    });
-                }
-                highlight_start_idx = Some(0);
-            }
-
-            // Add current line and build next one
-            lines.push(line);
-            let last_line = &lines[lines.len() - 1];
-            line = DisplayLine::new(
-                last_line.line_num,
-                last_line.infile_index + num_chars,
-                last_line.inline_index + num_chars,
-            );
-            num_chars = 0;
-            total_char_width = 0;
-
-            // Line Numbers for new line
-            if show_lines {
-                line.line_content = line.line_num.to_string();
-                line.line_content.push('|');
-                total_char_width = line.line_num.count_digits() as u16 + 1;
-            }
-        }
-
-        // Add character to line to be rendered
-        // Handle tabs specially due to dynamic sizes
-        if character == '\t' {
-            // Render tab space as a set of spaces
-            // while keeping track of which of them are 'invalid' for the cursor
-            line.line_content.push(' ');
-            num_chars += 1;
-            for i in 1..char_width {
-                line.line_content.push(' ');
-                line.invalid_cols.push(total_char_width + 1 + i)
-            }
-            total_char_width += char_width;
-        } else {
-            // Render character and...
-            line.line_content.push(character);
-            num_chars += 1;
-            // ...Keep track of 'invalid columns' for cursor due to wide characters
-            for i in 1..char_width {
-                line.invalid_cols.push(total_char_width + 1 + i)
-            }
-            total_char_width += char_width;
-        }
+#[derive(Debug)]
+pub enum QuitSelection {
+    SaveAndQuit,
+    NoSaveQuit,
+    Cancel,
+}
 
-        // Check Highlighting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwapSelection {
+    Recover,
+    ReadOnly,
+    Delete,
+    Abort,
+}
 
-        // Check if the char added is the last char of a match
-        let infile_char_idx = line.infile_index + num_chars - 1;
-        if let Some(start) = highlight_start_idx
-            && highlight_range.unwrap().end == infile_char_idx
-        {
-            if show_lines {
-                line.highlight_ranges.push(Range {
-                    start: start + line.line_num.count_digits() + 1,
-                    end: num_chars + line.line_num.count_digits(),
-                })
-            } else {
-                line.highlight_ranges.push(Range {
-                    start,
-                    end: num_chars - 1,
-                });
-            }
-            highlight_range = highlight_ranges.next();
-            highlight_start_idx = None;
-        }
+impl SwapSelection {
+    const ALL: [SwapSelection; 4] = [
+        SwapSelection::Recover,
+        SwapSelection::ReadOnly,
+        SwapSelection::Delete,
+        SwapSelection::Abort,
+    ];
+}
 
-        // Check if the char added is the first char of a match
-        if let Some(range) = highlight_range
-            && num_chars > 0
-            && range.start == infile_char_idx
-        {
-            highlight_start_idx = Some(num_chars - 1)
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileChangedSelection {
+    Reload,
+    KeepMine,
+    Diff,
+}
 
-    // Push the last line to lines
-    if let Some(start) = highlight_start_idx {
-        line.highlight_ranges.push(Range {
-            start,
-            end: line.line_content.len(),
-        })
-    }
-    lines.push(line);
-    lines
+impl FileChangedSelection {
+    const ALL: [FileChangedSelection; 3] = [
+        FileChangedSelection::Reload,
+        FileChangedSelection::KeepMine,
+        FileChangedSelection::Diff,
+    ];
+}
+
+// Where the cursor starts in a file given on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum StartPosition {
+    Line(usize, usize), // 1-indexed line and column, from +42 or file:42:7
+    LastLine,           // From + on its own
+    Pattern(String),    // First match of +/pattern
+}
+
+/*
+ * Search from before a query started being typed, put back if the query is cancelled
+ */
+#[derive(Debug)]
+struct SavedSearch {
+    term: String,
+    regex: Option<Pattern>,
+    job: Option<SearchJob>,
+    match_ranges: Vec<Range<usize>>,
+    match_buffer: usize,
+    match_edits: usize,
+    backward: bool,
 }
 
 #[derive(Debug)]
@@ -217,6 +201,7 @@ pub struct DisplayLine {
     pub inline_index: usize, // Char index of the start of this displayed line in the file line
     pub invalid_cols: Vec<u16>, // used to ensure cursor is never in the middle of a multi-column character
     pub highlight_ranges: Vec<Range<usize>>, // used to find which chars should be highlighted for search matching
+    pub highlight_starts: Vec<usize>, // File index of the start of the search match each highlighted range belongs to
 }
 
 impl DisplayLine {
@@ -228,59 +213,129 @@ impl DisplayLine {
             inline_index,
             invalid_cols: vec![],
             highlight_ranges: vec![],
+            highlight_starts: vec![],
         }
     }
 }
 
 #[derive(Debug)]
-pub struct App<'a> {
-    model: &'a mut EditorModel,
-    display_content: Vec<DisplayLine>, // Vector of DisplayLine structs representing content being displayed + useful info
-    scroll_amount: u16,                // How far did we scroll down display_content?
-    scroll_help_amount: u16,           // How far to scroll help popup
+pub struct App {
+    buffers: Vec<EditorModel>, // Every file opened during this session
+    tabs: Vec<TabPage>,        // Each tab page has its own window layout
+    active_tab: usize,         // Index of the tab page being shown
+    next_window_id: usize,
+    scroll_help_amount: u16, // How far to scroll help popup
     quit_selection: QuitSelection,
+    swap_selection: SwapSelection,
+    file_changed_selection: FileChangedSelection,
+    pending_starts: Vec<(usize, StartPosition)>, // Where files given on the command line start, until they're first shown
+    changed_buffers: Vec<usize>, // Buffers whose files other programs changed, waiting for the user to decide what to do
+    file_diff: Vec<String>, // Differences between the changed file and its buffer, once asked for
+    scroll_diff_amount: u16,
+    watcher: Option<FileWatcher>, // Notices other programs changing open files
+    autoread: bool, // Reload files changed by other programs without asking, when the buffer has no unsaved changes
+    prompt: Option<Prompt>, // What the user is being asked in Prompt Mode
+    finder: Option<FileFinder>, // State of the file finder popup while it is open
+    quickfix: QuickfixList, // Locations to step through (ex. compiler errors or search results)
+    grep_job: Option<(GrepJob, ListSource)>, // Project search still sending results, and the list receiving them
+    make_job: Option<(CommandJob, ListSource)>, // Build started by :make which is still running
+    make_marks: Vec<(PathBuf, usize, bool)>, // File, line and whether it's only a warning, for each problem found by the last build
+    makeprg: String,                         // Command run by :make
+    backup_options: BackupOptions, // Whether (and where) backups are made when writing files
+    test_job: Option<CommandJob>,  // Test run which is still going
+    test_run: Option<TestRun>,     // Output of the latest test run
+    show_hidden_files: bool,       // Do directory listings include hidden files?
     mode: Mode,
-    show_line_nums: bool,
     msg_display: Vec<char>, // What to show in the bottom message bar (user input, error messages, etc.)
     search_term: String, // What is being searched for in search mode. Only assigned on successful match for View highlighting
-    match_ranges: Vec<Range<usize>>, // Infile char indexes of search matches found
-    cursor_pos: (u16, u16), // cursor position in terminal. (y, x), or (row, col), with 1,1 being the top-left corner (1 not 0 due to border)
-    term_size: (u16, u16),  // Terminal size (Num rows, num cols)
+    search_regex: Option<Pattern>, // Compiled search_term, kept for searching again around edits
+    search_options: SearchOptions, // How search patterns are matched (case, regex or plain text)
+    incsearch: bool,     // Search as the query is typed
+    last_search: String, // Query last submitted, searched for again by n/N after highlighting was turned off
+    search_backward: bool, // Last search was started with ?, so n goes backward through the matches
+    search_origin: usize, // Character index the cursor was at when the search query started being typed
+    saved_search: Option<SavedSearch>, // Search from before the query being typed, restored if it's cancelled
+    pending_jump: Option<(usize, bool)>, // Move the cursor to the match after (or before) this character index, once it is found
+    search_job: Option<SearchJob>, // Search of the active window's buffer still sending matches
+    match_ranges: Vec<Range<usize>>, // Infile char indexes of search matches found in the active window's buffer
+    match_buffer: usize,             // Buffer the matches were found in
+    match_edits: usize,              // Number of the buffer's edits the matches are up to date with
+    term_size: (u16, u16),           // Terminal size (Num rows, num cols)
+    pending_command: Option<PendingCommand>, // Normal Mode command waiting for its second key
+    last_input: Instant, // When the last key was pressed, so swap files are written once typing pauses
     running: bool,
 }
 
-impl<'a> App<'a> {
-    pub fn new(
-        model: &'a mut EditorModel,
-        display_string: &'a str,
-        term_height: u16,
-        term_width: u16,
-    ) -> Self {
-        Self {
-            model,
-            display_content: string_to_lines(display_string, term_width, false, &[]),
-            scroll_amount: 0,
+impl App {
+    pub fn new(model: EditorModel, term_height: u16, term_width: u16) -> Self {
+        let mut app = Self {
+            buffers: vec![model],
+            tabs: vec![TabPage::new(Window::new(
+                0,
+                0,
+                (term_height, term_width),
+                false,
+            ))],
+            active_tab: 0,
+            next_window_id: 1,
             scroll_help_amount: 0,
             quit_selection: QuitSelection::Cancel,
+            swap_selection: SwapSelection::Recover,
+            file_changed_selection: FileChangedSelection::Reload,
+            pending_starts: vec![],
+            changed_buffers: vec![],
+            file_diff: vec![],
+            scroll_diff_amount: 0,
+            watcher: FileWatcher::new(),
+            autoread: false,
+            prompt: None,
+            finder: None,
+            quickfix: QuickfixList::default(),
+            grep_job: None,
+            make_job: None,
+            make_marks: vec![],
+            makeprg: String::from(DEFAULT_MAKEPRG),
+            backup_options: BackupOptions::default(),
+            test_job: None,
+            test_run: None,
+            show_hidden_files: false,
             mode: Mode::Normal,
-            show_line_nums: false,
             msg_display: vec![],
             search_term: String::new(),
+            search_regex: None,
+            search_options: SearchOptions::default(),
+            incsearch: true,
+            last_search: String::new(),
+            search_backward: false,
+            search_origin: 0,
+            saved_search: None,
+            pending_jump: None,
+            search_job: None,
             match_ranges: vec![],
-            cursor_pos: (1, 1),
+            match_buffer: 0,
+            match_edits: 0,
             term_size: (term_height, term_width),
+            pending_command: None,
+            last_input: Instant::now(),
             running: true,
-        }
+        };
+        app.watch_buffer(0);
+        app.resize_windows();
+        app.check_swap();
+        app
     }
 
     /*
      * Get ___ methods below:
      */
     pub fn get_filename(&self) -> &str {
-        self.model.file_name.as_str()
+        self.buffers[self.win().buffer].file_name.as_str()
     }
-    pub fn get_content(&self) -> &Vec<DisplayLine> {
-        &self.display_content
+    pub fn get_buffer_name(&self, buffer: usize) -> &str {
+        self.buffers[buffer].display_name()
+    }
+    pub fn get_cursor_line_num(&self) -> usize {
+        self.win().get_cursor_line().line_num
     }
     pub fn get_app_mode(&self) -> &Mode {
         &self.mode
@@ -289,10 +344,7 @@ impl<'a> App<'a> {
         self.msg_display.iter().collect()
     }
     pub fn get_cursor_pos(&self) -> (u16, u16) {
-        self.cursor_pos
-    }
-    pub fn get_scroll_amount(&self) -> u16 {
-        self.scroll_amount
+        self.win().cursor_pos
     }
     pub fn get_scroll_help_amount(&self) -> u16 {
         self.scroll_help_amount
@@ -300,8 +352,99 @@ impl<'a> App<'a> {
     pub fn get_quit_selection(&self) -> &QuitSelection {
         &self.quit_selection
     }
-    pub fn get_show_line_num(&self) -> bool {
-        self.show_line_nums
+    pub fn get_swap_selection(&self) -> SwapSelection {
+        self.swap_selection
+    }
+    pub fn get_existing_swap(&self) -> Option<&ExistingSwap> {
+        self.buffers[self.win().buffer].existing_swap.as_ref()
+    }
+    pub fn get_finder(&self) -> Option<&FileFinder> {
+        self.finder.as_ref()
+    }
+    // Line endings of the active buffer's file, shown in the status bar
+    pub fn get_file_format(&self) -> &str {
+        self.buffers[self.win().buffer].file_format.name()
+    }
+    // Percentage of the active buffer's file read so far, shown in the status bar while it is still loading
+    pub fn get_load_progress(&self) -> Option<u64> {
+        self.buffers[self.win().buffer].load_progress()
+    }
+    pub fn get_read_only(&self) -> bool {
+        self.buffers[self.win().buffer].read_only
+    }
+    // Character encoding of the active buffer's file, shown in the status bar
+    pub fn get_file_encoding(&self) -> &str {
+        self.buffers[self.win().buffer].file_encoding.name()
+    }
+    pub fn get_file_changed_selection(&self) -> FileChangedSelection {
+        self.file_changed_selection
+    }
+    // Name of the buffer the file changed popup is asking about
+    pub fn get_changed_file(&self) -> Option<&str> {
+        let buffer = *self.changed_buffers.first()?;
+        Some(self.buffers[buffer].file_name.as_str())
+    }
+    pub fn get_file_diff(&self) -> &[String] {
+        &self.file_diff
+    }
+    pub fn get_scroll_diff_amount(&self) -> u16 {
+        self.scroll_diff_amount
+    }
+    pub fn get_test_run(&self) -> Option<&TestRun> {
+        self.test_run.as_ref()
+    }
+    pub fn get_active_window_id(&self) -> usize {
+        self.tab().active_window
+    }
+    pub fn get_window(&self, id: usize) -> &Window {
+        self.tab()
+            .windows
+            .iter()
+            .find(|window| window.id == id)
+            .unwrap()
+    }
+
+    pub fn get_active_tab(&self) -> usize {
+        self.active_tab
+    }
+    // Tab line is only shown once there is more than one tab page
+    pub fn get_show_tab_line(&self) -> bool {
+        self.tabs.len() > 1
+    }
+    // File name of each tab page's active window, and whether that file has unsaved changes
+    pub fn get_tab_labels(&self) -> Vec<(&str, bool)> {
+        self.tabs
+            .iter()
+            .map(|tab| {
+                let model = &self.buffers[tab.win().buffer];
+                (model.display_name(), model.modified)
+            })
+            .collect()
+    }
+
+    // Used by View to offer writing changes which have no file name with :w file, as they can't just be saved
+    pub fn get_unnamed_changes(&self) -> bool {
+        self.buffers.iter().any(EditorModel::is_unnamed_change)
+    }
+
+    // Which part of the given content area does each window occupy?
+    pub fn get_window_areas(&self, area: Rect) -> Vec<(usize, Rect)> {
+        self.tab().layout.areas(area)
+    }
+
+    // Lines of the buffer with problems found by the last :make, and whether each is only a warning
+    pub fn get_marks(&self, buffer: usize) -> Vec<(usize, bool)> {
+        if self.make_marks.is_empty() {
+            return vec![];
+        }
+        let Ok(path) = Path::new(&self.buffers[buffer].file_name).canonicalize() else {
+            return vec![];
+        };
+        self.make_marks
+            .iter()
+            .filter(|(mark_path, _, _)| *mark_path == path)
+            .map(|(_, line, is_warning)| (*line, *is_warning))
+            .collect()
     }
 
     // Are we highlighting search matches?
@@ -309,209 +452,1101 @@ impl<'a> App<'a> {
         !self.search_term.is_empty()
     }
 
+    /*
+     * Used by View to show which search match the cursor is at, out of how many (ex. [3/17]).
+     * The count is marked with a + while the search is still running
+     */
+    pub fn get_match_counter(&self) -> Option<String> {
+        if !self.get_show_highlights() || self.match_ranges.is_empty() {
+            return None;
+        }
+        let index = self.cursor_char_index();
+        let current = self
+            .match_ranges
+            .partition_point(|range| range.start <= index);
+        let running = if self.search_job.is_some() { "+" } else { "" };
+        Some(format!(
+            "[{}/{}{}]",
+            current,
+            self.match_ranges.len(),
+            running
+        ))
+    }
+
+    // Used by View to highlight search matches. Only the active window's buffer has its matches highlighted
+    pub fn get_match_ranges(&self, buffer: usize) -> &[Range<usize>] {
+        if buffer == self.win().buffer {
+            &self.match_ranges
+        } else {
+            &[]
+        }
+    }
+
+    // Used by View to highlight the match under the cursor differently, given by the file index it starts at
+    pub fn get_current_match(&self) -> Option<usize> {
+        let index = self.cursor_char_index();
+        let current = self
+            .match_ranges
+            .partition_point(|range| range.start <= index)
+            .checked_sub(1)?;
+        let range = &self.match_ranges[current];
+        (index < range.end || range.is_empty() && index == range.start).then_some(range.start)
+    }
+
     /*
      * Used by View to show the current mode, and important inputs
      */
     pub fn get_mode_text(&self) -> &str {
         match &self.mode {
-            Mode::Normal => "Normal Mode [z]=>Help [i]=>Insert [:]=>Command [/]=>Search",
+            Mode::Normal if self.is_directory_listing() => {
+                "Directory [ENTER]=>Open [-]=>Up [%]=>New File [d]=>New Dir [R]=>Rename [D]=>Delete [gh]=>Hidden"
+            }
+            Mode::Normal => {
+                "Normal Mode [z]=>Help [i]=>Insert [:]=>Command [/]=>Search [n][N]=>Next/Prev Match"
+            }
             Mode::Command => "Command Mode [ENTER]=>Submit [ESC]=>Exit",
             Mode::SearchInput => "Search Mode [ENTER]=>Submit [ESC]=>Exit",
             Mode::Insert => "Insertion Mode [ESC]=>Exit",
             Mode::Minimized => "Please Enlarge Terminal Window",
             Mode::Help => "Help Page [ESC]=>Exit [^][v] to Scroll Help Text",
             Mode::Quit => "[<][>] to select, [ENTER]=>Confirm, [ESC]=>Cancel",
+            Mode::Prompt => "Prompt [ENTER]=>Submit [ESC]=>Cancel",
+            Mode::Finder => "File Finder [ENTER]=>Open [^][v]=>Select [ESC]=>Exit",
+            Mode::TestResults => {
+                "Test Results [ENTER]=>Jump [n][N]=>Next/Prev Failure [^][v]=>Select [ESC]=>Exit"
+            }
+            Mode::Swap => {
+                "[<][>] to select, [ENTER]=>Confirm, or [r]ecover, [o]pen read-only, [d]elete, [a]bort"
+            }
+            Mode::FileChanged => {
+                "[<][>] to select, [ENTER]=>Confirm, or [r]eload, [k]eep mine, [d]iff [^][v]=>Scroll Diff"
+            }
         }
     }
 
     /*
-     * Used to index app's DisplayContent vector using cursor coordinates and scroll amount
+     * Used to get which column of the file line the cursor is currently located at
+     */
+    pub fn get_cursor_inline_index(&self) -> usize {
+        self.win().get_cursor_inline_index(&self.mode)
+    }
+
+    /*
+     * Used to get the character index of the cursor in the entire file
      */
-    pub fn get_cursor_display_row(&self) -> usize {
-        // display_content is 0-indexed, cursor_pos is 1-indexed
-        (self.scroll_amount + self.cursor_pos.0) as usize - 1
+    pub fn get_cursor_file_index(&self) -> usize {
+        self.win().get_cursor_file_index(&self.mode)
     }
+
     /*
-     * Used to get which column of the file line the cursor is currently located at
+     * Used to access the window with focus, and the buffer it shows
      */
-    pub fn get_cursor_inline_index(&self) -> usize {
-        // Get the number of non-character columns in the current line
-        let line = &self.display_content[self.get_cursor_display_row()];
-        let invalid_cols = &line.invalid_cols;
-        let num_skipped_cols = invalid_cols
-            .iter()
-            .filter(|col| col < &&self.cursor_pos.1)
-            .count();
+    fn tab(&self) -> &TabPage {
+        &self.tabs[self.active_tab]
+    }
+    fn tab_mut(&mut self) -> &mut TabPage {
+        &mut self.tabs[self.active_tab]
+    }
+    fn win(&self) -> &Window {
+        self.tab().win()
+    }
+    fn win_mut(&mut self) -> &mut Window {
+        self.tab_mut().win_mut()
+    }
+    fn model(&mut self) -> &mut EditorModel {
+        let buffer = self.win().buffer;
+        &mut self.buffers[buffer]
+    }
+
+    // Used to re-wrap the displayed text of every window showing the active buffer after it's been updated
+    fn wrap_text(&mut self) {
+        let buffer = self.win().buffer;
+        self.wrap_buffer(buffer);
+    }
 
-        // Sum the inline index of the displayed line the cursor is on, with the cursor position, and subtract non-char columns
-        let mut index = line.inline_index + (self.cursor_pos.1 as usize) - num_skipped_cols;
-        if let Mode::Insert = self.mode {
-            index -= 1; // Insertion mode has a thinner cursor that can move into 0 indexing
+    fn wrap_buffer(&mut self, buffer: usize) {
+        if buffer == self.win().buffer {
+            self.update_matches();
         }
-        if self.show_line_nums {
-            index -= line.line_num.count_digits() + 1; // subtract the line number characters
+        let model = &self.buffers[buffer];
+        let tab = &mut self.tabs[self.active_tab];
+        for window in tab
+            .windows
+            .iter_mut()
+            .filter(|window| window.buffer == buffer)
+        {
+            window.wrap_text(model);
+            // Edits made through the active window can leave other windows' cursors out of bounds
+            if window.id != tab.active_window {
+                window.clamp_cursor(&Mode::Normal);
+            }
         }
-        index
     }
 
     /*
-     * Used to get the character index of the cursor in the entire file
+     * Search the active window's buffer for the search term from scratch, on a background thread.
+     * Matches are highlighted as they come in
      */
-    pub fn get_cursor_file_index(&self) -> usize {
-        let line = &self.display_content[self.get_cursor_display_row()];
-        let invalid_cols = &line.invalid_cols;
-        let num_skipped_cols = invalid_cols
-            .iter()
-            .filter(|col| col < &&self.cursor_pos.1)
-            .count();
+    fn start_search(&mut self) {
+        if let Some(job) = self.search_job.take() {
+            job.cancel();
+        }
+        self.match_ranges = vec![];
+        self.pending_jump = None;
+        let Some(re) = self.search_regex.clone() else {
+            return;
+        };
+        let buffer = self.win().buffer;
+        let model = &self.buffers[buffer];
+        self.match_buffer = buffer;
+        self.match_edits = model.num_edits();
+        self.search_job = Some(SearchJob::spawn(re, model.rope.clone()));
+    }
 
-        // Sum the infile index of the displayed line the cursor is on, with the cursor position, and subtract non-char columns
-        let mut index = line.infile_index + (self.cursor_pos.1 as usize) - num_skipped_cols;
-        if let Mode::Insert = self.mode {
-            index -= 1; // Insertion mode has a thinner cursor that can move into 0 indexing
+    /*
+     * Bring the active window's matches up to date with the edits made since they were found,
+     * searching again only around the edits
+     */
+    fn update_matches(&mut self) {
+        let buffer = self.win().buffer;
+        let Some(re) = &self.search_regex else {
+            return;
+        };
+        if buffer != self.match_buffer {
+            self.start_search();
+            return;
         }
-        if self.show_line_nums {
-            index -= line.line_num.count_digits() + 1; // subtract the line number characters
+        let model = &self.buffers[buffer];
+        // A running search catches up with the edits once it finishes
+        if self.search_job.is_some() || model.num_edits() == self.match_edits {
+            return;
+        }
+        match model.edits_since(self.match_edits) {
+            Some(edits) => search::update_matches(&mut self.match_ranges, &model.rope, re, edits),
+            None => {
+                self.start_search();
+                return;
+            }
         }
-        index
+        self.match_edits = model.num_edits();
     }
 
-    // Used to re-wrap the displayed text after it's been updated
-    fn wrap_text(&mut self) {
-        // If highlighting, rerun search to update highlighting
-        if self.get_show_highlights() {
-            self.match_ranges = self.model.run_search(self.search_term.as_str());
+    // Turn off search highlighting, stopping any search still running
+    fn clear_search(&mut self) {
+        if let Some(job) = self.search_job.take() {
+            job.cancel();
         }
-        self.display_content = string_to_lines(
-            self.model.rope.to_string().as_str(),
-            self.term_size.1,
-            self.show_line_nums,
-            &self.match_ranges,
-        );
+        self.search_term = String::new();
+        self.search_regex = None;
+        self.match_ranges = vec![];
+        self.pending_jump = None;
+    }
+
+    // Put the current search aside while a new query is typed, turning its highlighting off
+    fn save_search(&mut self) {
+        self.discard_saved_search();
+        self.pending_jump = None;
+        self.saved_search = Some(SavedSearch {
+            term: mem::take(&mut self.search_term),
+            regex: self.search_regex.take(),
+            job: self.search_job.take(),
+            match_ranges: mem::take(&mut self.match_ranges),
+            match_buffer: self.match_buffer,
+            match_edits: self.match_edits,
+            backward: self.search_backward,
+        });
     }
 
     /*
-     * Defines the boundaries of the cursor in the terminal window
+     * Go back to the search from before the query was typed, like Vim does when it's cancelled.
+     * Nothing can be edited while typing a query, so its matches are still up to date
      */
-    pub fn term_top_cursor_bound(&self) -> u16 {
-        1
-    }
-    pub fn term_bottom_cursor_bound(&self) -> u16 {
-        // -4 because borders + bottom status window
-        self.term_size.0 - 4
+    fn restore_search(&mut self) {
+        self.clear_search();
+        let Some(saved) = self.saved_search.take() else {
+            return;
+        };
+        self.search_term = saved.term;
+        self.search_regex = saved.regex;
+        self.search_job = saved.job;
+        self.match_ranges = saved.match_ranges;
+        self.match_buffer = saved.match_buffer;
+        self.match_edits = saved.match_edits;
+        self.search_backward = saved.backward;
     }
-    pub fn term_left_cursor_bound(&self) -> u16 {
-        if self.show_line_nums {
-            return self.display_content[self.get_cursor_display_row()]
-                .line_num
-                .count_digits() as u16
-                + 2;
+
+    // Forget the search from before the query, once the query has replaced it
+    fn discard_saved_search(&mut self) {
+        if let Some(job) = self.saved_search.take().and_then(|saved| saved.job) {
+            job.cancel();
         }
-        1
     }
-    pub fn term_right_cursor_bound(&self) -> u16 {
-        // - 2 because borders
-        self.term_size.1 - 2
+
+    /*
+     * Search the active window's buffer for a query in the background, highlighting matches as they are found
+     */
+    fn search_for(&mut self, query: String) -> Result<(), String> {
+        let re = search::compile(query.as_str(), self.search_options)?;
+        self.search_regex = Some(re);
+        self.search_term = query;
+        self.start_search();
+        Ok(())
+    }
+
+    // Character index of the cursor in the active window, counting the character under it
+    fn cursor_char_index(&self) -> usize {
+        let rope = &self.buffers[self.win().buffer].rope;
+        // Outside of insertion mode the cursor's file index counts the character under it
+        let index = self
+            .win()
+            .get_cursor_file_index(&Mode::Normal)
+            .saturating_sub(1);
+        cmp::min(index, rope.len_chars())
     }
 
     /*
-     * Used to handle updates in the terminal size
+     * Index of the match after the given character index, or before it when going backward,
+     * wrapping around the ends of the buffer. None if it isn't known yet, while the search is still running
      */
-    pub fn update_term_size(&mut self, term_height: u16, term_width: u16) {
-        self.term_size = (term_height, term_width);
+    fn next_match(&self, from: usize, backward: bool) -> Option<usize> {
+        let matches = &self.match_ranges;
+        let running = self.search_job.is_some();
+        let after = matches.partition_point(|range| range.start <= from);
+        if !backward {
+            return match after < matches.len() {
+                true => Some(after),
+                false if running => None,
+                false => (!matches.is_empty()).then_some(0),
+            };
+        }
+        // Matches arrive in order, so every match before the index is known once one at or after it is
+        if running && matches.last().is_none_or(|range| range.start < from) {
+            return None;
+        }
+        let before = matches.partition_point(|range| range.start < from);
+        match before.checked_sub(1) {
+            Some(idx) => Some(idx),
+            None if running => None,
+            None => matches.len().checked_sub(1),
+        }
+    }
 
-        // Used to prevent panic from shrinking terminal too small
-        if term_height <= 4 {
-            self.mode = Mode::Minimized;
-            self.cursor_pos = (1, 1);
+    /*
+     * Move the cursor to the match a search is waiting to jump to, once it has been found.
+     * Outside of Search Mode, wrapping around an end of the buffer is reported in the message bar
+     */
+    fn resolve_jump(&mut self) {
+        let Some((from, backward)) = self.pending_jump else {
+            return;
+        };
+        let Some(idx) = self.next_match(from, backward) else {
+            return;
+        };
+        self.pending_jump = None;
+        let start = self.match_ranges[idx].start;
+        self.win_mut().move_cursor_to_index(start, &Mode::Normal);
+        if !matches!(self.mode, Mode::Normal) {
             return;
         }
-        // Use to return to normal functionality after enlarging terminal back to usable size
-        if let Mode::Minimized = self.mode
-            && term_height > 4
-        {
-            self.mode = Mode::Normal;
-            // Clear any previous unsubmitted user input
-            if !self.get_show_highlights() {
-                self.msg_display = vec![];
-            } else {
-                // Re-display search matches message if we are still highlighting
-                self.msg_display = format!(
-                    "{} matches for {}",
-                    self.match_ranges.len(),
-                    &self.search_term
-                )
+        if !backward && start <= from {
+            self.msg_display = "Search hit BOTTOM, continuing at TOP".chars().collect();
+        } else if backward && start >= from {
+            self.msg_display = "Search hit TOP, continuing at BOTTOM".chars().collect();
+        }
+    }
+
+    // Show how many matches were found, or that the search is still running
+    fn show_search_status(&mut self) {
+        if self.search_job.is_some() {
+            self.msg_display = format!("Searching for {}", &self.search_term)
                 .chars()
                 .collect();
-            }
+        } else {
+            self.reset_msg_display();
         }
+    }
 
-        // Re-wrap display content
-        self.wrap_text();
+    /*
+     * Jump to the next search match with n (or the previous one with N, the other way round after a ? search).
+     * Once highlighting is turned off, the last query is searched for again
+     */
+    fn search_next(&mut self, reverse: bool) {
+        if self.search_regex.is_none() {
+            if self.last_search.is_empty() {
+                self.msg_display = "Error: No previous search".chars().collect();
+                return;
+            }
+            if let Err(msg) = self.search_for(self.last_search.clone()) {
+                self.msg_display = msg.chars().collect();
+                return;
+            }
+        }
+        self.show_search_status();
+        self.pending_jump = Some((self.cursor_char_index(), self.search_backward != reverse));
+        self.resolve_jump();
+    }
 
-        // Update cursor position if terminal size shrunk
-        if self.cursor_pos.1 > self.term_right_cursor_bound() {
-            self.cursor_pos.1 = self.term_right_cursor_bound();
+    /*
+     * Replace matches on the current line (or every line) for :s, returning how many were made.
+     * Without the g flag only the first match starting on each line is replaced. An empty pattern uses the last search
+     */
+    fn substitute(&mut self, substitute: &Substitute) -> Result<String, String> {
+        let buffer = self.win().buffer;
+        if self.buffers[buffer].is_listing() {
+            return Err(String::from("Error: Cannot edit a listing"));
         }
-        if self.cursor_pos.0 > self.term_bottom_cursor_bound() {
-            self.cursor_pos.0 = self.term_bottom_cursor_bound();
+        if self.buffers[buffer].is_loading() {
+            return Err(String::from("Error: File is still loading"));
         }
+        let query = if !substitute.pattern.is_empty() {
+            substitute.pattern.clone()
+        } else if !self.last_search.is_empty() {
+            self.last_search.clone()
+        } else {
+            return Err(String::from("Error: No previous search"));
+        };
+        let re = search::compile(query.as_str(), self.search_options)?;
 
-        // Edge case if enlarging terminal window and unwrapping displayed text reduced number of rows occupied by text
-        if self.cursor_pos.0 > self.display_content.len() as u16 {
-            self.cursor_pos.0 = self.display_content.len() as u16;
+        let cursor = self.cursor_char_index();
+        let rope = &self.buffers[buffer].rope;
+        let (first_line, end_line) = if substitute.whole_file {
+            (0, rope.len_lines())
+        } else {
+            let line = rope.char_to_line(cursor);
+            (line, line + 1)
+        };
+        let offset = rope.line_to_char(first_line);
+        let text = rope.slice(offset..rope.line_to_char(end_line)).to_string();
+        // Character ranges of the matches to replace, and their replacements
+        let mut replacements = vec![];
+        let (mut byte_idx, mut char_idx) = (0, offset);
+        let (mut last_line, mut lines) = (None, 0);
+        for groups in re.captures_iter(text.as_str()) {
+            let Some(found) = groups[0].clone() else {
+                continue;
+            };
+            char_idx += text[byte_idx..found.start].chars().count();
+            byte_idx = found.start;
+            // $ also matches at the very end of the text, which is the start of the next line
+            let line = rope.char_to_line(char_idx);
+            if line >= end_line || (!substitute.global && last_line == Some(line)) {
+                continue;
+            }
+            if last_line != Some(line) {
+                lines += 1;
+            }
+            last_line = Some(line);
+            let end_idx = char_idx + text[found.clone()].chars().count();
+            replacements.push((char_idx..end_idx, substitute.expand(text.as_str(), &groups)));
+        }
+        if replacements.is_empty() {
+            return Err(format!("Error: Pattern not found: {}", query));
         }
 
-        // Ensure the cursor stays in a valid location
-        self.snap_cursor();
-        self.slip_cursor();
+        // Later matches move as earlier ones are replaced
+        let mut shift = 0;
+        let mut last_start = 0;
+        for (range, replacement) in &replacements {
+            let start = range.start.strict_add_signed(shift);
+            let end = range.end.strict_add_signed(shift);
+            self.buffers[buffer].replace_range(start, end, replacement.as_str());
+            shift += replacement.chars().count() as isize - range.len() as isize;
+            last_start = start;
+        }
+        self.wrap_text();
+        // Like Vim, leave the cursor at the start of the last line changed
+        let rope = &self.buffers[buffer].rope;
+        let line_start = rope.line_to_char(rope.char_to_line(last_start));
+        self.win_mut()
+            .move_cursor_to_index(line_start, &Mode::Normal);
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        Ok(format!(
+            "{} substitution{} on {} line{}",
+            replacements.len(),
+            plural(replacements.len()),
+            lines,
+            plural(lines)
+        ))
     }
 
     /*
-     * Used to start/stop the app
+     * With incsearch, search again each time the query changes, jumping from where the cursor was when typing started.
+     * Queries which aren't finished yet (ex. an unclosed group) just show no matches
      */
-    pub fn running(&self) -> bool {
-        self.running
+    fn incremental_search(&mut self) {
+        if !self.incsearch {
+            return;
+        }
+        let origin = self.search_origin;
+        self.win_mut().move_cursor_to_index(origin, &Mode::Normal);
+        let query: String = self.msg_display[1..].iter().collect();
+        if query.is_empty() || self.search_for(query).is_err() {
+            self.clear_search();
+            return;
+        }
+        self.pending_jump = Some((origin, self.search_backward));
+        self.resolve_jump();
     }
-    fn exit(&mut self) {
-        self.running = false;
+
+    // Leave Search Mode without searching, putting the cursor and the previous search's highlighting back
+    fn cancel_search_input(&mut self) {
+        self.restore_search();
+        if self.incsearch {
+            let origin = self.search_origin;
+            self.win_mut().move_cursor_to_index(origin, &Mode::Normal);
+        }
+        self.show_search_status();
+        self.mode = Mode::Normal;
+    }
+
+    // Area of the terminal shared between the windows (everything between the tab line and the status bar)
+    fn content_area(&self) -> Rect {
+        let tab_line_height = self.get_show_tab_line() as u16;
+        Rect::new(
+            0,
+            tab_line_height,
+            self.term_size.1,
+            self.term_size.0.saturating_sub(2 + tab_line_height),
+        )
     }
 
     /*
-     * Accepts any user inputs provided via crossterm while the program is running,
-     * and passes them to the Controller for further processing
+     * Used to handle updates in the terminal size
      */
-    pub fn handle_events(&mut self) -> io::Result<()> {
-        // TO DO: event::read is a blocking call, consider using event::poll instead?
-        match event::read()? {
-            // Checks that this was a key press event.
-            Event::Key(key_event) => {
-                if key_event.kind == KeyEventKind::Press {
-                    self.handle_key_event(key_event)
-                }
-            }
-            // Handle terminal resizing
-            Event::Resize(col, row) => self.update_term_size(row, col),
-            _ => {}
-        };
-        Ok(())
+    pub fn update_term_size(&mut self, term_height: u16, term_width: u16) {
+        self.term_size = (term_height, term_width);
+        self.resize_windows();
     }
 
     /*
-     * Handles key press events specifically
+     * Fit every window to its area of the layout, re-wrapping their contents
      */
-    fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match self.mode {
-            Mode::Command => self.command_handle_key_event(key_event),
-            Mode::Insert => self.insert_handle_key_event(key_event),
-            Mode::Normal => self.normal_handle_key_event(key_event),
-            Mode::SearchInput => self.search_input_handle_key_event(key_event),
-            Mode::Minimized => {}
-            Mode::Help => self.help_handle_key_event(key_event),
-            Mode::Quit => self.quit_handle_key_event(key_event),
+    fn resize_windows(&mut self) {
+        let areas = self.tab().layout.areas(self.content_area());
+
+        // Used to prevent panic from shrinking terminal (and its windows) too small
+        if self.term_size.0 <= 4
+            || areas
+                .iter()
+                .any(|(_, area)| area.height < MIN_WINDOW_HEIGHT || area.width < MIN_WINDOW_WIDTH)
+        {
+            self.mode = Mode::Minimized;
+            for window in self.tab_mut().windows.iter_mut() {
+                window.cursor_pos = (1, 1);
+            }
+            return;
+        }
+        // Use to return to normal functionality after enlarging terminal back to usable size
+        if let Mode::Minimized = self.mode {
+            self.mode = Mode::Normal;
+            // Clear any previous unsubmitted user input
+            self.reset_msg_display();
+        }
+
+        for (id, area) in areas {
+            if let Some(window) = self
+                .tab_mut()
+                .windows
+                .iter_mut()
+                .find(|window| window.id == id)
+            {
+                window.size = (area.height, area.width);
+            }
+        }
+
+        // Re-wrap display content of every buffer on screen
+        let mut buffers: Vec<usize> = self
+            .tab()
+            .windows
+            .iter()
+            .map(|window| window.buffer)
+            .collect();
+        buffers.sort();
+        buffers.dedup();
+        for buffer in buffers {
+            self.wrap_buffer(buffer);
+        }
+
+        // Ensure the cursor stays in a valid location
+        let mode = &self.mode;
+        let tab = &mut self.tabs[self.active_tab];
+        let active_window = tab.active_window;
+        for window in tab.windows.iter_mut() {
+            if window.id == active_window {
+                window.clamp_cursor(mode);
+            } else {
+                window.clamp_cursor(&Mode::Normal);
+            }
+        }
+    }
+
+    // Clear the message bar, re-displaying search matches message if we are still highlighting
+    fn reset_msg_display(&mut self) {
+        if self.get_show_highlights() {
+            self.msg_display = format!(
+                "{} matches for {}",
+                self.match_ranges.len(),
+                &self.search_term
+            )
+            .chars()
+            .collect();
+        } else {
+            self.msg_display = vec![];
+        }
+    }
+
+    // Show a message in the message bar, such as why a file couldn't be opened
+    pub fn show_message(&mut self, msg: &str) {
+        self.msg_display = msg.chars().collect();
+    }
+
+    // Load a file given on the command line, returning its buffer for show_file
+    pub fn add_file(&mut self, model: EditorModel) -> usize {
+        self.buffers.push(model);
+        let buffer = self.buffers.len() - 1;
+        self.watch_buffer(buffer);
+        buffer
+    }
+
+    /*
+     * Show a file given on the command line. With a split direction the current window is split to show it,
+     * with the new window getting focus. Otherwise it is left for :e, starting where it should once it's first shown
+     */
+    pub fn show_file(
+        &mut self,
+        buffer: usize,
+        split: Option<Direction>,
+        start: Option<StartPosition>,
+    ) -> Result<(), &'static str> {
+        match (split, start) {
+            (Some(direction), start) => {
+                self.split_window_with(direction, buffer)?;
+                if let Some(start) = start {
+                    self.start_at(&start);
+                }
+            }
+            // A file named again keeps the start it was first given
+            (None, Some(start))
+                if buffer != self.win().buffer
+                    && !self
+                        .pending_starts
+                        .iter()
+                        .any(|(pending, _)| *pending == buffer) =>
+            {
+                self.pending_starts.push((buffer, start));
+            }
+            (None, _) => {}
+        }
+        Ok(())
+    }
+
+    // Move the cursor to where a file given on the command line should start, the first time it is shown
+    fn start_pending(&mut self) {
+        let buffer = self.win().buffer;
+        if let Some(idx) = self
+            .pending_starts
+            .iter()
+            .position(|(pending, _)| *pending == buffer)
+        {
+            let (_, start) = self.pending_starts.remove(idx);
+            self.start_at(&start);
+        }
+    }
+
+    // Give every window of the current tab page an equal share of the screen
+    pub fn equalize_windows(&mut self) {
+        let area = self.content_area();
+        self.tab_mut().layout.equalize(area);
+        self.resize_windows();
+    }
+
+    /*
+     * Move the current window's cursor to where a file given on the command line should start
+     */
+    pub fn start_at(&mut self, start: &StartPosition) {
+        let buffer = self.win().buffer;
+        let model = &self.buffers[buffer];
+        let index = match start {
+            StartPosition::Line(line, col) => model.line_col_to_index(*line, *col),
+            // The empty line after a final newline doesn't count
+            StartPosition::LastLine => {
+                let len = model.rope.len_chars();
+                let mut line = model.rope.len_lines();
+                if len > 0 && model.rope.char(len - 1) == '\n' {
+                    line -= 1;
+                }
+                model.line_col_to_index(line, 1)
+            }
+            StartPosition::Pattern(pattern) => {
+                let re = match search::compile(pattern, self.search_options) {
+                    Ok(re) => re,
+                    Err(msg) => {
+                        self.msg_display = msg.chars().collect();
+                        return;
+                    }
+                };
+                // Search straight away, since the cursor can't be placed until the first match is known
+                let mut matches = vec![];
+                search::find_matches(&model.rope, &re, 0..model.rope.len_chars(), |found| {
+                    matches.extend(found);
+                    true
+                });
+                let Some(first_match) = matches.first() else {
+                    self.msg_display = "Error: No matches found".chars().collect();
+                    return;
+                };
+                let index = first_match.start;
+                self.clear_search();
+                self.search_term = pattern.clone();
+                self.search_regex = Some(re);
+                self.match_ranges = matches;
+                self.match_buffer = buffer;
+                self.match_edits = self.buffers[buffer].num_edits();
+                self.wrap_text();
+                self.reset_msg_display();
+                index
+            }
+        };
+        self.tabs[self.active_tab]
+            .win_mut()
+            .move_cursor_to_index(index, &Mode::Normal);
+    }
+
+    // Run an ex command given on the command line with -c, as if typed after ":"
+    pub fn run_startup_command(&mut self, command: &str) {
+        let command = command.trim();
+        if command.starts_with(':') {
+            self.run_command(command);
+        } else {
+            self.run_command(format!(":{}", command).as_str());
+        }
+    }
+
+    /*
+     * Used to start/stop the app
+     */
+    pub fn running(&self) -> bool {
+        self.running
+    }
+    fn exit(&mut self) {
+        self.running = false;
+        for model in self.buffers.iter_mut() {
+            model.close_swap();
+        }
+    }
+
+    /*
+     * Accepts any user inputs provided via crossterm while the program is running,
+     * and passes them to the Controller for further processing
+     */
+    pub fn handle_events(&mut self) -> io::Result<()> {
+        // Don't block on input while background work has results to show, or unsaved changes are waiting to be written to swap files
+        let background_work = self.grep_job.is_some()
+            || self.search_job.is_some()
+            || self.make_job.is_some()
+            || self.test_job.is_some()
+            || self.buffers.iter().any(|model| model.is_loading());
+        let timeout = if background_work {
+            Some(BACKGROUND_POLL_INTERVAL)
+        } else if self
+            .buffers
+            .iter()
+            .any(|model| model.pending_swap_changes() > 0)
+        {
+            Some(swap::UPDATE_TIME.saturating_sub(self.last_input.elapsed()))
+        } else {
+            None
+        };
+        // Keep checking for other programs changing open files while idle
+        let timeout = match (timeout, &self.watcher) {
+            (Some(timeout), Some(_)) => Some(timeout.min(WATCH_POLL_INTERVAL)),
+            (None, Some(_)) => Some(WATCH_POLL_INTERVAL),
+            (timeout, None) => timeout,
+        };
+        if let Some(timeout) = timeout
+            && !event::poll(timeout)?
+        {
+            self.poll_background();
+            self.sync_swaps();
+            self.check_file_changed();
+            return Ok(());
+        }
+        match event::read()? {
+            // Checks that this was a key press event.
+            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
+                self.last_input = Instant::now();
+                self.handle_key_event(key_event)
+            }
+            // Handle terminal resizing
+            Event::Resize(col, row) => self.update_term_size(row, col),
+            _ => {}
+        };
+        self.poll_background();
+        self.sync_swaps();
+        self.check_swap();
+        self.check_file_changed();
+        Ok(())
+    }
+
+    /*
+     * Write unsaved changes to swap files once typing pauses, or after many changes
+     */
+    fn sync_swaps(&mut self) {
+        let paused = self.last_input.elapsed() >= swap::UPDATE_TIME;
+        for model in self.buffers.iter_mut() {
+            let pending_changes = model.pending_swap_changes();
+            if pending_changes >= swap::UPDATE_COUNT || (paused && pending_changes > 0) {
+                model.sync_swap();
+            }
+        }
+    }
+
+    /*
+     * Ask what to do when the file just shown has a swap file from another process
+     */
+    fn check_swap(&mut self) {
+        if !matches!(self.mode, Mode::Normal) {
+            return;
+        }
+        if let Some(existing) = self.get_existing_swap() {
+            self.swap_selection = if existing.text.is_some() && !existing.running {
+                SwapSelection::Recover
+            } else {
+                SwapSelection::ReadOnly
+            };
+            self.mode = Mode::Swap;
+            self.msg_display = vec![];
+        }
+    }
+
+    /*
+     * Show any results background work has produced since the last check
+     */
+    fn poll_background(&mut self) {
+        self.poll_loading();
+        self.poll_search();
+        self.poll_grep();
+        self.poll_make();
+        self.poll_test();
+        self.poll_watcher();
+    }
+
+    // Start watching a buffer's file for changes made by other programs
+    fn watch_buffer(&mut self, buffer: usize) {
+        let model = &self.buffers[buffer];
+        if let Some(watcher) = &mut self.watcher
+            && !model.is_listing()
+        {
+            watcher.watch(Path::new(&model.file_name));
         }
     }
 
+    /*
+     * Find buffers whose files were changed by other programs. With autoread, those without unsaved changes
+     * are reloaded straight away, while the rest wait for the user to decide what to do
+     */
+    fn poll_watcher(&mut self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+        let paths = watcher.poll();
+        if paths.is_empty() {
+            return;
+        }
+        for buffer in 0..self.buffers.len() {
+            let model = &self.buffers[buffer];
+            if self.changed_buffers.contains(&buffer)
+                || !Path::new(&model.file_name)
+                    .canonicalize()
+                    .is_ok_and(|path| paths.contains(&path))
+                || !model.changed_on_disk()
+            {
+                continue;
+            }
+            if self.autoread && !model.modified {
+                let result = self.buffers[buffer].reload(None);
+                self.wrap_buffer(buffer);
+                if matches!(self.mode, Mode::Normal) {
+                    self.msg_display = match result {
+                        Ok(None) => format!("Reloaded {}", self.buffers[buffer].file_name),
+                        Ok(Some(warning)) => {
+                            format!("Reloaded {} ({})", self.buffers[buffer].file_name, warning)
+                        }
+                        Err(e) => format!("Error: could not reload file: {}", e),
+                    }
+                    .chars()
+                    .collect();
+                }
+            } else {
+                self.changed_buffers.push(buffer);
+            }
+        }
+    }
+
+    /*
+     * Ask what to do about the next file changed by another program
+     */
+    fn check_file_changed(&mut self) {
+        if !matches!(self.mode, Mode::Normal) {
+            return;
+        }
+        if let Some(&buffer) = self.changed_buffers.first() {
+            self.file_changed_selection = if self.buffers[buffer].modified {
+                FileChangedSelection::KeepMine
+            } else {
+                FileChangedSelection::Reload
+            };
+            self.file_diff = vec![];
+            self.scroll_diff_amount = 0;
+            self.mode = Mode::FileChanged;
+            self.msg_display = vec![];
+        }
+    }
+
+    /*
+     * Highlight the matches found by a running search, and report how many there were once it finishes
+     */
+    fn poll_search(&mut self) {
+        let Some(job) = &self.search_job else {
+            return;
+        };
+        let (matches, running) = job.poll();
+        if matches.is_empty() && running {
+            return;
+        }
+        self.match_ranges.extend(matches);
+        if !running {
+            self.search_job = None;
+            let no_matches = self.match_ranges.is_empty();
+            if no_matches {
+                self.clear_search();
+            }
+            // Report the result, unless the user is busy typing into the message bar
+            if matches!(self.mode, Mode::Normal) {
+                if no_matches {
+                    self.msg_display = "Error: No matches found".chars().collect();
+                } else {
+                    self.reset_msg_display();
+                }
+            }
+        }
+        // Re-wrapping also catches the matches up with edits made while searching
+        self.wrap_text();
+        self.resolve_jump();
+    }
+
+    /*
+     * Show the text of large files read in the background since the last check
+     */
+    fn poll_loading(&mut self) {
+        for buffer in 0..self.buffers.len() {
+            let (changed, finished) = self.buffers[buffer].poll_loading();
+            if changed {
+                self.wrap_buffer(buffer);
+            }
+            // Report how loading went, unless the user is busy typing into the message bar
+            if let Some(result) = finished
+                && matches!(self.mode, Mode::Normal)
+            {
+                let file_name = &self.buffers[buffer].file_name;
+                self.msg_display = match result {
+                    Ok(_) => format!(
+                        "Loaded {} (large file: no swap file, and no backup while writing)",
+                        file_name
+                    ),
+                    Err(e) => format!("Error: could not read all of {}: {}", file_name, e),
+                }
+                .chars()
+                .collect();
+            }
+        }
+    }
+
+    fn poll_test(&mut self) {
+        let (Some(job), Some(test_run)) = (&mut self.test_job, &mut self.test_run) else {
+            return;
+        };
+        let (lines, status) = job.poll();
+        test_run.push_lines(lines);
+        let Some(status) = status else {
+            return;
+        };
+        let passed = status.is_ok_and(|status| status.success());
+        test_run.finish(passed);
+        let num_failures = test_run.num_failures();
+        self.test_job = None;
+        // Report the result, unless the user is busy typing into the message bar
+        if matches!(self.mode, Mode::Normal | Mode::TestResults) {
+            self.msg_display = if passed {
+                String::from("Tests passed")
+            } else {
+                format!(
+                    "Tests failed with {} failures (:TestResults to show them)",
+                    num_failures
+                )
+            }
+            .chars()
+            .collect();
+        }
+    }
+
+    fn poll_grep(&mut self) {
+        let Some((job, source)) = self.grep_job.take() else {
+            return;
+        };
+        let (mut locations, running) = job.poll();
+        if locations.is_empty() && running {
+            self.grep_job = Some((job, source));
+            return;
+        }
+        let Some(list) = self.list_mut(source) else {
+            // The window owning the location list was closed
+            job.cancel();
+            return;
+        };
+
+        // Stop searching once there are more results than anyone would look through
+        let limit_reached = list.entries.len() + locations.len() >= grep::MAX_RESULTS;
+        locations.truncate(grep::MAX_RESULTS - list.entries.len());
+        list.entries.extend(locations.iter().cloned());
+        if limit_reached {
+            job.cancel();
+            list.title.push_str(" (stopped at limit)");
+        } else if running {
+            self.grep_job = Some((job, source));
+        }
+
+        if let Some(buffer) = self.list_buffer(source) {
+            self.buffers[buffer].append_entries(&locations);
+            self.buffers[buffer].file_name = self.list_buffer_name(source);
+            self.wrap_buffer(buffer);
+        }
+    }
+
+    /*
+     * Add the problems reported by a running build to its list, and mark them in their buffers
+     */
+    fn poll_make(&mut self) {
+        let Some((mut job, source)) = self.make_job.take() else {
+            return;
+        };
+        let (lines, status) = job.poll();
+        if lines.is_empty() && status.is_none() {
+            self.make_job = Some((job, source));
+            return;
+        }
+        let entries = quickfix::parse_errors(lines.join("\n").as_str());
+        for entry in entries.iter() {
+            if let Ok(path) = Path::new(&entry.path).canonicalize() {
+                self.make_marks.push((path, entry.line, entry.is_warning()));
+            }
+        }
+        if let Some(list) = self.list_mut(source) {
+            list.entries.extend(entries.iter().cloned());
+        }
+
+        match status {
+            None => self.make_job = Some((job, source)),
+            // Report how the build went, unless the user is busy typing into the message bar
+            Some(status) if matches!(self.mode, Mode::Normal) => {
+                let num_warnings = self.make_marks.iter().filter(|mark| mark.2).count();
+                let num_errors = self.make_marks.len() - num_warnings;
+                self.msg_display = match status {
+                    Ok(status) => format!(
+                        "Build {} with {} errors and {} warnings",
+                        if status.success() {
+                            "finished"
+                        } else {
+                            "failed"
+                        },
+                        num_errors,
+                        num_warnings
+                    ),
+                    Err(e) => format!("Error: build stopped: {}", e),
+                }
+                .chars()
+                .collect();
+            }
+            Some(_) => {}
+        }
+
+        if let Some(buffer) = self.list_buffer(source) {
+            self.buffers[buffer].append_entries(&entries);
+            self.buffers[buffer].file_name = self.list_buffer_name(source);
+            self.wrap_buffer(buffer);
+        }
+    }
+
+    /*
+     * Stop any build, test run or project search running in the background.
+     * Output collected so far is kept, and lists are titled as cancelled
+     */
+    fn cancel_jobs(&mut self) {
+        let mut cancelled = vec![];
+        if let Some((job, source)) = self.make_job.take() {
+            drop(job);
+            self.mark_list_cancelled(source);
+            cancelled.push("build");
+        }
+        if let Some(job) = self.test_job.take() {
+            drop(job);
+            if let Some(test_run) = self.test_run.as_mut() {
+                test_run.push_lines(vec![String::from("Cancelled")]);
+                test_run.finish(false);
+            }
+            cancelled.push("tests");
+        }
+        if let Some((job, source)) = self.grep_job.take() {
+            job.cancel();
+            self.mark_list_cancelled(source);
+            cancelled.push("search");
+        }
+        self.msg_display = if cancelled.is_empty() {
+            String::from("Nothing running to cancel")
+        } else {
+            format!("Cancelled {}", cancelled.join(", "))
+        }
+        .chars()
+        .collect();
+    }
+
+    fn mark_list_cancelled(&mut self, source: ListSource) {
+        if let Some(list) = self.list_mut(source) {
+            list.title.push_str(" (cancelled)");
+        }
+        if let Some(buffer) = self.list_buffer(source) {
+            self.buffers[buffer].file_name = self.list_buffer_name(source);
+        }
+    }
+
+    /*
+     * Handles key press events specifically
+     */
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        let buffer = self.win().buffer;
+        let was_modified = self.buffers[buffer].modified;
+        match self.mode {
+            Mode::Command => self.command_handle_key_event(key_event),
+            Mode::Insert => self.insert_handle_key_event(key_event),
+            Mode::Normal => self.normal_handle_key_event(key_event),
+            Mode::SearchInput => self.search_input_handle_key_event(key_event),
+            Mode::Minimized => {}
+            Mode::Help => self.help_handle_key_event(key_event),
+            Mode::Quit => self.quit_handle_key_event(key_event),
+            Mode::Prompt => self.prompt_handle_key_event(key_event),
+            Mode::Finder => self.finder_handle_key_event(key_event),
+            Mode::TestResults => self.test_results_handle_key_event(key_event),
+            Mode::Swap => self.swap_handle_key_event(key_event),
+            Mode::FileChanged => self.file_changed_handle_key_event(key_event),
+        }
+        // Read-only buffers can still be changed, but the first change comes with a warning
+        let model = &self.buffers[buffer];
+        if model.read_only && model.modified && !was_modified {
+            self.msg_display = "Warning: Changing a read-only file (:w! to write it)"
+                .chars()
+                .collect();
+        }
+    }
     fn quit_handle_key_event(&mut self, key_event: KeyEvent) {
         match key_event.code {
             // Change selected option
@@ -545,16 +1580,34 @@ impl<'a> App<'a> {
                     }
                 }
                 QuitSelection::NoSaveQuit => self.exit(),
-                QuitSelection::SaveAndQuit => match self.model.save() {
-                    Ok(_) => {
-                        self.exit();
+                QuitSelection::SaveAndQuit => {
+                    // Changes with no file name can't be saved, so they're shown to be written with :w file instead
+                    if let Some(buffer) =
+                        self.buffers.iter().position(EditorModel::is_unnamed_change)
+                    {
+                        if self.win().buffer != buffer {
+                            self.show_buffer(buffer);
+                        }
+                        self.mode = Mode::Command;
+                        self.msg_display = ":w ".chars().collect();
+                        return;
                     }
-                    Err(e) => {
-                        self.msg_display = format!("Error: could not write file: {}", e)
-                            .chars()
-                            .collect();
+                    match self
+                        .buffers
+                        .iter_mut()
+                        .filter(|model| model.modified)
+                        .try_for_each(|model| model.save(&self.backup_options, false).map(|_| ()))
+                    {
+                        Ok(_) => {
+                            self.exit();
+                        }
+                        Err(e) => {
+                            self.msg_display = format!("Error: could not write file: {}", e)
+                                .chars()
+                                .collect();
+                        }
                     }
-                },
+                }
             },
             // Cancel and return to Normal Mode
             KeyCode::Esc => {
@@ -577,6 +1630,158 @@ impl<'a> App<'a> {
         }
     }
 
+    fn swap_handle_key_event(&mut self, key_event: KeyEvent) {
+        let pos = SwapSelection::ALL
+            .iter()
+            .position(|selection| *selection == self.swap_selection)
+            .unwrap();
+        let selection = match key_event.code {
+            // Change selected option
+            KeyCode::Left | KeyCode::Char('<') | KeyCode::Char(',') => {
+                self.swap_selection = SwapSelection::ALL[pos.saturating_sub(1)];
+                return;
+            }
+            KeyCode::Right | KeyCode::Char('>') | KeyCode::Char('.') => {
+                self.swap_selection = SwapSelection::ALL[cmp::min(pos + 1, 3)];
+                return;
+            }
+            // Confirm selection, or pick an option by its first letter
+            KeyCode::Enter => self.swap_selection,
+            KeyCode::Char('r') => SwapSelection::Recover,
+            KeyCode::Char('o') => SwapSelection::ReadOnly,
+            KeyCode::Char('d') => SwapSelection::Delete,
+            KeyCode::Char('a') => SwapSelection::Abort,
+            _ => return,
+        };
+
+        let buffer = self.win().buffer;
+        let model = &mut self.buffers[buffer];
+        let result = match selection {
+            SwapSelection::Recover => model
+                .recover_swap()
+                .map(|_| "Recovered unsaved changes, :w to keep them"),
+            SwapSelection::ReadOnly => {
+                model.open_read_only();
+                Ok("Opened read-only")
+            }
+            SwapSelection::Delete => model.delete_swap().map(|_| "Deleted swap file"),
+            SwapSelection::Abort => {
+                self.mode = Mode::Normal;
+                self.abort_buffer(buffer);
+                return;
+            }
+        };
+        match result {
+            Ok(msg) => {
+                self.mode = Mode::Normal;
+                self.wrap_buffer(buffer);
+                self.slip_cursor();
+                self.msg_display = msg.chars().collect();
+            }
+            Err(msg) => self.msg_display = msg.chars().collect(),
+        }
+    }
+
+    fn file_changed_handle_key_event(&mut self, key_event: KeyEvent) {
+        let pos = FileChangedSelection::ALL
+            .iter()
+            .position(|selection| *selection == self.file_changed_selection)
+            .unwrap();
+        let selection = match key_event.code {
+            // Change selected option
+            KeyCode::Left | KeyCode::Char('<') | KeyCode::Char(',') => {
+                self.file_changed_selection = FileChangedSelection::ALL[pos.saturating_sub(1)];
+                return;
+            }
+            KeyCode::Right | KeyCode::Char('>') | KeyCode::Char('.') => {
+                self.file_changed_selection = FileChangedSelection::ALL[cmp::min(pos + 1, 2)];
+                return;
+            }
+            // Scroll through the differences
+            KeyCode::Up => {
+                self.scroll_diff_amount = self.scroll_diff_amount.saturating_sub(1);
+                return;
+            }
+            KeyCode::Down => {
+                if (self.scroll_diff_amount as usize) + 1 < self.file_diff.len() {
+                    self.scroll_diff_amount += 1;
+                }
+                return;
+            }
+            // Confirm selection, or pick an option by its first letter
+            KeyCode::Enter => self.file_changed_selection,
+            KeyCode::Char('r') => FileChangedSelection::Reload,
+            KeyCode::Char('k') | KeyCode::Esc => FileChangedSelection::KeepMine,
+            KeyCode::Char('d') => FileChangedSelection::Diff,
+            _ => return,
+        };
+
+        let buffer = self.changed_buffers[0];
+        let model = &mut self.buffers[buffer];
+        let result = match selection {
+            FileChangedSelection::Reload => model
+                .reload(None)
+                .map(|warning| match warning {
+                    Some(warning) => format!("Reloaded file ({})", warning),
+                    None => String::from("Reloaded file"),
+                })
+                .map_err(|e| format!("Error: could not reload file: {}", e)),
+            FileChangedSelection::KeepMine => {
+                model.keep_changes();
+                Ok(String::from("Kept buffer, :w to overwrite the file"))
+            }
+            FileChangedSelection::Diff => {
+                self.file_changed_selection = FileChangedSelection::Diff;
+                self.file_diff = model.diff_with_disk();
+                self.scroll_diff_amount = 0;
+                return;
+            }
+        };
+        self.changed_buffers.remove(0);
+        self.file_diff = vec![];
+        self.mode = Mode::Normal;
+        self.wrap_buffer(buffer);
+        self.win_mut().clamp_cursor(&Mode::Normal);
+        self.msg_display = match result {
+            Ok(msg) | Err(msg) => msg.chars().collect(),
+        };
+    }
+
+    /*
+     * Close every window showing a buffer the user chose not to open, quitting if no windows are left
+     */
+    fn abort_buffer(&mut self, buffer: usize) {
+        if self
+            .tabs
+            .iter()
+            .all(|page| page.windows.iter().all(|window| window.buffer == buffer))
+        {
+            self.exit();
+            return;
+        }
+        let mut tab = 0;
+        while tab < self.tabs.len() {
+            let page = &mut self.tabs[tab];
+            for window in page.windows.iter().filter(|window| window.buffer == buffer) {
+                page.layout.remove(window.id);
+            }
+            page.windows.retain(|window| window.buffer != buffer);
+            if page.windows.is_empty() {
+                self.tabs.remove(tab);
+                continue;
+            }
+            let ids = page.layout.window_ids();
+            if !ids.contains(&page.active_window) {
+                page.active_window = ids[0];
+            }
+            if page.previous_window.is_some_and(|id| !ids.contains(&id)) {
+                page.previous_window = None;
+            }
+            tab += 1;
+        }
+        self.switch_tab(cmp::min(self.active_tab, self.tabs.len() - 1));
+    }
+
     fn help_handle_key_event(&mut self, key_event: KeyEvent) {
         match key_event.code {
             // Close help pop-up
@@ -603,6 +1808,15 @@ impl<'a> App<'a> {
     }
 
     fn normal_handle_key_event(&mut self, key_event: KeyEvent) {
+        // Finish any two key command
+        if let Some(pending_command) = self.pending_command.take() {
+            self.reset_msg_display();
+            match pending_command {
+                PendingCommand::Window => self.window_handle_key_event(key_event),
+                PendingCommand::Goto => self.goto_handle_key_event(key_event),
+            }
+            return;
+        }
         // Clear any error/status messages once the user makes an input
         if !self.get_show_highlights() {
             self.msg_display = vec![];
@@ -616,27 +1830,72 @@ impl<'a> App<'a> {
             .chars()
             .collect();
         }
+        // Directory listings have their own commands
+        if self.is_directory_listing() && self.explorer_handle_key_event(key_event) {
+            return;
+        }
+        // Jump to the list entry under the cursor
+        if key_event.code == KeyCode::Enter
+            && let Some(source) = self.buffers[self.win().buffer].list
+        {
+            let line_num = self.win().get_cursor_line().line_num;
+            if let Err(msg) = self.jump_to_entry(source, line_num - 1) {
+                self.msg_display = msg.chars().collect();
+            }
+            return;
+        }
         match key_event.code {
             // Turn off any search highlighting
             KeyCode::Esc => {
-                self.search_term = String::new();
-                self.match_ranges = vec![];
+                self.clear_search();
                 self.msg_display = vec![];
             }
+            // Start a window command
+            KeyCode::Char('w') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.pending_command = Some(PendingCommand::Window);
+                self.msg_display = "^W".chars().collect();
+            }
+            // Open the file finder popup
+            KeyCode::Char('p') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.open_finder();
+            }
+            // Stop a running build, test run or project search
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cancel_jobs();
+            }
+            // Start a goto command (ex. [g][t] for next tab page)
+            KeyCode::Char('g') => {
+                self.pending_command = Some(PendingCommand::Goto);
+                self.msg_display = vec!['g'];
+            }
             // Enter insert mode
+            KeyCode::Char('i') | KeyCode::Char('I')
+                if self.buffers[self.win().buffer].is_listing() =>
+            {
+                self.msg_display = "Error: Cannot edit a listing".chars().collect();
+            }
+            KeyCode::Char('i') | KeyCode::Char('I')
+                if self.buffers[self.win().buffer].is_loading() =>
+            {
+                self.msg_display = "Error: File is still loading".chars().collect();
+            }
             KeyCode::Char('i') | KeyCode::Char('I') => self.mode = Mode::Insert,
             // Enter command mode
             KeyCode::Char(':') => {
                 self.mode = Mode::Command;
                 self.msg_display = vec![':'];
             }
-            // Enter search mode
-            KeyCode::Char('/') => {
+            // Enter search mode, searching backward with ?
+            KeyCode::Char('/') | KeyCode::Char('?') => {
                 self.mode = Mode::SearchInput;
-                self.search_term = String::new();
-                self.match_ranges = vec![];
-                self.msg_display = vec!['/'];
+                self.save_search();
+                self.search_backward = key_event.code == KeyCode::Char('?');
+                self.search_origin = self.cursor_char_index();
+                self.msg_display = vec![if self.search_backward { '?' } else { '/' }];
             }
+            // Jump to the next/previous search match
+            KeyCode::Char('n') => self.search_next(false),
+            KeyCode::Char('N') => self.search_next(true),
             // Open help popup
             KeyCode::Char('z') | KeyCode::Char('Z') => {
                 self.mode = Mode::Help;
@@ -673,409 +1932,1903 @@ impl<'a> App<'a> {
             // Submit command and execute if it exists
             KeyCode::Enter => {
                 let command: String = self.msg_display.iter().collect();
-                match command.as_str() {
-                    // Write to file
-                    ":w" | ":write" => {
-                        match self.model.save() {
-                            Ok(_) => {
-                                self.msg_display = "Wrote file".chars().collect();
-                            }
-                            Err(e) => {
-                                self.msg_display = format!("Error: could not write file: {}", e)
-                                    .chars()
-                                    .collect();
-                            }
-                        }
-                        self.mode = Mode::Normal;
+                self.run_command(command.as_str());
+            }
+            // Delete right-most user input character
+            KeyCode::Backspace => {
+                self.msg_display.pop();
+                if self.msg_display.is_empty() {
+                    if self.get_show_highlights() {
+                        // Re-display search matches message if we are still highlighting
+                        self.msg_display = format!(
+                            "{} matches for {}",
+                            self.match_ranges.len(),
+                            &self.search_term
+                        )
+                        .chars()
+                        .collect();
                     }
-                    // Attempt to Quit without saving
-                    ":q" | ":quit" => self.mode = Mode::Quit,
-                    // Write and quit
-                    ":wq" => match self.model.save() {
-                        Ok(_) => {
-                            self.exit();
-                        }
-                        Err(e) => {
-                            self.msg_display = format!("Error: could not write file: {}", e)
-                                .chars()
-                                .collect();
+                    self.mode = Mode::Normal;
+                }
+            }
+            // Type into user input
+            KeyCode::Char(character) => self.msg_display.push(character),
+            _ => {}
+        }
+    }
 
-                            self.mode = Mode::Normal;
-                        }
-                    },
-                    // Toggle line numbers
-                    ":set number" | ":set num" | ":set nu" | ":num" | ":nu" => {
-                        self.show_line_nums = !self.show_line_nums;
-                        // Re-wrap display content for view
-                        self.wrap_text();
-                        self.snap_cursor(); // mainly used when turning off show_line_nums to snap to end of short lines
-                        self.slip_cursor(); // mainly used when turning on show_line_nums to stay out of line num region
-
-                        if self.get_show_highlights() {
-                            // Re-display search matches message if we are still highlighting
-                            self.msg_display = format!(
-                                "{} matches for {}",
-                                self.match_ranges.len(),
-                                &self.search_term
-                            )
+    /*
+     * Executes a submitted command line, such as ":w" or ":split file.txt"
+     */
+    fn run_command(&mut self, command: &str) {
+        // Replace matches of a pattern (ex. :s/foo/bar/g), which may contain spaces
+        if let Some(substitute) = Substitute::parse(command) {
+            self.mode = Mode::Normal;
+            self.msg_display = match substitute.and_then(|substitute| self.substitute(&substitute))
+            {
+                Ok(msg) | Err(msg) => msg,
+            }
+            .chars()
+            .collect();
+            return;
+        }
+        // Separate the command name from its argument (if any)
+        let (command_name, command_arg) = match command.split_once(' ') {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (command, ""),
+        };
+        match (command_name, command_arg) {
+            // Write to another file, which becomes the buffer's file if it doesn't have one yet
+            (name @ (":w" | ":write" | ":w!" | ":write!"), file_name) if !file_name.is_empty() => {
+                self.mode = Mode::Normal;
+                self.msg_display = match self.write_to(file_name, name.ends_with('!')) {
+                    Ok(_) => format!("Wrote {}", file_name),
+                    Err(e) => format!("Error: could not write file: {}", e),
+                }
+                .chars()
+                .collect();
+            }
+            // Write to file, with ! overwriting read-only files or changes made by other programs
+            (":w" | ":write" | ":w!" | ":write!", "") => {
+                match self.save_buffer(command_name.ends_with('!')) {
+                    Ok(None) => {
+                        self.msg_display = "Wrote file".chars().collect();
+                    }
+                    Ok(Some(warning)) => {
+                        self.msg_display = format!("Wrote file ({})", warning).chars().collect();
+                    }
+                    Err(e) => {
+                        self.msg_display = format!("Error: could not write file: {}", e)
                             .chars()
                             .collect();
-                        } else {
-                            // clear the command from the window
-                            self.msg_display = vec![];
-                        }
-                        self.mode = Mode::Normal;
                     }
-                    // Delete current file line at cursor
-                    ":dd" => {
-                        let deleting_line_num =
-                            self.display_content[self.get_cursor_display_row()].line_num;
-
-                        let mut start_idx = 0;
-                        let mut start_found = false;
-                        let mut end_found = false;
-                        let mut curr_display_row = 0;
-                        // Search for the start of the line being deleted, and the start of the line after it
-                        while curr_display_row < self.display_content.len() {
-                            let line = &self.display_content[curr_display_row];
-
-                            if start_found {
-                                if line.line_num != deleting_line_num {
-                                    let end_idx = line.infile_index;
-                                    self.model.delete_range(start_idx, end_idx);
-                                    end_found = true;
-                                    break;
-                                }
-                            } else if line.line_num == deleting_line_num {
-                                start_idx = line.infile_index;
-                                start_found = true;
-                            }
-
-                            curr_display_row += 1;
-                        }
+                }
+                self.mode = Mode::Normal;
+            }
+            // Attempt to Quit without saving. Only the last window asks for confirmation,
+            // while other windows refuse to close if they're all that's left showing unsaved changes
+            (":q" | ":quit", "") => {
+                self.mode = Mode::Normal;
+                let is_last_window = self.tabs.len() == 1 && self.tab().windows.len() == 1;
+                if !is_last_window && self.hides_changes() {
+                    self.msg_display = "Error: No write since last change (add ! to override)"
+                        .chars()
+                        .collect();
+                } else if self.close_window().is_err() && self.close_tab().is_err() {
+                    self.mode = Mode::Quit;
+                } else {
+                    self.reset_msg_display();
+                }
+            }
+            // Close the window even if it hides unsaved changes, or quit without saving from the last window
+            (":q!" | ":quit!", "") => {
+                self.mode = Mode::Normal;
+                if self.close_window().is_err() && self.close_tab().is_err() {
+                    self.exit();
+                } else {
+                    self.reset_msg_display();
+                }
+            }
+            // Write and quit
+            (":wq" | ":wq!", "") => match self.save_buffer(command_name.ends_with('!')) {
+                Ok(_) => {
+                    self.mode = Mode::Normal;
+                    if self.close_window().is_err() && self.close_tab().is_err() {
+                        self.exit();
+                    }
+                    self.reset_msg_display();
+                }
+                Err(e) => {
+                    self.msg_display = format!("Error: could not write file: {}", e)
+                        .chars()
+                        .collect();
+
+                    self.mode = Mode::Normal;
+                }
+            },
+            // Toggle line numbers
+            (":set", "number" | "num" | "nu") | (":num" | ":nu", "") => {
+                let show_line_nums = &mut self.win_mut().show_line_nums;
+                *show_line_nums = !*show_line_nums;
+                // Re-wrap display content for view
+                self.wrap_text();
+                self.snap_cursor(); // mainly used when turning off show_line_nums to snap to end of short lines
+                self.slip_cursor(); // mainly used when turning on show_line_nums to stay out of line num region
 
-                        // If we reached the end of the file before finding a new line, then this is the last line.
-                        // Delete to the end
-                        if !end_found {
-                            self.model.delete_to_end(start_idx);
+                if self.get_show_highlights() {
+                    // Re-display search matches message if we are still highlighting
+                    self.msg_display = format!(
+                        "{} matches for {}",
+                        self.match_ranges.len(),
+                        &self.search_term
+                    )
+                    .chars()
+                    .collect();
+                } else {
+                    // clear the command from the window
+                    self.msg_display = vec![];
+                }
+                self.mode = Mode::Normal;
+            }
+            // Change an option (ex. :set makeprg=cargo check)
+            (":set", option) => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.set_option(option) {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            (":dd", "") if self.buffers[self.win().buffer].is_listing() => {
+                self.mode = Mode::Normal;
+                self.msg_display = "Error: Cannot edit a listing".chars().collect();
+            }
+            (":dd", "") if self.buffers[self.win().buffer].is_loading() => {
+                self.mode = Mode::Normal;
+                self.msg_display = "Error: File is still loading".chars().collect();
+            }
+            // Delete current file line at cursor
+            (":dd", "") => {
+                let deleting_line_num = self.win().get_cursor_line().line_num;
+
+                // Find the start of the line being deleted, and the start of the line after it
+                let rope = &self.model().rope;
+                let start_idx = rope.line_to_char(deleting_line_num - 1);
+                let end_idx = (deleting_line_num < rope.len_lines())
+                    .then(|| rope.line_to_char(deleting_line_num));
+
+                match end_idx {
+                    Some(end_idx) => self.model().delete_range(start_idx, end_idx),
+                    // If we reached the end of the file before finding a new line, then this is the last line.
+                    // Delete to the end
+                    None => self.model().delete_to_end(start_idx),
+                }
+
+                // Re-wrap displayed text
+                self.wrap_text();
+
+                // Move cursor upwards if :dd ended up leaving cursor out of bounds
+                let window = self.win_mut();
+                while window.get_cursor_display_row() >= window.num_rows() {
+                    window.cursor_pos.0 -= 1;
+                }
+                self.snap_cursor();
+                self.slip_cursor();
+
+                // Return to normal mode and clear :dd command from message display
+                self.mode = Mode::Normal;
+                self.msg_display = vec![];
+            }
+            // Split the current window, optionally opening another file in the new window
+            (":sp" | ":split", file_name) => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.split_window(Direction::Vertical, file_name) {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            (":vs" | ":vsplit", file_name) => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.split_window(Direction::Horizontal, file_name) {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            // Close the current window, unless it is the last one
+            (":clo" | ":close", "") => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.close_window() {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            // Close every other window
+            (":on" | ":only", "") => {
+                self.mode = Mode::Normal;
+                self.only_window();
+                self.reset_msg_display();
+            }
+            // Browse the directory of the current file, or the given directory
+            (":Ex" | ":Explore", dir_name) => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                let dir_name = if !dir_name.is_empty() {
+                    String::from(dir_name)
+                } else if self.is_directory_listing() {
+                    String::from(self.get_filename())
+                } else {
+                    match Path::new(self.get_filename()).parent() {
+                        Some(parent) if !parent.as_os_str().is_empty() => {
+                            parent.to_string_lossy().into_owned()
                         }
+                        _ => String::from("."),
+                    }
+                };
+                match self.open_buffer(dir_name.as_str()) {
+                    Ok(buffer) => self.show_buffer(buffer),
+                    Err(_) => {
+                        self.msg_display = "Error: could not open directory".chars().collect();
+                    }
+                }
+            }
+            // Open a file in the current window, or re-read the current file (! discarding unsaved changes)
+            (name @ (":e" | ":edit" | ":e!" | ":edit!"), arg) => {
+                self.mode = Mode::Normal;
+                self.msg_display = match self.edit_file(arg, name.ends_with('!')) {
+                    Ok(msg) | Err(msg) => msg.chars().collect(),
+                };
+            }
+            // Open a file (or keep the current one) read-only
+            (":view" | ":vie", arg) => {
+                self.mode = Mode::Normal;
+                let result = if arg.is_empty() {
+                    Ok(String::new())
+                } else {
+                    self.edit_file(arg, false)
+                };
+                let buffer = self.win().buffer;
+                self.msg_display = match result {
+                    Ok(_) if self.buffers[buffer].is_listing() => {
+                        String::from("Error: Cannot view a listing read-only")
+                    }
+                    Ok(_) => {
+                        self.buffers[buffer].read_only = true;
+                        format!("Viewing {} read-only", self.buffers[buffer].file_name)
+                    }
+                    Err(msg) => msg,
+                }
+                .chars()
+                .collect();
+            }
+            // Open the file finder popup
+            (":Files", "") => self.open_finder(),
+            // Run the test (or test module) around the cursor
+            (":TestNearest", "") => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.run_nearest_test() {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            // Show the output of the latest test run again
+            (":TestResults", "") => {
+                if self.test_run.is_some() {
+                    self.mode = Mode::TestResults;
+                    self.msg_display = vec![];
+                } else {
+                    self.mode = Mode::Normal;
+                    self.msg_display = "Error: No tests have been run".chars().collect();
+                }
+            }
+            // Save the open buffers, tab pages and windows to be restored with rust-vim -S
+            (name @ (":mksession" | ":mks" | ":mksession!" | ":mks!"), file_name) => {
+                self.mode = Mode::Normal;
+                let file_name = if file_name.is_empty() {
+                    session::DEFAULT_SESSION_FILE
+                } else {
+                    file_name
+                };
+                let result = if !name.ends_with('!') && Path::new(file_name).exists() {
+                    Err(format!("Error: {} exists (add ! to overwrite)", file_name))
+                } else {
+                    self.make_session().and_then(|session| {
+                        session
+                            .write(Path::new(file_name))
+                            .map_err(|e| format!("Error: could not write session: {}", e))
+                    })
+                };
+                self.msg_display = match result {
+                    Ok(_) => format!("Wrote session to {}", file_name),
+                    Err(msg) => msg,
+                }
+                .chars()
+                .collect();
+            }
+            // Open a new tab page, optionally with another file
+            (":tabnew" | ":tabe" | ":tabedit", file_name) => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.new_tab(file_name) {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            // Close the current tab page, unless it is the last one
+            (":tabc" | ":tabclose", "") => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Err(msg) = self.close_tab() {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+            // Cycle through tab pages
+            (":tabn" | ":tabnext", "") => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                let _ = self.focus_tab(true);
+            }
+            (":tabp" | ":tabprevious", "") => {
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                let _ = self.focus_tab(false);
+            }
+            // Quickfix and location list commands (including :grep), otherwise an invalid command
+            _ => {
+                self.mode = Mode::Normal;
+                let Some((list_command, use_location_list)) = ListCommand::parse(command_name)
+                else {
+                    self.msg_display = "Error: Invalid Command".chars().collect();
+                    return;
+                };
+                self.reset_msg_display();
+                let source = if use_location_list {
+                    ListSource::Location(self.location_owner())
+                } else {
+                    ListSource::Quickfix
+                };
+                if let Err(msg) = self.run_list_command(list_command, source, command, command_arg)
+                {
+                    self.msg_display = msg.chars().collect();
+                }
+            }
+        }
+    }
+
+    fn insert_handle_key_event(&mut self, key_event: KeyEvent) {
+        // Clear any error/status messages once the user makes an input
+        if self.get_show_highlights() {
+            // Re-display search matches message if we are still highlighting
+            self.msg_display = format!(
+                "{} matches for {}",
+                self.match_ranges.len(),
+                &self.search_term
+            )
+            .chars()
+            .collect();
+        } else {
+            self.msg_display = vec![];
+        }
+        match key_event.code {
+            // Exit to normal mode
+            KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                self.snap_cursor();
+                self.slip_cursor();
+            }
+            // Delete characters
+            KeyCode::Backspace => {
+                if self.get_cursor_file_index() > 0 {
+                    self.cursor_left();
+                    self.delete_char();
+                } else {
+                    self.msg_display = "Error: Start of file reached".chars().collect();
+                }
+            }
+            KeyCode::Delete => self.delete_char(),
+            // Type characters
+            KeyCode::Enter => self.insert_char('\n'),
+            KeyCode::Tab => self.insert_char('\t'),
+            KeyCode::Char(character) => self.insert_char(character),
+            // Move cursor
+            KeyCode::Up => self.cursor_up(),
+            KeyCode::Down => self.cursor_down(),
+            KeyCode::Left => self.cursor_left(),
+            KeyCode::Right => self.cursor_right(),
+            _ => {}
+        }
+    }
+    fn delete_char(&mut self) {
+        let file_ind = self.get_cursor_file_index(); // char index of file where character should be deleted
+        if self.model().delete_char(file_ind) {
+            // Re-wrap file content for display
+            self.wrap_text();
+        } else {
+            self.msg_display = "Error: End of file reached".chars().collect();
+        }
+    }
+    fn insert_char(&mut self, c: char) {
+        let file_ind = self.get_cursor_file_index(); // char index of file where character should be inserted
+        self.model().insert_char(c, file_ind);
+
+        // Re-wrap file content for display
+        self.wrap_text();
+        self.cursor_right();
+
+        // If inserted at end of display line, need to shift cursor right one more time to be right of the new character on the new line
+        if self.win().cursor_pos.1 == 1 && c != '\n' {
+            self.cursor_right();
+        }
+    }
+
+    fn search_input_handle_key_event(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            // Cancel and exit to Normal Mode
+            KeyCode::Esc => self.cancel_search_input(),
+            // Submit search query, and jump to the first match after the cursor once it is found
+            KeyCode::Enter => {
+                let search_query: String = self.msg_display[1..].iter().collect();
+                self.mode = Mode::Normal;
+                // Queries which can't be searched for leave the previous search as it was
+                if search_query.is_empty() {
+                    self.restore_search();
+                    self.msg_display = "Error: No matches found".chars().collect();
+                    return;
+                }
+                // Matches are highlighted as the search finds them, and counted once it finishes.
+                // With incsearch, typing the query already started the search
+                if self.search_regex.is_none() || search_query != self.search_term {
+                    let origin = self.search_origin;
+                    if let Err(msg) = self.search_for(search_query.clone()) {
+                        self.restore_search();
+                        self.win_mut().move_cursor_to_index(origin, &Mode::Normal);
+                        self.msg_display = msg.chars().collect();
+                        return;
+                    }
+                }
+                self.discard_saved_search();
+                self.last_search = search_query;
+                self.show_search_status();
+                self.pending_jump = Some((self.search_origin, self.search_backward));
+                self.resolve_jump();
+            }
+            // Delete right-most user input character
+            KeyCode::Backspace => {
+                self.msg_display.pop();
+                // If entire user input deleted, return to normal mode
+                if self.msg_display.is_empty() {
+                    self.cancel_search_input();
+                } else {
+                    self.incremental_search();
+                }
+            }
+            // Type into user input
+            KeyCode::Char(character) => {
+                self.msg_display.push(character);
+                self.incremental_search();
+            }
+            _ => {}
+        }
+    }
+
+    fn scroll_help_up(&mut self) {
+        if self.scroll_help_amount > 0 {
+            self.scroll_help_amount -= 1;
+        }
+    }
+    fn scroll_help_down(&mut self) {
+        self.scroll_help_amount = cmp::min(MAX_HELP_SCROLL, self.scroll_help_amount + 1);
+    }
+
+    fn cursor_up(&mut self) {
+        let window = self.tabs[self.active_tab].win_mut();
+        if let Err(msg) = window.cursor_up(&self.mode) {
+            self.msg_display = msg.chars().collect();
+        }
+    }
+    fn cursor_down(&mut self) {
+        let window = self.tabs[self.active_tab].win_mut();
+        if let Err(msg) = window.cursor_down(&self.mode) {
+            self.msg_display = msg.chars().collect();
+        }
+    }
+    fn cursor_right(&mut self) {
+        let window = self.tabs[self.active_tab].win_mut();
+        if let Err(msg) = window.cursor_right(&self.mode) {
+            self.msg_display = msg.chars().collect();
+        }
+    }
+    fn cursor_left(&mut self) {
+        let window = self.tabs[self.active_tab].win_mut();
+        if let Err(msg) = window.cursor_left(&self.mode) {
+            self.msg_display = msg.chars().collect();
+        }
+    }
+    fn snap_cursor(&mut self) {
+        self.tabs[self.active_tab].win_mut().snap_cursor(&self.mode);
+    }
+    fn slip_cursor(&mut self) {
+        self.win_mut().slip_cursor();
+    }
+
+    fn prompt_handle_key_event(&mut self, key_event: KeyEvent) {
+        let label_len = match &self.prompt {
+            Some(prompt) => prompt.label().chars().count(),
+            None => 0,
+        };
+        match key_event.code {
+            // Cancel and return to Normal Mode
+            KeyCode::Esc => {
+                self.prompt = None;
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+            }
+            // Submit the answer
+            KeyCode::Enter => {
+                let answer: String = self.msg_display[label_len..].iter().collect();
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Some(prompt) = self.prompt.take()
+                    && let Err(e) = self.answer_prompt(prompt, answer.trim())
+                {
+                    self.msg_display = format!("Error: {}", e).chars().collect();
+                }
+            }
+            // Delete right-most user input character, but keep the question
+            KeyCode::Backspace if self.msg_display.len() > label_len => {
+                self.msg_display.pop();
+            }
+            // Type into user input
+            KeyCode::Char(character) => self.msg_display.push(character),
+            _ => {}
+        }
+    }
+
+    fn finder_handle_key_event(&mut self, key_event: KeyEvent) {
+        let Some(finder) = self.finder.as_mut() else {
+            return;
+        };
+        let control = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        match key_event.code {
+            // Close the popup and return to Normal Mode
+            KeyCode::Esc => {
+                self.finder = None;
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+            }
+            // Open the highlighted file in the current window
+            KeyCode::Enter => {
+                let selected = finder.selected_path().map(String::from);
+                self.finder = None;
+                self.mode = Mode::Normal;
+                self.reset_msg_display();
+                if let Some(path) = selected {
+                    match self.open_buffer(path.as_str()) {
+                        Ok(buffer) => self.show_buffer(buffer),
+                        Err(e) => {
+                            self.msg_display = format!("Error: could not open {}: {}", path, e)
+                                .chars()
+                                .collect();
+                        }
+                    }
+                }
+            }
+            // Move the highlight through the matches
+            KeyCode::Up => finder.select_prev(),
+            KeyCode::Down => finder.select_next(),
+            KeyCode::Char('p') if control => finder.select_prev(),
+            KeyCode::Char('n') if control => finder.select_next(),
+            // Edit the query
+            KeyCode::Backspace => finder.pop_char(),
+            KeyCode::Char(character) => finder.push_char(character),
+            _ => {}
+        }
+    }
+
+    fn test_results_handle_key_event(&mut self, key_event: KeyEvent) {
+        let Some(test_run) = self.test_run.as_mut() else {
+            return;
+        };
+        match key_event.code {
+            // Close the popup, leaving the tests running
+            KeyCode::Esc => self.mode = Mode::Normal,
+            // Stop the tests, keeping the popup open
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cancel_jobs();
+            }
+            // Open the file at the selected failure
+            KeyCode::Enter => match test_run.selected_location().cloned() {
+                Some(location) => {
+                    self.mode = Mode::Normal;
+                    self.reset_msg_display();
+                    if let Err(msg) = self.show_location(&location) {
+                        self.msg_display = msg.chars().collect();
+                    }
+                }
+                None => {
+                    self.msg_display = "Error: No location on this line".chars().collect();
+                }
+            },
+            // Move the selection through the output
+            KeyCode::Up | KeyCode::Char('k') => test_run.select_prev(),
+            KeyCode::Down | KeyCode::Char('j') => test_run.select_next(),
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                let result = test_run.select_failure(key_event.code == KeyCode::Char('n'));
+                self.msg_display = match result {
+                    Ok(_) => vec![],
+                    Err(msg) => msg.chars().collect(),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /*
+     * Run the test function or module around the cursor in the background, showing its output in a popup
+     */
+    fn run_nearest_test(&mut self) -> Result<(), String> {
+        if self.test_job.is_some() {
+            return Err(String::from("Error: Tests are already running"));
+        }
+        let window = self.win();
+        let cursor_line = window.get_cursor_line().line_num;
+        let model = &self.buffers[window.buffer];
+        let target = testrunner::find_test(
+            Path::new(&model.file_name),
+            model.rope.to_string().as_str(),
+            cursor_line,
+        )
+        .ok_or(String::from("Error: No test found around the cursor"))?;
+        let test_run = TestRun::new(&target);
+        let job = CommandJob::spawn(test_run.command.as_str(), &target.crate_root)
+            .map_err(|e| format!("Error: could not run {}: {}", test_run.command, e))?;
+        self.test_job = Some(job);
+        self.test_run = Some(test_run);
+        self.mode = Mode::TestResults;
+        self.msg_display = vec![];
+        Ok(())
+    }
+
+    fn open_finder(&mut self) {
+        self.finder = Some(FileFinder::new(Path::new(".")));
+        self.mode = Mode::Finder;
+        self.msg_display = vec![];
+    }
+
+    fn start_prompt(&mut self, prompt: Prompt) {
+        self.msg_display = prompt.label().chars().collect();
+        self.prompt = Some(prompt);
+        self.mode = Mode::Prompt;
+    }
+
+    /*
+     * Carry out the directory browser operation the user was prompted for
+     */
+    fn answer_prompt(&mut self, prompt: Prompt, answer: &str) -> io::Result<()> {
+        let Some(listing) = &self.buffers[self.win().buffer].directory else {
+            return Ok(());
+        };
+        let dir_path = listing.path.clone();
+        match prompt {
+            Prompt::NewFile if !answer.is_empty() => {
+                fs::File::create_new(dir_path.join(answer))?;
+            }
+            Prompt::NewDirectory if !answer.is_empty() => {
+                fs::create_dir(dir_path.join(answer))?;
+            }
+            Prompt::Rename(path) if !answer.is_empty() => {
+                let new_path = dir_path.join(answer);
+                let old_path = model::canonical_path(&path);
+                fs::rename(&path, &new_path)?;
+                // Keep any buffer showing the renamed file pointing at it
+                for model in self.buffers.iter_mut() {
+                    if model.is_file(&old_path) {
+                        model.file_moved(explorer::display_path(&new_path).as_str());
+                    }
+                }
+            }
+            Prompt::Delete(path) if answer == "y" || answer == "Y" => {
+                if path.is_dir() {
+                    fs::remove_dir(&path)?; // Only empty directories are removed
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+            _ => {}
+        }
+        self.refresh_listing()
+    }
+
+    /*
+     * Handles Normal Mode keys specific to directory listings.
+     * Returns false for keys which should behave as they usually do
+     */
+    fn explorer_handle_key_event(&mut self, key_event: KeyEvent) -> bool {
+        let result = match key_event.code {
+            // Open the file or directory under the cursor
+            KeyCode::Enter => match self.listing_entry() {
+                Some(path) => self.open_in_window(path),
+                None => Err("Error: No file under cursor"),
+            },
+            // Go up to the parent directory
+            KeyCode::Char('-') => match &self.buffers[self.win().buffer].directory {
+                Some(listing) => self.open_in_window(listing.parent()),
+                None => Ok(()),
+            },
+            // Create, rename and delete entries
+            KeyCode::Char('%') => {
+                self.start_prompt(Prompt::NewFile);
+                Ok(())
+            }
+            KeyCode::Char('d') => {
+                self.start_prompt(Prompt::NewDirectory);
+                Ok(())
+            }
+            KeyCode::Char('R') | KeyCode::Char('D') => match self.listing_entry() {
+                Some(path) if self.listing_contains(&path) => {
+                    if key_event.code == KeyCode::Char('R') {
+                        self.start_prompt(Prompt::Rename(path));
+                    } else {
+                        self.start_prompt(Prompt::Delete(path));
+                    }
+                    Ok(())
+                }
+                _ => Err("Error: No file under cursor"),
+            },
+            _ => return false,
+        };
+        if let Err(msg) = result {
+            self.msg_display = msg.chars().collect();
+        }
+        true
+    }
+
+    /*
+     * Change an option with "name=value", or show its value with "name?"
+     */
+    fn set_option(&mut self, option: &str) -> Result<(), String> {
+        if let Some(name) = option.strip_suffix('?') {
+            // Boolean options are shown by name, with a "no" prefix when off
+            if let Some(on) = self.flag_mut(name).map(|on| *on) {
+                let prefix = if on { "" } else { "no" };
+                self.msg_display = format!("{}{}", prefix, name).chars().collect();
+                return Ok(());
+            }
+            let backup_options = &self.backup_options;
+            let value = match name {
+                "makeprg" | "mp" => self.makeprg.clone(),
+                "backupdir" | "bdir" => backup_options.dir.clone(),
+                "backupext" | "bex" => backup_options.ext.clone(),
+                "backupstyle" => String::from(backup_options.style.name()),
+                "backupkeep" => backup_options.keep.to_string(),
+                "fileformat" | "ff" => String::from(self.get_file_format()),
+                "fileencoding" | "fenc" => String::from(self.get_file_encoding()),
+                _ => return Err(format!("Error: Unknown option: {}", name)),
+            };
+            self.msg_display = format!("{}={}", name, value).chars().collect();
+            return Ok(());
+        }
+
+        // Boolean options are turned on by name, and off with a "no" prefix (ex. :set nobackup)
+        let Some((name, value)) = option.split_once('=') else {
+            let (name, on) = match option.strip_prefix("no") {
+                Some(name) => (name, false),
+                None => (option, true),
+            };
+            *self
+                .flag_mut(name)
+                .ok_or(format!("Error: Unknown option: {}", option))? = on;
+            return Ok(());
+        };
+        if value.is_empty() {
+            return Err(format!("Error: {} cannot be empty", name));
+        }
+        let backup_options = &mut self.backup_options;
+        match name {
+            "makeprg" | "mp" => self.makeprg = String::from(value),
+            "backupdir" | "bdir" => backup_options.dir = String::from(value),
+            "backupext" | "bex" => backup_options.ext = String::from(value),
+            "backupstyle" => {
+                backup_options.style = BackupStyle::parse(value).ok_or(String::from(
+                    "Error: backupstyle must be simple, numbered or timestamped",
+                ))?;
+            }
+            "backupkeep" => {
+                backup_options.keep = value
+                    .parse()
+                    .ok()
+                    .filter(|keep| *keep > 0)
+                    .ok_or(String::from("Error: backupkeep must be a positive number"))?;
+            }
+            // Buffer options only apply to the active buffer
+            "fileformat" | "ff" => {
+                let file_format = FileFormat::parse(value)
+                    .ok_or(String::from("Error: fileformat must be unix, dos or mac"))?;
+                let buffer = self.win().buffer;
+                self.buffers[buffer].set_file_format(file_format)?;
+            }
+            "fileencoding" | "fenc" => {
+                let file_encoding = Encoding::parse(value).ok_or(String::from(
+                    "Error: fileencoding must be utf-8, utf-8-bom, utf-16le, utf-16be (with -bom for a byte order mark), latin1 or cp1252",
+                ))?;
+                let buffer = self.win().buffer;
+                self.buffers[buffer].set_file_encoding(file_encoding)?;
+            }
+            _ => return Err(format!("Error: Unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    // Boolean option with the given name (or its short form)
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "backup" | "bk" => Some(&mut self.backup_options.backup),
+            "writebackup" | "wb" => Some(&mut self.backup_options.writebackup),
+            "autoread" | "ar" => Some(&mut self.autoread),
+            "ignorecase" | "ic" => Some(&mut self.search_options.ignorecase),
+            "smartcase" | "scs" => Some(&mut self.search_options.smartcase),
+            "literal" => Some(&mut self.search_options.literal),
+            "incsearch" | "is" => Some(&mut self.incsearch),
+            // Buffer options only apply to the active buffer
+            "readonly" | "ro" => {
+                let buffer = self.win().buffer;
+                Some(&mut self.buffers[buffer].read_only)
+            }
+            _ => None,
+        }
+    }
+
+    /*
+     * Every option as an argument to :set, used to save them in sessions
+     */
+    fn option_settings(&self) -> Vec<String> {
+        let backup_options = &self.backup_options;
+        let flag = |name: &str, on: bool| format!("{}{}", if on { "" } else { "no" }, name);
+        vec![
+            format!("makeprg={}", self.makeprg),
+            flag("backup", backup_options.backup),
+            flag("writebackup", backup_options.writebackup),
+            format!("backupdir={}", backup_options.dir),
+            format!("backupext={}", backup_options.ext),
+            format!("backupstyle={}", backup_options.style.name()),
+            format!("backupkeep={}", backup_options.keep),
+            flag("autoread", self.autoread),
+            flag("ignorecase", self.search_options.ignorecase),
+            flag("smartcase", self.search_options.smartcase),
+            flag("literal", self.search_options.literal),
+            flag("incsearch", self.incsearch),
+        ]
+    }
+
+    /*
+     * Runs a quickfix or location list command on the given list
+     */
+    fn run_list_command(
+        &mut self,
+        list_command: ListCommand,
+        source: ListSource,
+        command: &str,
+        arg: &str,
+    ) -> Result<(), String> {
+        match list_command {
+            ListCommand::Open => self.open_list_window(source),
+            ListCommand::Close => self.close_list_window(source),
+            ListCommand::Next | ListCommand::Prev => {
+                let list = self.list(source).ok_or(no_list_error(source))?;
+                let idx = list.step(matches!(list_command, ListCommand::Next))?;
+                self.jump_to_entry(source, idx)
+            }
+            // Jump to the given (1-indexed) entry, or back to the current one
+            ListCommand::Jump => {
+                let list = self.list(source).ok_or(no_list_error(source))?;
+                if list.entries.is_empty() {
+                    return Err(String::from("Error: No entries"));
+                }
+                let idx = if arg.is_empty() {
+                    list.current.unwrap_or(0)
+                } else {
+                    let entry_num: usize = arg
+                        .parse()
+                        .map_err(|_| String::from("Error: Invalid entry number"))?;
+                    entry_num.clamp(1, list.entries.len()) - 1
+                };
+                self.jump_to_entry(source, idx)
+            }
+            // There is no expression language, so the argument is the text itself (with \n separating lines)
+            ListCommand::Expr => {
+                let text = arg.trim_matches('"').replace("\\n", "\n");
+                self.fill_list(source, command, quickfix::parse_errors(&text))
+            }
+            ListCommand::File => {
+                let file_name = if arg.is_empty() { ERROR_FILE } else { arg };
+                let text = fs::read_to_string(file_name)
+                    .map_err(|e| format!("Error: could not read {}: {}", file_name, e))?;
+                self.fill_list(source, command, quickfix::parse_errors(&text))
+            }
+            ListCommand::Grep => self.start_grep(source, command, arg),
+            ListCommand::Make => self.start_make(source, arg),
+        }
+    }
+
+    /*
+     * Start running makeprg (with any extra arguments) in the background.
+     * Problems it reports fill the given list as they are printed
+     */
+    fn start_make(&mut self, source: ListSource, args: &str) -> Result<(), String> {
+        if self.make_job.is_some() {
+            return Err(String::from("Error: A build is already running"));
+        }
+        let command = if args.is_empty() {
+            self.makeprg.clone()
+        } else {
+            format!("{} {}", self.makeprg, args)
+        };
+        let job = CommandJob::spawn(command.as_str(), Path::new("."))
+            .map_err(|e| format!("Error: could not run {}: {}", command, e))?;
+        self.make_job = Some((job, source));
+        self.make_marks.clear();
+        if let Err(msg) = self.set_list(
+            source,
+            QuickfixList::new(format!(":{}", command).as_str(), vec![]),
+        ) {
+            self.make_job = None;
+            return Err(msg);
+        }
+        self.msg_display = format!("Running {}...", command).chars().collect();
+        Ok(())
+    }
+
+    /*
+     * Replace a list with the given entries and jump to the first one
+     */
+    fn fill_list(
+        &mut self,
+        source: ListSource,
+        title: &str,
+        entries: Vec<Location>,
+    ) -> Result<(), String> {
+        let empty = entries.is_empty();
+        self.set_list(source, QuickfixList::new(title, entries))?;
+        if empty {
+            return Err(String::from("Error: No entries found"));
+        }
+        self.jump_to_entry(source, 0)
+    }
+
+    /*
+     * Start a project search, sending its matches to the given list as they are found
+     */
+    fn start_grep(&mut self, source: ListSource, title: &str, args: &str) -> Result<(), String> {
+        let (pattern, path) = grep::split_args(args);
+        if pattern.is_empty() {
+            return Err(String::from("Error: No pattern given"));
+        }
+        let path = if path.is_empty() { "." } else { path };
+        if !Path::new(path).exists() {
+            return Err(format!("Error: {} does not exist", path));
+        }
+        if let Some((job, _)) = self.grep_job.take() {
+            job.cancel();
+        }
+        let re = search::compile(pattern, self.search_options)?;
+        let job = GrepJob::spawn(re, Path::new(path));
+        self.grep_job = Some((job, source));
+        if let Err(msg) = self.set_list(source, QuickfixList::new(title, vec![])) {
+            self.grep_job = None;
+            return Err(msg);
+        }
+        self.open_list_window(source)
+    }
+
+    /*
+     * Used to access the quickfix list, or a window's location list
+     */
+    fn list(&self, source: ListSource) -> Option<&QuickfixList> {
+        match source {
+            ListSource::Quickfix => Some(&self.quickfix),
+            ListSource::Location(id) => self
+                .tabs
+                .iter()
+                .flat_map(|tab| tab.windows.iter())
+                .find(|window| window.id == id)?
+                .location_list
+                .as_ref(),
+        }
+    }
+    fn list_mut(&mut self, source: ListSource) -> Option<&mut QuickfixList> {
+        match source {
+            ListSource::Quickfix => Some(&mut self.quickfix),
+            ListSource::Location(id) => self
+                .tabs
+                .iter_mut()
+                .flat_map(|tab| tab.windows.iter_mut())
+                .find(|window| window.id == id)?
+                .location_list
+                .as_mut(),
+        }
+    }
+
+    fn set_list(&mut self, source: ListSource, list: QuickfixList) -> Result<(), String> {
+        match source {
+            ListSource::Quickfix => self.quickfix = list,
+            ListSource::Location(id) => {
+                let window = self
+                    .tabs
+                    .iter_mut()
+                    .flat_map(|tab| tab.windows.iter_mut())
+                    .find(|window| window.id == id)
+                    .ok_or(String::from("Error: Window no longer exists"))?;
+                window.location_list = Some(list);
+            }
+        }
+        self.refresh_list_buffer(source);
+        Ok(())
+    }
+
+    // Window that location list commands apply to. From a location list window, that's the window owning the list
+    fn location_owner(&self) -> usize {
+        match self.buffers[self.win().buffer].list {
+            Some(ListSource::Location(owner)) => owner,
+            _ => self.tab().active_window,
+        }
+    }
+
+    fn list_buffer(&self, source: ListSource) -> Option<usize> {
+        self.buffers
+            .iter()
+            .position(|model| model.list == Some(source))
+    }
+
+    fn list_buffer_name(&self, source: ListSource) -> String {
+        let (title, num_entries) = match self.list(source) {
+            Some(list) => (list.title.as_str(), list.entries.len()),
+            None => ("", 0),
+        };
+        let running = matches!(&self.grep_job, Some((_, target)) if *target == source)
+            || matches!(&self.make_job, Some((_, target)) if *target == source);
+        format!(
+            "[{}] {} ({} entries{})",
+            source.label(),
+            title,
+            num_entries,
+            if running { ", running..." } else { "" }
+        )
+    }
+
+    // Re-display a list in its buffer (if it has one) after its entries changed
+    fn refresh_list_buffer(&mut self, source: ListSource) {
+        let Some(buffer) = self.list_buffer(source) else {
+            return;
+        };
+        let entries = self
+            .list(source)
+            .map(|list| list.entries.clone())
+            .unwrap_or_default();
+        self.buffers[buffer].set_entries(&entries);
+        self.buffers[buffer].file_name = self.list_buffer_name(source);
+        self.wrap_buffer(buffer);
+    }
+
+    /*
+     * Focus the window showing a list, opening one if needed.
+     * The quickfix window spans the bottom of the tab, location list windows open below the window owning the list
+     */
+    fn open_list_window(&mut self, source: ListSource) -> Result<(), String> {
+        if self.list(source).is_none() {
+            return Err(no_list_error(source));
+        }
+        let buffer = match self.list_buffer(source) {
+            Some(buffer) => buffer,
+            None => {
+                self.buffers.push(EditorModel::from_list(source));
+                self.refresh_list_buffer(source);
+                self.buffers.len() - 1
+            }
+        };
+        if let Some(id) = self
+            .tab()
+            .windows
+            .iter()
+            .find(|window| window.buffer == buffer)
+            .map(|window| window.id)
+        {
+            self.focus_window(id);
+            return Ok(());
+        }
+
+        let new_id = self.next_window_id;
+        let area = self.content_area();
+        let areas = self.tab().layout.areas(area);
+        let tab = self.tab_mut();
+        match source {
+            ListSource::Location(owner) if tab.layout.contains(owner) => {
+                let (_, owner_area) = *areas.iter().find(|(id, _)| *id == owner).unwrap();
+                tab.layout
+                    .split(owner, new_id, Direction::Vertical, owner_area.height);
+                tab.layout.swap(owner, new_id);
+            }
+            _ => tab
+                .layout
+                .split_bottom(new_id, LIST_WINDOW_HEIGHT, area.height),
+        }
+        // Undo the split if it leaves any window too small
+        if tab
+            .layout
+            .areas(area)
+            .iter()
+            .any(|(_, area)| area.height < MIN_WINDOW_HEIGHT || area.width < MIN_WINDOW_WIDTH)
+        {
+            tab.layout.remove(new_id);
+            return Err(String::from("Error: Not enough room for the list window"));
+        }
+        self.next_window_id += 1;
+        let show_line_nums = self.win().show_line_nums;
+        self.tab_mut().windows.push(Window::new(
+            new_id,
+            buffer,
+            (LIST_WINDOW_HEIGHT, area.width),
+            show_line_nums,
+        ));
+        self.focus_window(new_id);
+        self.resize_windows();
+        Ok(())
+    }
+
+    fn close_list_window(&mut self, source: ListSource) -> Result<(), String> {
+        let Some(buffer) = self.list_buffer(source) else {
+            return Ok(());
+        };
+        let Some(id) = self
+            .tab()
+            .windows
+            .iter()
+            .find(|window| window.buffer == buffer)
+            .map(|window| window.id)
+        else {
+            return Ok(());
+        };
+        let active_window = self.tab().active_window;
+        self.focus_window(id);
+        self.close_window()?;
+        if active_window != id {
+            self.focus_window(active_window);
+        }
+        Ok(())
+    }
+
+    /*
+     * Open the file of a list entry at its line and column, making it the list's current entry.
+     * From a list window, the file is shown in the window owning the location list,
+     * the window used before the list window, or another window of the tab
+     */
+    fn jump_to_entry(&mut self, source: ListSource, idx: usize) -> Result<(), String> {
+        let list = self.list_mut(source).ok_or(no_list_error(source))?;
+        let location = list
+            .entries
+            .get(idx)
+            .cloned()
+            .ok_or(String::from("Error: No such entry"))?;
+        list.current = Some(idx);
+        let num_entries = list.entries.len();
+        if !Path::new(&location.path).is_file() {
+            return Err(format!("Error: {} does not exist", location.path));
+        }
+
+        if self.buffers[self.win().buffer].list.is_some() {
+            let tab = self.tab();
+            let shows_file = |id: &usize| {
+                tab.windows
+                    .iter()
+                    .any(|window| window.id == *id && self.buffers[window.buffer].list.is_none())
+            };
+            let owner = match source {
+                ListSource::Location(owner) => Some(owner),
+                ListSource::Quickfix => None,
+            };
+            let target = owner
+                .filter(|id| tab.layout.contains(*id))
+                .or(tab.previous_window.filter(shows_file))
+                .or_else(|| tab.layout.window_ids().into_iter().find(shows_file));
+            match target {
+                Some(id) => self.focus_window(id),
+                None => self.split_window(Direction::Vertical, "")?,
+            }
+        }
+        self.show_location(&location)?;
+
+        // Keep list windows' cursors on the current entry
+        if let Some(list_buffer) = self.list_buffer(source)
+            && idx < self.buffers[list_buffer].rope.len_lines()
+        {
+            let index = self.buffers[list_buffer].rope.line_to_char(idx);
+            for window in self.tabs[self.active_tab]
+                .windows
+                .iter_mut()
+                .filter(|window| window.buffer == list_buffer)
+            {
+                window.move_cursor_to_index(index, &Mode::Normal);
+            }
+        }
+        self.msg_display = format!("({} of {}): {}", idx + 1, num_entries, location.text)
+            .chars()
+            .collect();
+        Ok(())
+    }
+
+    /*
+     * Open a location's file in the current window, with the cursor at its line and column
+     */
+    fn show_location(&mut self, location: &Location) -> Result<(), String> {
+        if !Path::new(&location.path).is_file() {
+            return Err(format!("Error: {} does not exist", location.path));
+        }
+        let buffer = self
+            .open_buffer(location.path.as_str())
+            .map_err(|_| String::from("Error: could not open file"))?;
+        if self.win().buffer != buffer {
+            self.show_buffer(buffer);
+        }
+        let index = self.buffers[buffer].line_col_to_index(location.line, location.col);
+        self.tabs[self.active_tab]
+            .win_mut()
+            .move_cursor_to_index(index, &self.mode);
+        Ok(())
+    }
+
+    // Write the active buffer to its file
+    fn save_buffer(&mut self, force: bool) -> io::Result<Option<String>> {
+        let buffer = self.win().buffer;
+        self.buffers[buffer].save(&self.backup_options, force)
+    }
+
+    /*
+     * Write the active buffer to the given file. Unnamed buffers take the name and are saved as usual,
+     * while other buffers have a copy written. Existing files are only overwritten when forced
+     */
+    fn write_to(&mut self, file_name: &str, force: bool) -> io::Result<()> {
+        let buffer = self.win().buffer;
+        let model = &mut self.buffers[buffer];
+        if !force && Path::new(file_name).exists() {
+            return Err(io::Error::other(format!(
+                "{} exists (add ! to overwrite)",
+                file_name
+            )));
+        }
+        if !model.file_name.is_empty() {
+            return model.write_copy(Path::new(file_name));
+        }
+        model.set_file_name(file_name);
+        model.save(&self.backup_options, force)?;
+        self.watch_buffer(buffer);
+        Ok(())
+    }
 
-                        // Re-wrap displayed text
-                        self.wrap_text();
+    fn is_directory_listing(&self) -> bool {
+        self.buffers[self.win().buffer].directory.is_some()
+    }
 
-                        // Move cursor upwards if :dd ended up leaving cursor out of bounds
-                        while self.get_cursor_display_row() >= self.display_content.len() {
-                            self.cursor_pos.0 -= 1;
-                        }
-                        self.snap_cursor();
-                        self.slip_cursor();
+    // Path of the directory listing entry under the cursor
+    fn listing_entry(&self) -> Option<PathBuf> {
+        let window = self.win();
+        let line_num = window.get_cursor_line().line_num;
+        self.buffers[window.buffer]
+            .directory
+            .as_ref()?
+            .entry_at_line(line_num)
+    }
 
-                        // Return to normal mode and clear :dd command from message display
-                        self.mode = Mode::Normal;
-                        self.msg_display = vec![];
-                    }
-                    // Invalid command
-                    _ => {
-                        let error_msg = String::from("Error: Invalid Command");
-                        self.mode = Mode::Normal;
-                        self.msg_display = error_msg.chars().collect();
-                    }
-                }
-            }
-            // Delete right-most user input character
-            KeyCode::Backspace => {
-                self.msg_display.pop();
-                if self.msg_display.is_empty() {
-                    if self.get_show_highlights() {
-                        // Re-display search matches message if we are still highlighting
-                        self.msg_display = format!(
-                            "{} matches for {}",
-                            self.match_ranges.len(),
-                            &self.search_term
-                        )
-                        .chars()
-                        .collect();
-                    }
-                    self.mode = Mode::Normal;
-                }
-            }
-            // Type into user input
-            KeyCode::Char(character) => self.msg_display.push(character),
-            _ => {}
+    fn listing_contains(&self, path: &PathBuf) -> bool {
+        match &self.buffers[self.win().buffer].directory {
+            Some(listing) => listing.entries.contains(path),
+            None => false,
         }
     }
 
-    fn insert_handle_key_event(&mut self, key_event: KeyEvent) {
-        // Clear any error/status messages once the user makes an input
-        if self.get_show_highlights() {
-            // Re-display search matches message if we are still highlighting
-            self.msg_display = format!(
-                "{} matches for {}",
-                self.match_ranges.len(),
-                &self.search_term
-            )
-            .chars()
-            .collect();
+    fn open_in_window(&mut self, path: PathBuf) -> Result<(), &'static str> {
+        let buffer = self
+            .open_buffer(explorer::display_path(&path).as_str())
+            .map_err(|_| "Error: could not open file")?;
+        self.show_buffer(buffer);
+        Ok(())
+    }
+
+    /*
+     * Open a file in the current window, or re-read the current file when no name is given.
+     * A leading ++enc=name (ex. :e ++enc=latin1 old.txt) reads the file with that encoding instead of guessing.
+     * Buffers with unsaved changes are only re-read when forced
+     */
+    fn edit_file(&mut self, arg: &str, force: bool) -> Result<String, String> {
+        let (encoding, file_name) = match arg.strip_prefix("++enc=") {
+            Some(rest) => {
+                let (name, file_name) = rest.split_once(' ').unwrap_or((rest, ""));
+                let encoding =
+                    Encoding::parse(name).ok_or(format!("Error: Unknown encoding: {}", name))?;
+                (Some(encoding), file_name.trim())
+            }
+            None => (None, arg),
+        };
+        let num_buffers = self.buffers.len();
+        let buffer = if file_name.is_empty() {
+            self.win().buffer
         } else {
-            self.msg_display = vec![];
-        }
-        match key_event.code {
-            // Exit to normal mode
-            KeyCode::Esc => {
-                self.mode = Mode::Normal;
-                self.snap_cursor();
-                self.slip_cursor();
+            self.open_buffer(file_name)
+                .map_err(|e| format!("Error: could not open file: {}", e))?
+        };
+
+        let model = &mut self.buffers[buffer];
+        // Buffers already open are only read again when asked to (no file name, or another encoding)
+        let mut read = buffer >= num_buffers && !model.is_listing();
+        let mut warning = None;
+        if file_name.is_empty() || encoding.is_some() {
+            if model.is_listing() {
+                return Err(String::from("Error: Cannot re-read a listing"));
             }
-            // Delete characters
-            KeyCode::Backspace => {
-                if self.get_cursor_file_index() > 0 {
-                    self.cursor_left();
-                    self.delete_char();
-                } else {
-                    self.msg_display = "Error: Start of file reached".chars().collect();
-                }
+            if model.modified && !force {
+                return Err(String::from(
+                    "Error: No write since last change (add ! to discard changes)",
+                ));
             }
-            KeyCode::Delete => self.delete_char(),
-            // Type characters
-            KeyCode::Enter => self.insert_char('\n'),
-            KeyCode::Tab => self.insert_char('\t'),
-            KeyCode::Char(character) => self.insert_char(character),
-            // Move cursor
-            KeyCode::Up => self.cursor_up(),
-            KeyCode::Down => self.cursor_down(),
-            KeyCode::Left => self.cursor_left(),
-            KeyCode::Right => self.cursor_right(),
-            _ => {}
+            warning = model
+                .reload(encoding)
+                .map_err(|e| format!("Error: could not read file: {}", e))?;
+            read = true;
         }
-    }
-    fn delete_char(&mut self) {
-        let file_ind = self.get_cursor_file_index(); // char index of file where character should be deleted
-        if self.model.delete_char(file_ind) {
-            // Re-wrap file content for display
-            self.wrap_text();
+        let mut msg = if read {
+            format!("Read {} as {}", model.file_name, model.file_encoding.name())
         } else {
-            self.msg_display = "Error: End of file reached".chars().collect();
+            format!("Showing {}", model.display_name())
+        };
+        if let Some(warning) = warning {
+            msg = format!("{} ({})", msg, warning);
         }
+        if buffer == self.win().buffer {
+            self.wrap_buffer(buffer);
+            self.win_mut().clamp_cursor(&Mode::Normal);
+        } else {
+            self.show_buffer(buffer);
+        }
+        Ok(msg)
     }
-    fn insert_char(&mut self, c: char) {
-        let file_ind = self.get_cursor_file_index(); // char index of file where character should be inserted
-        self.model.insert_char(c, file_ind);
 
-        // Re-wrap file content for display
+    // Re-read the current directory listing and re-display it
+    fn refresh_listing(&mut self) -> io::Result<()> {
+        let show_hidden = self.show_hidden_files;
+        self.model().refresh_directory(show_hidden)?;
         self.wrap_text();
-        self.cursor_right();
+        let window = self.tabs[self.active_tab].win_mut();
+        window.clamp_cursor(&self.mode);
+        Ok(())
+    }
 
-        // If inserted at end of display line, need to shift cursor right one more time to be right of the new character on the new line
-        if self.cursor_pos.1 == 1 && c != '\n' {
-            self.cursor_right();
+    /*
+     * Handles the key pressed after [g] in Normal Mode
+     */
+    fn goto_handle_key_event(&mut self, key_event: KeyEvent) {
+        let result = match key_event.code {
+            // Toggle hidden files in directory listings
+            KeyCode::Char('h') if self.is_directory_listing() => {
+                self.show_hidden_files = !self.show_hidden_files;
+                self.refresh_listing()
+                    .map_err(|_| "Error: could not read directory")
+            }
+            // Cycle through tab pages
+            KeyCode::Char('t') => self.focus_tab(true),
+            KeyCode::Char('T') => self.focus_tab(false),
+            _ => Ok(()),
+        };
+        if let Err(msg) = result {
+            self.msg_display = msg.chars().collect();
         }
     }
 
-    fn search_input_handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            // Cancel and exit to Normal Mode
-            KeyCode::Esc => {
-                self.msg_display = vec![];
-                self.mode = Mode::Normal;
+    /*
+     * Handles the key pressed after [Ctrl-W] in Normal Mode
+     */
+    fn window_handle_key_event(&mut self, key_event: KeyEvent) {
+        let result = match key_event.code {
+            // Move focus to a neighbouring window
+            KeyCode::Char('h') | KeyCode::Left => self.focus_neighbour(KeyCode::Left),
+            KeyCode::Char('j') | KeyCode::Down => self.focus_neighbour(KeyCode::Down),
+            KeyCode::Char('k') | KeyCode::Up => self.focus_neighbour(KeyCode::Up),
+            KeyCode::Char('l') | KeyCode::Right => self.focus_neighbour(KeyCode::Right),
+            // Cycle focus through every window
+            KeyCode::Char('w') => self.focus_next(true),
+            KeyCode::Char('W') => self.focus_next(false),
+            // Split the current window
+            KeyCode::Char('s') | KeyCode::Char('S') => self.split_window(Direction::Vertical, ""),
+            KeyCode::Char('v') => self.split_window(Direction::Horizontal, ""),
+            // Resize the current window
+            KeyCode::Char('+') => self.resize_window(Direction::Vertical, 1),
+            KeyCode::Char('-') => self.resize_window(Direction::Vertical, -1),
+            KeyCode::Char('>') => self.resize_window(Direction::Horizontal, 1),
+            KeyCode::Char('<') => self.resize_window(Direction::Horizontal, -1),
+            KeyCode::Char('=') => {
+                let area = self.content_area();
+                self.tab_mut().layout.equalize(area);
+                self.resize_windows();
+                Ok(())
             }
-            // Submit search query and see if matches are found
-            KeyCode::Enter => {
-                let search_query: String = self.msg_display[1..].iter().collect();
-                self.match_ranges = self.model.run_search(search_query.as_str());
-                if !self.match_ranges.is_empty() {
-                    // Matches found, update app state so view knows to highlight them
-                    self.wrap_text();
-                    self.search_term = search_query;
-                    self.msg_display = format!(
-                        "{} matches for {}",
-                        self.match_ranges.len(),
-                        &self.search_term
-                    )
-                    .chars()
-                    .collect();
-                } else {
-                    self.msg_display = "Error: No matches found".chars().collect();
-                }
-                self.mode = Mode::Normal;
+            // Close windows
+            KeyCode::Char('c') => self.close_window(),
+            KeyCode::Char('q') => {
+                self.run_command(":q");
+                Ok(())
             }
-            // Delete right-most user input character
-            KeyCode::Backspace => {
-                self.msg_display.pop();
-                // If entire user input deleted, return to normal mode
-                if self.msg_display.is_empty() {
-                    self.mode = Mode::Normal;
-                }
+            KeyCode::Char('o') => {
+                self.only_window();
+                Ok(())
             }
-            // Type into user input
-            KeyCode::Char(character) => self.msg_display.push(character),
-            _ => {}
+            // Rotate the windows in the current row/column
+            KeyCode::Char('r') => self.rotate_windows(true),
+            KeyCode::Char('R') => self.rotate_windows(false),
+            _ => Ok(()),
+        };
+        if let Err(msg) = result {
+            self.msg_display = msg.chars().collect();
         }
     }
 
-    fn scroll_help_up(&mut self) {
-        if self.scroll_help_amount > 0 {
-            self.scroll_help_amount -= 1;
+    /*
+     * Give focus to another window, refreshing search matches for the buffer it shows
+     */
+    fn focus_window(&mut self, id: usize) {
+        let previous_buffer = self.win().buffer;
+        let tab = self.tab_mut();
+        if tab.active_window != id {
+            tab.previous_window = Some(tab.active_window);
         }
-    }
-    fn scroll_help_down(&mut self) {
-        self.scroll_help_amount = cmp::min(MAX_HELP_SCROLL, self.scroll_help_amount + 1);
-    }
-    // Scroll content up, but do not let it scroll out of bounds
-    fn scroll_up(&mut self) -> Result<(), &str> {
-        if self.scroll_amount > 0 {
-            self.scroll_amount -= 1;
-            Ok(())
-        } else {
-            Err("Error: Start of file reached")
+        tab.active_window = id;
+        if self.win().buffer != previous_buffer && self.get_show_highlights() {
+            self.start_search();
+            self.reset_msg_display();
         }
     }
-    // Scroll content down, but do not let it scroll out of bounds
-    fn scroll_down(&mut self) -> Result<(), &str> {
-        let max_scroll_amount = self.display_content.len()
-            - (self.term_bottom_cursor_bound() - self.term_top_cursor_bound() + 1) as usize;
-        if (self.scroll_amount as usize) < max_scroll_amount {
-            self.scroll_amount += 1;
-            Ok(())
+
+    fn focus_next(&mut self, forwards: bool) -> Result<(), &'static str> {
+        let ids = self.tab().layout.window_ids();
+        let pos = ids
+            .iter()
+            .position(|id| *id == self.tab().active_window)
+            .unwrap();
+        let next = if forwards {
+            (pos + 1) % ids.len()
         } else {
-            Err("Error: End of file reached")
+            (pos + ids.len() - 1) % ids.len()
+        };
+        self.focus_window(ids[next]);
+        Ok(())
+    }
+
+    // Focus the window next to the current one in the direction of the given arrow key
+    fn focus_neighbour(&mut self, direction: KeyCode) -> Result<(), &'static str> {
+        let areas = self.tab().layout.areas(self.content_area());
+        let (_, current) = *areas
+            .iter()
+            .find(|(id, _)| *id == self.tab().active_window)
+            .unwrap();
+        let overlaps_rows =
+            |area: &Rect| area.top() < current.bottom() && current.top() < area.bottom();
+        let overlaps_cols =
+            |area: &Rect| area.left() < current.right() && current.left() < area.right();
+        let neighbours: Vec<&(usize, Rect)> = areas
+            .iter()
+            .filter(|(_, area)| match direction {
+                KeyCode::Left => area.right() == current.left() && overlaps_rows(area),
+                KeyCode::Right => area.left() == current.right() && overlaps_rows(area),
+                KeyCode::Up => area.bottom() == current.top() && overlaps_cols(area),
+                KeyCode::Down => area.top() == current.bottom() && overlaps_cols(area),
+                _ => false,
+            })
+            .collect();
+
+        // Prefer the neighbour lined up with the cursor
+        let cursor_pos = self.win().cursor_pos;
+        let (cursor_y, cursor_x) = (current.y + cursor_pos.0, current.x + cursor_pos.1);
+        let neighbour = neighbours
+            .iter()
+            .find(|(_, area)| match direction {
+                KeyCode::Left | KeyCode::Right => {
+                    area.top() <= cursor_y && cursor_y < area.bottom()
+                }
+                _ => area.left() <= cursor_x && cursor_x < area.right(),
+            })
+            .or(neighbours.first());
+        match neighbour {
+            Some((id, _)) => {
+                self.focus_window(*id);
+                Ok(())
+            }
+            None => Err("Error: No window in that direction"),
         }
     }
 
-    fn cursor_up(&mut self) {
-        // Check if there is room to move the cursor upwards
-        if self.cursor_pos.0 > self.term_top_cursor_bound() {
-            // Move up, and adjust cursor to a viable position
-            self.cursor_pos.0 -= 1;
-            self.snap_cursor();
-            self.slip_cursor();
+    /*
+     * Split the current window in two. The new window shows the given file (or the same buffer if empty) and gets focus
+     */
+    fn split_window(&mut self, direction: Direction, file_name: &str) -> Result<(), &'static str> {
+        let buffer = if file_name.is_empty() {
+            self.win().buffer
         } else {
-            // If at top bound, try to scroll content instead of moving cursor
-            if let Err(msg) = self.scroll_up() {
-                self.msg_display = msg.chars().collect();
-            };
+            self.open_buffer(file_name)
+                .map_err(|_| "Error: could not open file")?
+        };
+        self.split_window_with(direction, buffer)
+    }
+
+    // Split the current window in two, with the new window showing the given buffer
+    fn split_window_with(
+        &mut self,
+        direction: Direction,
+        buffer: usize,
+    ) -> Result<(), &'static str> {
+        let areas = self.tab().layout.areas(self.content_area());
+        let (_, area) = *areas
+            .iter()
+            .find(|(id, _)| *id == self.tab().active_window)
+            .unwrap();
+        let (extent, min) = match direction {
+            Direction::Vertical => (area.height, MIN_WINDOW_HEIGHT),
+            Direction::Horizontal => (area.width, MIN_WINDOW_WIDTH),
+        };
+        if extent / 2 < min {
+            return Err("Error: Not enough room");
+        }
+
+        let new_id = self.next_window_id;
+        self.next_window_id += 1;
+        let current = self.win();
+        let mut window = Window::new(
+            new_id,
+            buffer,
+            (area.height, area.width),
+            current.show_line_nums,
+        );
+        // The new window starts with a copy of the current window's location list
+        window.location_list = current.location_list.clone();
+        // Start at the same place when showing the same buffer
+        if buffer == current.buffer {
+            window.scroll_amount = current.scroll_amount;
+            window.cursor_pos = current.cursor_pos;
         }
+        let tab = self.tab_mut();
+        tab.layout
+            .split(tab.active_window, new_id, direction, extent);
+        tab.windows.push(window);
+        self.focus_window(new_id);
+        self.resize_windows();
+        self.start_pending();
+        Ok(())
     }
 
-    fn cursor_down(&mut self) {
-        // Edge case: small file, big terminal. End of file was reached
-        if (self.cursor_pos.0 as usize) == self.display_content.len() {
-            self.msg_display = "Error: End of file reached".chars().collect();
-            return;
+    /*
+     * Find the buffer for the given file, loading it if it hasn't been opened yet
+     */
+    fn open_buffer(&mut self, file_name: &str) -> Result<usize, OpenError> {
+        let file_path = Path::new(file_name);
+
+        // Directories are opened as a listing of their contents
+        if file_path.is_dir() {
+            let dir_name = explorer::display_path(&file_path.canonicalize()?);
+            if let Some(buffer) = self
+                .buffers
+                .iter()
+                .position(|model| model.directory.is_some() && model.file_name == dir_name)
+            {
+                self.buffers[buffer].refresh_directory(self.show_hidden_files)?;
+                return Ok(buffer);
+            }
+            self.buffers.push(EditorModel::from_directory(
+                file_path,
+                self.show_hidden_files,
+            )?);
+            return Ok(self.buffers.len() - 1);
         }
 
-        // Check if there is room to move cursor downwards
-        if self.cursor_pos.0 < self.term_bottom_cursor_bound() {
-            // Move down, and adjust cursor to a viable position
-            self.cursor_pos.0 += 1;
-            self.snap_cursor();
-            self.slip_cursor();
-        } else {
-            // If at bottom bound, try to scroll content instead of moving cursor
-            if let Err(msg) = self.scroll_down() {
-                self.msg_display = msg.chars().collect();
-            };
+        // The same file may be spelled another way (ex. ./src/x.rs, or a relative path from cargo)
+        let canonical = model::canonical_path(file_path);
+        if let Some(buffer) = self
+            .buffers
+            .iter()
+            .position(|model| model.is_file(&canonical))
+        {
+            return Ok(buffer);
+        }
+        // This editor's own swap file means a buffer has the file under a path which no longer leads to it
+        // (ex. its directory was renamed), so that buffer is used rather than opening a rival
+        if let Some(existing) = swap::find_existing(file_path).filter(|existing| existing.is_ours())
+            && let Some(buffer) = self
+                .buffers
+                .iter()
+                .position(|model| model.has_swap(&existing.path))
+        {
+            return Ok(buffer);
         }
+        model::create_if_missing(file_path)?;
+        self.buffers.push(EditorModel::new(file_path, false)?);
+        self.watch_buffer(self.buffers.len() - 1);
+        Ok(self.buffers.len() - 1)
     }
 
-    fn cursor_right(&mut self) {
-        let line = &self.display_content[self.get_cursor_display_row()];
+    /*
+     * Show another buffer in the current window, starting from the top of it
+     */
+    fn show_buffer(&mut self, buffer: usize) {
+        let window = self.win_mut();
+        window.buffer = buffer;
+        window.scroll_amount = 0;
+        window.cursor_pos = (1, 1);
+        self.wrap_text();
+        self.slip_cursor();
+        self.reset_msg_display();
+        self.start_pending();
+    }
 
-        // If cursor will move into the middle of a wide character (ex tab space) 'slip' it rightwards until the next character is valid
-        let invalid_cols = &line.invalid_cols;
-        while invalid_cols.contains(&(self.cursor_pos.1 + 1)) {
-            self.cursor_pos.1 += 1;
+    fn resize_window(&mut self, direction: Direction, delta: i32) -> Result<(), &'static str> {
+        let min = match direction {
+            Direction::Vertical => MIN_WINDOW_HEIGHT,
+            Direction::Horizontal => MIN_WINDOW_WIDTH,
+        };
+        let tab = self.tab_mut();
+        if !tab.layout.resize(tab.active_window, direction, delta, min) {
+            return Err("Error: No window to resize against");
         }
+        self.resize_windows();
+        Ok(())
+    }
 
-        let mut bound = width(&line.line_content);
-        // Allow the cursor to move to the end of the line if in insertion mode
-        if let Mode::Insert = self.mode {
-            bound += 1;
+    fn close_window(&mut self) -> Result<(), &'static str> {
+        if self.tab().windows.len() == 1 {
+            return Err("Error: Cannot close last window");
         }
+        let ids = self.tab().layout.window_ids();
+        let pos = ids
+            .iter()
+            .position(|id| *id == self.tab().active_window)
+            .unwrap();
+        let closing = self.tab().active_window;
+        self.tab_mut().layout.remove(closing);
+        // Focus the window that received the space
+        self.focus_window(if pos > 0 { ids[pos - 1] } else { ids[1] });
+        self.tab_mut().windows.retain(|window| window.id != closing);
+        self.resize_windows();
+        Ok(())
+    }
 
-        // If cursor is at or past the right boundary (end of the line), move to the start of the next line if available
-        if self.cursor_pos.1 as u64 >= bound {
-            // Edge case: small file, big terminal. End of file was reached
-            if (self.get_cursor_display_row() + 1) == self.display_content.len() {
-                self.msg_display = "Error: End of file reached".chars().collect();
-                return;
-            }
+    // Does the current buffer have unsaved changes, and no other window (in any tab page) showing it?
+    fn hides_changes(&self) -> bool {
+        let buffer = self.win().buffer;
+        self.buffers[buffer].modified
+            && self
+                .tabs
+                .iter()
+                .flat_map(|tab| &tab.windows)
+                .filter(|window| window.buffer == buffer)
+                .count()
+                == 1
+    }
 
-            // If scrolling needed, try to do so
-            if self.cursor_pos.0 == self.term_bottom_cursor_bound() {
-                if let Err(msg) = self.scroll_down() {
-                    self.msg_display = msg.chars().collect();
-                    return;
-                };
-            } else {
-                // Only move down if did not scroll
-                self.cursor_pos.0 += 1;
-            }
-            self.cursor_pos.1 = self.term_left_cursor_bound(); // Move to start of next line
-        } else {
-            self.cursor_pos.1 += 1; // Move cursor one step to the right
-        }
+    // Close every window except the current one
+    fn only_window(&mut self) {
+        let tab = self.tab_mut();
+        tab.layout = LayoutNode::Leaf(tab.active_window);
+        let active_window = tab.active_window;
+        tab.windows.retain(|window| window.id == active_window);
+        self.resize_windows();
     }
 
-    fn cursor_left(&mut self) {
-        // If the cursor is at the start of the line, move to the end of the previous line if available
-        if self.cursor_pos.1 == self.term_left_cursor_bound() {
-            // If scrolling needed, try to do so
-            if self.cursor_pos.0 == self.term_top_cursor_bound() {
-                if let Err(msg) = self.scroll_up() {
-                    self.msg_display = msg.chars().collect();
-                    return;
-                };
-            } else {
-                // Only move up if did not scroll
-                self.cursor_pos.0 -= 1;
-            }
-            // Get end of line coordinates
-            let line = &self.display_content[self.get_cursor_display_row()].line_content;
-            let mut bound = width(line);
+    fn rotate_windows(&mut self, forwards: bool) -> Result<(), &'static str> {
+        let tab = self.tab_mut();
+        tab.layout.rotate(tab.active_window, forwards)?;
+        self.resize_windows();
+        Ok(())
+    }
 
-            // Allow the cursor to move one space further if in insertion mode
-            if let Mode::Insert = self.mode {
-                bound += 1;
-            }
+    /*
+     * Open a new tab page with a single window showing the given file (or the current buffer if empty)
+     */
+    fn new_tab(&mut self, file_name: &str) -> Result<(), &'static str> {
+        let buffer = if file_name.is_empty() {
+            self.win().buffer
+        } else {
+            self.open_buffer(file_name)
+                .map_err(|_| "Error: could not open file")?
+        };
+        let window = Window::new(
+            self.next_window_id,
+            buffer,
+            self.term_size,
+            self.win().show_line_nums,
+        );
+        self.next_window_id += 1;
+        self.tabs.insert(self.active_tab + 1, TabPage::new(window));
+        self.switch_tab(self.active_tab + 1);
+        self.start_pending();
+        Ok(())
+    }
+
+    fn close_tab(&mut self) -> Result<(), &'static str> {
+        if self.tabs.len() == 1 {
+            return Err("Error: Cannot close last tab page");
+        }
+        self.tabs.remove(self.active_tab);
+        self.switch_tab(self.active_tab.saturating_sub(1));
+        Ok(())
+    }
 
-            self.cursor_pos.1 = bound as u16; // Move to end of prev line
+    fn focus_tab(&mut self, forwards: bool) -> Result<(), &'static str> {
+        if self.tabs.len() == 1 {
+            return Err("Error: No other tab pages");
+        }
+        let next = if forwards {
+            (self.active_tab + 1) % self.tabs.len()
         } else {
-            self.cursor_pos.1 -= 1; // Move cursor one step to the left
+            (self.active_tab + self.tabs.len() - 1) % self.tabs.len()
+        };
+        self.switch_tab(next);
+        Ok(())
+    }
+
+    /*
+     * Show another tab page. Its windows are re-fitted since the terminal size
+     * (or the buffers they show) may have changed while it was hidden
+     */
+    fn switch_tab(&mut self, tab: usize) {
+        self.active_tab = tab;
+        if self.get_show_highlights() {
+            self.update_matches();
         }
-        self.slip_cursor();
+        self.resize_windows();
+        self.reset_msg_display();
     }
 
-    // Snap cursor to end of line after moving up/down into a shorter line of text
-    fn snap_cursor(&mut self) {
-        // Get end of line coordinates
-        let line = &self.display_content[self.get_cursor_display_row()].line_content;
-        let mut bound = cmp::max(width(line), 1);
+    /*
+     * Describe the open buffers, options and window layouts so they can be restored later.
+     * Quickfix and location list windows are left out, since the lists themselves aren't saved, as are unnamed buffers
+     */
+    fn make_session(&self) -> Result<Session, String> {
+        let directory = env::current_dir()
+            .map_err(|e| format!("Error: could not read working directory: {}", e))?;
+        // Unnamed buffers have no file to reopen either
+        let is_list = |window: &Window| {
+            let model = &self.buffers[window.buffer];
+            model.list.is_some() || model.file_name.is_empty()
+        };
 
-        // Allow the cursor to move to the end of the line if in insertion mode
-        if let Mode::Insert = self.mode
-            && !line.is_empty()
-        {
-            bound += 1;
+        let mut tabs = vec![];
+        let mut active_tab = 0;
+        for (tab_idx, tab) in self.tabs.iter().enumerate() {
+            let mut layout = tab.layout.clone();
+            for window in tab.windows.iter().filter(|window| is_list(window)) {
+                layout.remove(window.id);
+            }
+            let ids = layout.window_ids();
+            let windows: Vec<&Window> = ids
+                .iter()
+                .filter_map(|id| tab.windows.iter().find(|window| window.id == *id))
+                .collect();
+            // Tab page only showing a list
+            if windows.iter().any(|window| is_list(window)) {
+                continue;
+            }
+            layout.map_ids(&|id| ids.iter().position(|other| *other == id).unwrap());
+            if tab_idx <= self.active_tab {
+                active_tab = tabs.len();
+            }
+            tabs.push(TabState {
+                windows: windows
+                    .iter()
+                    .map(|window| self.window_state(window))
+                    .collect(),
+                layout,
+                active_window: ids
+                    .iter()
+                    .position(|id| *id == tab.active_window)
+                    .unwrap_or(0),
+            });
         }
+        if tabs.is_empty() {
+            return Err(String::from("Error: No windows to save"));
+        }
+
+        Ok(Session {
+            directory,
+            options: self.option_settings(),
+            buffers: self
+                .buffers
+                .iter()
+                .filter(|model| model.list.is_none() && !model.file_name.is_empty())
+                .map(|model| model.file_name.clone())
+                .collect(),
+            tabs,
+            active_tab,
+        })
+    }
 
-        // Snap cursor to end of line after moving up
-        let line_len = bound as u16;
-        if line_len < self.cursor_pos.1 {
-            self.cursor_pos.1 = line_len;
+    fn window_state(&self, window: &Window) -> WindowState {
+        let rope = &self.buffers[window.buffer].rope;
+        // Outside of insertion mode the cursor's file index counts the character under it
+        let index = cmp::min(
+            window
+                .get_cursor_file_index(&Mode::Normal)
+                .saturating_sub(1),
+            rope.len_chars(),
+        );
+        let line = rope.char_to_line(index);
+        WindowState {
+            file_name: self.buffers[window.buffer].file_name.clone(),
+            line: line + 1,
+            col: index - rope.line_to_char(line) + 1,
+            top_line: window
+                .display_content
+                .display_line(window.scroll_amount, &[])
+                .line_num,
+            show_line_nums: window.show_line_nums,
         }
     }
 
-    // If cursor just moved into the middle of a wide character (ex tab space) 'slip' it leftwards to valid space
-    // Also used to keep the cursor out of the line numbers
-    fn slip_cursor(&mut self) {
-        let invalid_cols = &self.display_content[self.get_cursor_display_row()].invalid_cols;
-        while invalid_cols.contains(&self.cursor_pos.1) {
-            self.cursor_pos.1 -= 1;
+    /*
+     * Replace the tab pages and windows with those of a saved session, opening its buffers and applying its options.
+     * Anything which couldn't be restored is reported in the message bar
+     */
+    pub fn restore_session(&mut self, session: &Session) {
+        let mut errors = vec![];
+        for option in &session.options {
+            if let Err(msg) = self.set_option(option) {
+                errors.push(msg);
+            }
+        }
+        for file_name in &session.buffers {
+            if self.open_buffer(file_name).is_err() {
+                errors.push(format!("Error: could not open {}", file_name));
+            }
         }
 
-        // Ensure cursor shifts out of the line number region
-        while self.cursor_pos.1 < self.term_left_cursor_bound() {
-            self.cursor_pos.1 += 1;
+        let mut tabs = vec![];
+        for tab_state in &session.tabs {
+            let first_id = self.next_window_id;
+            self.next_window_id += tab_state.windows.len();
+            let mut windows = vec![];
+            for (idx, window_state) in tab_state.windows.iter().enumerate() {
+                // Windows whose file can't be opened show the first buffer instead
+                let buffer = self
+                    .open_buffer(window_state.file_name.as_str())
+                    .unwrap_or_else(|_| {
+                        errors.push(format!("Error: could not open {}", window_state.file_name));
+                        0
+                    });
+                windows.push(Window::new(
+                    first_id + idx,
+                    buffer,
+                    self.term_size,
+                    window_state.show_line_nums,
+                ));
+            }
+            let mut layout = tab_state.layout.clone();
+            layout.map_ids(&|idx| first_id + idx);
+            tabs.push(TabPage {
+                windows,
+                layout,
+                active_window: first_id + tab_state.active_window,
+                previous_window: None,
+            });
+        }
+        self.tabs = tabs;
+
+        // Fit each tab page's windows to the terminal, then put their cursors back
+        for (tab, tab_state) in session.tabs.iter().enumerate() {
+            self.switch_tab(tab);
+            for (window, window_state) in self.tabs[tab].windows.iter_mut().zip(&tab_state.windows)
+            {
+                let visible_rows =
+                    (window.bottom_cursor_bound() - window.top_cursor_bound() + 1) as usize;
+                let max_scroll = window.num_rows().saturating_sub(visible_rows);
+                let top_row = window.display_content.row_of_line(window_state.top_line);
+                window.scroll_amount = cmp::min(top_row, max_scroll);
+                let index = self.buffers[window.buffer]
+                    .line_col_to_index(window_state.line, window_state.col);
+                window.move_cursor_to_index(index, &Mode::Normal);
+            }
+        }
+        self.switch_tab(session.active_tab);
+        if let Some(msg) = errors.first() {
+            self.msg_display = msg.chars().collect();
         }
     }
 }
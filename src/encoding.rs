@@ -0,0 +1,326 @@
+/*
+ * Character encodings files can be read and written in. Buffers always hold UTF-8,
+ * and files are converted back to their own encoding when written
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf8Bom, // UTF-8 starting with a byte order mark
+    Utf16Le,
+    Utf16LeBom, // UTF-16 (little endian) starting with a byte order mark
+    Utf16Be,
+    Utf16BeBom, // UTF-16 (big endian) starting with a byte order mark, like Vim's "utf-16"
+    Latin1,     // ISO-8859-1, one byte per character
+    Windows1252,
+}
+
+// Characters for bytes 0x80 to 0x9F in Windows-1252, where Latin-1 has control characters.
+// The five unused bytes are read as the matching control characters, like Windows does
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16_BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+impl Encoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-8-bom" | "utf8-bom" => Some(Encoding::Utf8Bom),
+            "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16le-bom" | "utf16le-bom" => Some(Encoding::Utf16LeBom),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "utf-16be-bom" | "utf16be-bom" | "utf-16" | "utf16" => Some(Encoding::Utf16BeBom),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16LeBom => "utf-16le-bom",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Utf16BeBom => "utf-16be-bom",
+            Encoding::Latin1 => "latin1",
+            Encoding::Windows1252 => "cp1252",
+        }
+    }
+
+    /*
+     * Guess a file's encoding: from its byte order mark if it has one, then UTF-16 if it looks like it,
+     * then UTF-8 if it is valid, falling back to Windows-1252. Every file can be read as Windows-1252,
+     * so guessing never fails. Files with a byte order mark get one back when written, and others don't
+     */
+    pub fn detect(contents: &[u8]) -> Self {
+        if contents.starts_with(UTF8_BOM) {
+            return Encoding::Utf8Bom;
+        }
+        if contents.starts_with(UTF16_LE_BOM) {
+            return Encoding::Utf16LeBom;
+        }
+        if contents.starts_with(UTF16_BE_BOM) {
+            return Encoding::Utf16BeBom;
+        }
+        // Characters up to U+00FF (ASCII and most European letters) have a zero high byte in UTF-16,
+        // so text mostly made of them has zeros on one side only. Other encodings only have zeros for NUL
+        if contents.len() >= 2 && contents.len().is_multiple_of(2) {
+            let zeros = |offset: usize| {
+                contents[offset..]
+                    .iter()
+                    .step_by(2)
+                    .filter(|byte| **byte == 0)
+                    .count()
+            };
+            let (even_zeros, odd_zeros) = (zeros(0), zeros(1));
+            let units = contents.len() / 2;
+            for (encoding, high_zeros, low_zeros) in [
+                (Encoding::Utf16Le, odd_zeros, even_zeros),
+                (Encoding::Utf16Be, even_zeros, odd_zeros),
+            ] {
+                if high_zeros * 2 >= units
+                    && low_zeros * 8 <= high_zeros
+                    && encoding.decode_part(contents).is_ok()
+                {
+                    return encoding;
+                }
+            }
+        }
+        if str::from_utf8(contents).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+
+    /*
+     * Read a file's contents as this encoding, skipping its byte order mark (even if the encoding doesn't write one)
+     */
+    pub fn decode(&self, contents: &[u8]) -> Result<String, String> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                let contents = contents.strip_prefix(UTF8_BOM).unwrap_or(contents);
+                String::from_utf8(contents.to_vec())
+                    .map_err(|e| format!("not valid utf-8: {}", e.utf8_error()))
+            }
+            Encoding::Utf16Le | Encoding::Utf16LeBom => {
+                let contents = contents.strip_prefix(UTF16_LE_BOM).unwrap_or(contents);
+                self.decode_utf16(contents)
+            }
+            Encoding::Utf16Be | Encoding::Utf16BeBom => {
+                let contents = contents.strip_prefix(UTF16_BE_BOM).unwrap_or(contents);
+                self.decode_utf16(contents)
+            }
+            Encoding::Latin1 => Ok(contents.iter().map(|byte| *byte as char).collect()),
+            Encoding::Windows1252 => Ok(contents
+                .iter()
+                .map(|byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+                    _ => *byte as char,
+                })
+                .collect()),
+        }
+    }
+
+    // Byte order mark the file starts with, if the encoding has one
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => UTF8_BOM,
+            Encoding::Utf16LeBom => UTF16_LE_BOM,
+            Encoding::Utf16BeBom => UTF16_BE_BOM,
+            _ => &[],
+        }
+    }
+
+    /*
+     * Read part of a file as this encoding, returning the text and the number of bytes it used.
+     * Bytes at the end which may be the start of a character continued in the next part are left unused.
+     * Byte order marks are not skipped, as the part may not be the start of the file
+     */
+    pub fn decode_part(&self, contents: &[u8]) -> Result<(String, usize), String> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => {
+                let used = match str::from_utf8(contents) {
+                    Ok(_) => contents.len(),
+                    Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                    // Offsets into the part would be misleading, so suggest reading the file another way instead
+                    Err(_) => return Err(String::from("not valid utf-8 (try :e ++enc=cp1252)")),
+                };
+                // Checked above, so this never fails
+                let text = str::from_utf8(&contents[..used]).unwrap_or_default();
+                Ok((String::from(text), used))
+            }
+            Encoding::Utf16Le | Encoding::Utf16LeBom | Encoding::Utf16Be | Encoding::Utf16BeBom => {
+                let mut used = contents.len() - contents.len() % 2;
+                // A high surrogate needs the low surrogate following it
+                let last_unit = if self.little_endian() {
+                    contents.get(used.wrapping_sub(1))
+                } else {
+                    contents.get(used.wrapping_sub(2))
+                };
+                if last_unit.is_some_and(|byte| (0xD8..=0xDB).contains(byte)) {
+                    used -= 2;
+                }
+                Ok((self.decode_utf16(&contents[..used])?, used))
+            }
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                Ok((self.decode(contents)?, contents.len()))
+            }
+        }
+    }
+
+    /*
+     * Convert text to this encoding for writing, failing on characters it has no way to store
+     */
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Utf8Bom => Ok([UTF8_BOM, text.as_bytes()].concat()),
+            Encoding::Utf16Le | Encoding::Utf16LeBom => Ok(self
+                .bom()
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect()),
+            Encoding::Utf16Be | Encoding::Utf16BeBom => Ok(self
+                .bom()
+                .iter()
+                .copied()
+                .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                .collect()),
+            Encoding::Latin1 | Encoding::Windows1252 => text
+                .chars()
+                .map(|ch| {
+                    let byte = match self {
+                        Encoding::Windows1252 => WINDOWS_1252_HIGH
+                            .iter()
+                            .position(|high| *high == ch)
+                            .map(|idx| 0x80 + idx as u32)
+                            .or_else(|| {
+                                let code = ch as u32;
+                                (code < 0x80 || (0xA0..=0xFF).contains(&code)).then_some(code)
+                            }),
+                        _ => Some(ch as u32).filter(|code| *code <= 0xFF),
+                    };
+                    byte.map(|byte| byte as u8).ok_or(format!(
+                        "'{}' cannot be written as {}",
+                        ch,
+                        self.name()
+                    ))
+                })
+                .collect(),
+        }
+    }
+
+    fn little_endian(&self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16LeBom)
+    }
+
+    fn decode_utf16(&self, contents: &[u8]) -> Result<String, String> {
+        if !contents.len().is_multiple_of(2) {
+            return Err(format!("not valid {}: odd number of bytes", self.name()));
+        }
+        let units = contents.chunks_exact(2).map(|pair| {
+            let pair = [pair[0], pair[1]];
+            if self.little_endian() {
+                u16::from_le_bytes(pair)
+            } else {
+                u16::from_be_bytes(pair)
+            }
+        });
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|e| format!("not valid {}: {}", self.name(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(Encoding::detect(b"\xEF\xBB\xBFabc"), Encoding::Utf8Bom);
+        assert_eq!(
+            Encoding::detect(&[UTF16_LE_BOM, &utf16le("abc")].concat()),
+            Encoding::Utf16LeBom
+        );
+        assert_eq!(
+            Encoding::detect(&[UTF16_BE_BOM, &utf16be("abc")].concat()),
+            Encoding::Utf16BeBom
+        );
+    }
+
+    #[test]
+    fn detects_utf16_without_byte_order_mark() {
+        assert_eq!(
+            Encoding::detect(&utf16le("plain text\n")),
+            Encoding::Utf16Le
+        );
+        assert_eq!(
+            Encoding::detect(&utf16be("plain text\n")),
+            Encoding::Utf16Be
+        );
+        // Characters past U+00FF have no zero byte, but most of the text still does
+        let text = "Preis: 10 € – 20 € ≈ günstig\n";
+        assert_eq!(Encoding::detect(&utf16le(text)), Encoding::Utf16Le);
+        assert_eq!(Encoding::detect(&utf16be(text)), Encoding::Utf16Be);
+        // Zero bytes on both sides aren't text
+        assert_eq!(Encoding::detect(&[0, 0, 0, 0, b'a', 0]), Encoding::Utf8);
+        assert_eq!(Encoding::detect("plain text\n".as_bytes()), Encoding::Utf8);
+    }
+
+    #[test]
+    fn writes_byte_order_mark_back_as_read() {
+        for (contents, encoding) in [
+            (
+                [UTF16_LE_BOM, &utf16le("a€\n")].concat(),
+                Encoding::Utf16LeBom,
+            ),
+            (
+                [UTF16_BE_BOM, &utf16be("a€\n")].concat(),
+                Encoding::Utf16BeBom,
+            ),
+            (utf16le("a€\n"), Encoding::Utf16Le),
+            (utf16be("a€\n"), Encoding::Utf16Be),
+            (b"\xEF\xBB\xBFa\xE2\x82\xAC\n".to_vec(), Encoding::Utf8Bom),
+        ] {
+            assert_eq!(Encoding::detect(&contents), encoding);
+            let text = encoding.decode(&contents).unwrap();
+            assert_eq!(text, "a€\n");
+            assert_eq!(encoding.encode(&text).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn parses_names() {
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf8Bom,
+            Encoding::Utf16Le,
+            Encoding::Utf16LeBom,
+            Encoding::Utf16Be,
+            Encoding::Utf16BeBom,
+            Encoding::Latin1,
+            Encoding::Windows1252,
+        ] {
+            assert_eq!(Encoding::parse(encoding.name()), Some(encoding));
+        }
+        assert_eq!(Encoding::parse("utf-16"), Some(Encoding::Utf16BeBom));
+    }
+}
@@ -0,0 +1,89 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/*
+ * Contents of a directory shown in a buffer, netrw style:
+ * a header line with the directory path, "../", then sub-directories and files
+ */
+#[derive(Debug)]
+pub struct DirectoryListing {
+    pub path: PathBuf,
+    pub entries: Vec<PathBuf>, // Sub-directories first, then files, each sorted by name
+}
+
+// Number of lines shown before the first entry (header and "../")
+const HEADER_LINES: usize = 2;
+
+impl DirectoryListing {
+    pub fn read(path: &Path, show_hidden: bool) -> io::Result<Self> {
+        let path = path.canonicalize()?;
+        let mut dirs = vec![];
+        let mut files = vec![];
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if hidden && !show_hidden {
+                continue;
+            }
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+        dirs.sort();
+        files.sort();
+        dirs.append(&mut files);
+        Ok(Self {
+            path,
+            entries: dirs,
+        })
+    }
+
+    /*
+     * Text shown in the buffer for this listing
+     */
+    pub fn to_text(&self) -> String {
+        let mut text = format!("\" {}\n../", self.path.display());
+        for entry in self.entries.iter() {
+            text.push('\n');
+            text.push_str(&entry.file_name().unwrap_or_default().to_string_lossy());
+            if entry.is_dir() {
+                text.push('/');
+            }
+        }
+        text
+    }
+
+    /*
+     * Path of the entry shown on the given (1-indexed) line of the listing
+     */
+    pub fn entry_at_line(&self, line_num: usize) -> Option<PathBuf> {
+        match line_num {
+            0 | 1 => None,
+            2 => Some(self.parent()),
+            _ => self.entries.get(line_num - HEADER_LINES - 1).cloned(),
+        }
+    }
+
+    pub fn parent(&self) -> PathBuf {
+        self.path.parent().unwrap_or(&self.path).to_path_buf()
+    }
+}
+
+/*
+ * Shortest way to show a path: relative to the working directory if it is inside it
+ */
+pub fn display_path(path: &Path) -> String {
+    if let Ok(current_dir) = env::current_dir()
+        && let Ok(relative) = path.strip_prefix(current_dir)
+    {
+        if relative.as_os_str().is_empty() {
+            return String::from(".");
+        }
+        return relative.to_string_lossy().into_owned();
+    }
+    path.to_string_lossy().into_owned()
+}
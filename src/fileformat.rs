@@ -0,0 +1,75 @@
+/*
+ * Line endings used by a file. Buffers always hold plain "\n" line endings,
+ * and the file's own endings are put back when it is written
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FileFormat {
+    #[default]
+    Unix, // "\n"
+    Dos, // "\r\n"
+    Mac, // "\r" (classic Mac OS)
+}
+
+impl FileFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            "mac" => Some(FileFormat::Mac),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        }
+    }
+
+    /*
+     * Guess a file's format from the line endings it uses most.
+     * Files without any line endings are treated as unix
+     */
+    pub fn detect(text: &str) -> Self {
+        let bytes = text.as_bytes();
+        let (mut num_lf, mut num_crlf, mut num_cr) = (0, 0, 0);
+        for (idx, byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' if idx > 0 && bytes[idx - 1] == b'\r' => num_crlf += 1,
+                b'\n' => num_lf += 1,
+                b'\r' if bytes.get(idx + 1) != Some(&b'\n') => num_cr += 1,
+                _ => {}
+            }
+        }
+        if num_crlf > 0 && num_crlf >= num_lf && num_crlf >= num_cr {
+            FileFormat::Dos
+        } else if num_cr > num_lf {
+            FileFormat::Mac
+        } else {
+            FileFormat::Unix
+        }
+    }
+
+    /*
+     * Convert a file's text to the "\n" line endings used by buffers.
+     * Files mixing line endings have them all converted, so they are written back with the format's endings
+     */
+    pub fn normalize(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n");
+        match self {
+            FileFormat::Mac => text.replace('\r', "\n"),
+            _ => text,
+        }
+    }
+
+    // Convert a buffer's text back to the format's line endings, for writing
+    pub fn apply(&self, text: &str) -> String {
+        match self {
+            FileFormat::Unix => String::from(text),
+            FileFormat::Dos => text.replace('\n', "\r\n"),
+            FileFormat::Mac => text.replace('\n', "\r"),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::fs::{self, File, OpenOptions};
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+/*
+ * What a file looked like when it was last read or written, used to notice other programs changing it
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    // State of the file at path, which was just read or written with the given contents
+    pub fn new(path: &Path, contents: &[u8]) -> Option<Self> {
+        Self::with_hash(path, hash(contents))
+    }
+
+    // State of the file at path, which was just read in parts, with the hash of its contents worked out along the way
+    pub fn with_hash(path: &Path, hash: u64) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash,
+        })
+    }
+
+    pub fn read(path: &Path) -> Option<Self> {
+        Self::new(path, &fs::read(path).ok()?)
+    }
+
+    /*
+     * Has the file's content changed since this state was recorded?
+     * The content is only compared when the modification time or size differs, and deleted files don't count
+     */
+    pub fn changed(&self, path: &Path) -> bool {
+        let Ok(metadata) = fs::metadata(path) else {
+            return false;
+        };
+        if metadata.modified().ok() == self.modified && metadata.len() == self.len {
+            return false;
+        }
+        fs::read(path).is_ok_and(|contents| hash(&contents) != self.hash)
+    }
+}
+
+// Files read in parts get the same hash by writing each part to a DefaultHasher in turn
+fn hash(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(contents);
+    hasher.finish()
+}
+
+/*
+ * Safely replace a file's contents, so a crash or full disk part way through never leaves it half written.
+ * The contents go to a temporary file beside it, which is flushed to disk and then renamed over the original,
+ * keeping its permissions and (where allowed) its owner. Symlinks are followed, so the link itself stays in place.
+ * Renaming would split a hard-linked file from its other names, so those are instead backed up to a temporary
+ * copy and overwritten in place (like Vim's backupcopy)
+ */
+pub fn write_atomic(path: &Path, contents: &[u8], overwrite_read_only: bool) -> io::Result<()> {
+    let target = resolve_symlinks(path);
+    let metadata = fs::metadata(&target).ok();
+    // Renaming would get around the file's own permissions, which plain writes respect.
+    // Forced writes instead make the file writable for the moment, which only its owner can do
+    if let Some(metadata) = &metadata
+        && !is_writable(&target)
+    {
+        if !overwrite_read_only {
+            return Err(not_writable());
+        }
+        return write_read_only(&target, contents, metadata);
+    }
+    if metadata.as_ref().is_some_and(is_hard_linked) {
+        return write_in_place(&target, contents);
+    }
+
+    let temp_path = temp_path(&target)?;
+    let mut temp_file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+    {
+        Ok(file) => file,
+        // Directory can't be written to, but the file itself may still be
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            return write_in_place(&target, contents);
+        }
+        Err(e) => return Err(e),
+    };
+    let result = (|| {
+        temp_file.write_all(contents)?;
+        if let Some(metadata) = &metadata {
+            temp_file.set_permissions(metadata.permissions())?;
+            copy_owner(&temp_file, metadata);
+        }
+        temp_file.sync_all()?;
+        fs::rename(&temp_path, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    sync_parent(&target);
+    Ok(())
+}
+
+/*
+ * Can the file be opened for writing? Unlike its permission bits, this also checks who owns it
+ * (ex. a root-owned file with mode 0644 isn't writable for anyone else), and whether its file system is mounted read-only.
+ * Opening without truncating changes nothing
+ */
+pub fn is_writable(path: &Path) -> bool {
+    !matches!(
+        OpenOptions::new().write(true).open(path),
+        Err(e) if matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+        )
+    )
+}
+
+fn not_writable() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "file is not writable")
+}
+
+/*
+ * Overwrite a file without write permission by allowing its owner to write it until the write is done.
+ * Only the owner (or root) may change its permissions, so files belonging to others still can't be written
+ */
+fn write_read_only(target: &Path, contents: &[u8], metadata: &fs::Metadata) -> io::Result<()> {
+    let permissions = metadata.permissions();
+    fs::set_permissions(target, owner_writable(permissions.clone())).map_err(|e| {
+        if e.kind() == io::ErrorKind::PermissionDenied {
+            not_writable()
+        } else {
+            e
+        }
+    })?;
+    let result = write_in_place(target, contents);
+    let _ = fs::set_permissions(target, permissions);
+    result
+}
+
+#[cfg(unix)]
+fn owner_writable(mut permissions: fs::Permissions) -> fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.set_mode(permissions.mode() | 0o200);
+    permissions
+}
+
+#[cfg(not(unix))]
+fn owner_writable(mut permissions: fs::Permissions) -> fs::Permissions {
+    permissions.set_readonly(false);
+    permissions
+}
+
+/*
+ * Path a symlink (or chain of them) finally points to. Links to missing files are written through
+ */
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut target = path.to_path_buf();
+    // Give up on cycles rather than following them forever
+    for _ in 0..40 {
+        let Ok(link) = fs::read_link(&target) else {
+            break;
+        };
+        target = match target.parent() {
+            Some(parent) => parent.join(link),
+            None => link,
+        };
+    }
+    target
+}
+
+// Temporary file in the same directory (and so the same file system) as the target, so it can be renamed over it
+fn temp_path(target: &Path) -> io::Result<PathBuf> {
+    let file_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::other("not a file name"))?;
+    Ok(target.with_file_name(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    )))
+}
+
+/*
+ * Overwrite a file without replacing it, keeping a temporary copy of its original contents until the write completes.
+ * Used when the file can't be replaced by renaming. If the write fails part way, the error says where the copy is
+ */
+fn write_in_place(target: &Path, contents: &[u8]) -> io::Result<()> {
+    // The copy can't always be made (ex. the directory isn't writable), and the write goes ahead without it
+    let backup_path = temp_path(target)
+        .ok()
+        .filter(|_| target.is_file())
+        .filter(|backup_path| fs::copy(target, backup_path).is_ok());
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(target)
+    {
+        Ok(file) => file,
+        // Nothing was changed, so the copy isn't needed
+        Err(e) => {
+            if let Some(backup_path) = backup_path {
+                let _ = fs::remove_file(backup_path);
+            }
+            return Err(e);
+        }
+    };
+    let result = file.write_all(contents).and_then(|_| file.sync_all());
+    match (result, backup_path) {
+        (Ok(()), Some(backup_path)) => {
+            let _ = fs::remove_file(backup_path);
+            Ok(())
+        }
+        (Ok(()), None) => Ok(()),
+        (Err(e), Some(backup_path)) => Err(io::Error::new(
+            e.kind(),
+            format!(
+                "{} (original contents kept in {})",
+                e,
+                backup_path.display()
+            ),
+        )),
+        (Err(e), None) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn is_hard_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn is_hard_linked(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+// Give the new file the original's owner and group. Only possible for root (or the group for its members)
+#[cfg(unix)]
+fn copy_owner(file: &File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{MetadataExt, fchown};
+    if fchown(file, Some(metadata.uid()), Some(metadata.gid())).is_err() {
+        let _ = fchown(file, None, Some(metadata.gid()));
+    }
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_file: &File, _metadata: &fs::Metadata) {}
+
+// Make the rename itself durable by flushing the directory entry
+fn sync_parent(target: &Path) {
+    if let Some(parent) = target.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+}
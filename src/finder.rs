@@ -0,0 +1,137 @@
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use ignore::WalkBuilder;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+// Number of lines of the highlighted file shown in the preview
+const PREVIEW_LINES: usize = 200;
+
+/*
+ * State of the fuzzy file finder popup: every file in the project,
+ * the ones matching the query (best match first), and a preview of the highlighted one
+ */
+#[derive(Debug)]
+pub struct FileFinder {
+    pub query: String,
+    paths: Vec<String>,       // Every file under the project root, relative to it
+    pub matches: Vec<String>, // Paths matching the query, sorted by fuzzy score
+    pub selected: usize,      // Index of the highlighted match
+    pub preview: Vec<String>, // First lines of the highlighted file
+}
+
+impl FileFinder {
+    /*
+     * Collect every file under the given directory, skipping anything ignored by .gitignore files
+     */
+    pub fn new(root: &Path) -> Self {
+        let mut paths = vec![];
+        for entry in WalkBuilder::new(root).build().flatten() {
+            if entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                let path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                paths.push(path.to_string_lossy().into_owned());
+            }
+        }
+        paths.sort();
+
+        let mut finder = Self {
+            query: String::new(),
+            paths,
+            matches: vec![],
+            selected: 0,
+            preview: vec![],
+        };
+        finder.update_matches();
+        finder
+    }
+
+    // Number of files the query is matched against
+    pub fn num_files(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn push_char(&mut self, ch: char) {
+        self.query.push(ch);
+        self.update_matches();
+    }
+
+    pub fn pop_char(&mut self) {
+        self.query.pop();
+        self.update_matches();
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+            self.load_preview();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            self.load_preview();
+        }
+    }
+
+    pub fn selected_path(&self) -> Option<&str> {
+        self.matches.get(self.selected).map(|path| path.as_str())
+    }
+
+    /*
+     * Re-rank every path against the query. Higher scores first, shorter paths break ties
+     */
+    fn update_matches(&mut self) {
+        let matcher = SkimMatcherV2::default();
+        let mut scored: Vec<(i64, &String)> = self
+            .paths
+            .iter()
+            .filter_map(|path| {
+                matcher
+                    .fuzzy_match(path, &self.query)
+                    .map(|score| (score, path))
+            })
+            .collect();
+        scored.sort_by(|(score_a, path_a), (score_b, path_b)| {
+            score_b
+                .cmp(score_a)
+                .then(path_a.len().cmp(&path_b.len()))
+                .then(path_a.cmp(path_b))
+        });
+        self.matches = scored.into_iter().map(|(_, path)| path.clone()).collect();
+        self.selected = 0;
+        self.load_preview();
+    }
+
+    fn load_preview(&mut self) {
+        self.preview = match self.selected_path() {
+            Some(path) => read_preview(Path::new(path)),
+            None => vec![],
+        };
+    }
+}
+
+/*
+ * First lines of a file, or a short note if it can't be shown as text
+ */
+fn read_preview(path: &Path) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return vec![String::from("[Cannot read file]")];
+    };
+    let mut lines = vec![];
+    for line in BufReader::new(file).split(b'\n').take(PREVIEW_LINES) {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.contains(&0) {
+            return vec![String::from("[Binary file]")];
+        }
+        // Tabs would otherwise be drawn as a single column
+        lines.push(String::from_utf8_lossy(&line).replace('\t', "    "));
+    }
+    lines
+}
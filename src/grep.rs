@@ -0,0 +1,166 @@
+use crate::pattern::Pattern;
+use crate::quickfix::Location;
+use ignore::{WalkBuilder, WalkState};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// Longest piece of a matched line kept for display
+const MAX_TEXT_LEN: usize = 200;
+// The search stops once this many matches were found
+pub const MAX_RESULTS: usize = 10_000;
+
+/*
+ * A project-wide search running on background threads.
+ * Matches are sent back as they are found, so results can be shown before the search finishes
+ */
+#[derive(Debug)]
+pub struct GrepJob {
+    receiver: Receiver<Location>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl GrepJob {
+    /*
+     * Start searching every text file under root (skipping files ignored by .gitignore) for the pattern
+     */
+    pub fn spawn(re: Pattern, root: &Path) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let walker = WalkBuilder::new(root).build_parallel();
+        let job_cancelled = Arc::clone(&cancelled);
+
+        thread::spawn(move || {
+            walker.run(|| {
+                let re = re.clone();
+                let sender = sender.clone();
+                let cancelled = Arc::clone(&job_cancelled);
+                Box::new(move |entry| {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return WalkState::Quit;
+                    }
+                    let Ok(entry) = entry else {
+                        return WalkState::Continue;
+                    };
+                    if !entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file())
+                    {
+                        return WalkState::Continue;
+                    }
+                    // Paths stay openable from the working directory, minus a leading "./"
+                    let path = entry.path().strip_prefix(".").unwrap_or(entry.path());
+                    for location in search_file(&re, entry.path(), &path.to_string_lossy()) {
+                        // Receiver is gone once the results are no longer wanted
+                        if sender.send(location).is_err() {
+                            return WalkState::Quit;
+                        }
+                    }
+                    WalkState::Continue
+                })
+            });
+        });
+
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    /*
+     * Collect the matches found since the last call.
+     * The second value is false once the search has finished
+     */
+    pub fn poll(&self) -> (Vec<Location>, bool) {
+        let mut locations = vec![];
+        loop {
+            match self.receiver.try_recv() {
+                Ok(location) => locations.push(location),
+                Err(TryRecvError::Empty) => return (locations, true),
+                Err(TryRecvError::Disconnected) => return (locations, false),
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/*
+ * Split the arguments of :grep into the pattern and the path to search.
+ * The pattern may be quoted to include spaces
+ */
+pub fn split_args(args: &str) -> (&str, &str) {
+    for quote in ['"', '\''] {
+        if let Some(rest) = args.strip_prefix(quote)
+            && let Some((pattern, path)) = rest.split_once(quote)
+        {
+            return (pattern, path.trim());
+        }
+    }
+    match args.split_once(' ') {
+        Some((pattern, path)) => (pattern, path.trim()),
+        None => (args, ""),
+    }
+}
+
+/*
+ * Every match of the pattern in a file. Binary and non UTF-8 files are skipped
+ */
+fn search_file(re: &Pattern, file_path: &Path, display_path: &str) -> Vec<Location> {
+    let Ok(bytes) = fs::read(file_path) else {
+        return vec![];
+    };
+    if bytes.contains(&0) {
+        return vec![];
+    }
+    let Ok(text) = String::from_utf8(bytes) else {
+        return vec![];
+    };
+
+    let mut locations = vec![];
+    for (line_idx, line) in text.lines().enumerate() {
+        for found_range in re.find_iter(line) {
+            locations.push(Location {
+                path: String::from(display_path),
+                line: line_idx + 1,
+                col: line[..found_range.start].chars().count() + 1,
+                text: line.trim().chars().take(MAX_TEXT_LEN).collect(),
+            });
+        }
+    }
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_pattern_from_path() {
+        assert_eq!(split_args("TODO src"), ("TODO", "src"));
+        assert_eq!(split_args("TODO"), ("TODO", ""));
+        assert_eq!(split_args("\"fn main\" src/bin"), ("fn main", "src/bin"));
+        assert_eq!(
+            split_args("'a \"quoted\" word'  docs "),
+            ("a \"quoted\" word", "docs")
+        );
+    }
+
+    #[test]
+    fn splits_unbalanced_quotes_at_spaces() {
+        assert_eq!(split_args("\"fn main src"), ("\"fn", "main src"));
+        assert_eq!(split_args("it's here"), ("it's", "here"));
+    }
+
+    #[test]
+    fn splits_empty_patterns() {
+        assert_eq!(split_args(""), ("", ""));
+        assert_eq!(split_args("\"\" src"), ("", "src"));
+        assert_eq!(split_args("''"), ("", ""));
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/*
+ * A shell command running in the background (ex. a build started by :make).
+ * Lines of its output (stdout and stderr combined) are sent back as they are printed.
+ * Dropping the job kills the command, along with anything it started
+ */
+#[derive(Debug)]
+pub struct CommandJob {
+    child: Child,
+    receiver: Receiver<String>,
+    output_closed: bool, // Have both of the command's outputs been read to the end?
+}
+
+impl CommandJob {
+    /*
+     * Run the command in the given directory. It gets a process group of its own,
+     * so the programs the shell runs can be killed with it
+     */
+    pub fn spawn(command: &str, dir: &Path) -> io::Result<Self> {
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(command)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(unix)]
+        shell.process_group(0);
+        let mut child = shell.spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        let stderr_sender = sender.clone();
+        thread::spawn(move || forward_lines(stderr, &stderr_sender));
+        thread::spawn(move || forward_lines(stdout, &sender));
+
+        Ok(Self {
+            child,
+            receiver,
+            output_closed: false,
+        })
+    }
+
+    /*
+     * Collect the lines printed since the last call.
+     * The exit status is returned once the command has finished and all of its output was collected
+     */
+    pub fn poll(&mut self) -> (Vec<String>, Option<io::Result<ExitStatus>>) {
+        let mut lines = vec![];
+        loop {
+            match self.receiver.try_recv() {
+                Ok(line) => lines.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.output_closed = true;
+                    break;
+                }
+            }
+        }
+        if !self.output_closed {
+            return (lines, None);
+        }
+        (lines, self.child.try_wait().transpose())
+    }
+}
+
+impl Drop for CommandJob {
+    fn drop(&mut self) {
+        // Nothing to kill once the command has finished
+        if !matches!(self.child.try_wait(), Ok(None)) {
+            return;
+        }
+        #[cfg(unix)]
+        if let Ok(pid) = libc::pid_t::try_from(self.child.id()) {
+            // The process group has the shell's pid
+            unsafe {
+                libc::killpg(pid, libc::SIGKILL);
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn forward_lines(output: impl Read, sender: &Sender<String>) {
+    for line in BufReader::new(output).split(b'\n').map_while(Result::ok) {
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        if sender.send(line).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    // Poll a job until it finishes, collecting everything it printed
+    fn wait(job: &mut CommandJob) -> (Vec<String>, io::Result<ExitStatus>) {
+        let mut output = vec![];
+        loop {
+            let (lines, status) = job.poll();
+            output.extend(lines);
+            if let Some(status) = status {
+                return (output, status);
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn collects_output_and_exit_status() {
+        let mut job = CommandJob::spawn("echo out; echo err >&2; exit 3", Path::new(".")).unwrap();
+        let (mut output, status) = wait(&mut job);
+        output.sort();
+        assert_eq!(output, ["err", "out"]);
+        assert_eq!(status.unwrap().code(), Some(3));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_the_command_when_dropped() {
+        let dir = std::env::temp_dir().join(format!("rust-vim-job-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // The shell starts a program of its own, which has to be killed along with it
+        let job = CommandJob::spawn("sleep 1 && touch done & wait", &dir).unwrap();
+        let start = Instant::now();
+        drop(job);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!dir.join("done").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use crate::encoding::Encoding;
+use crate::fileformat::FileFormat;
+use ropey::Rope;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+// Files at least this big are loaded in the background, and have features which are slow on them turned off
+pub const LARGE_FILE_SIZE: u64 = 16 * 1024 * 1024;
+// Amount of the file read at a time
+const CHUNK_SIZE: usize = 1024 * 1024;
+// Most text handed to the buffer at once, so showing it (which wraps every new line) never stalls the editor
+const MAX_CHARS_PER_POLL: usize = 4 * 1024 * 1024;
+
+#[derive(Debug)]
+enum LoadUpdate {
+    Text(Rope, u64), // Everything loaded so far, and the number of bytes of the file it came from
+    Finished(Result<u64, String>), // Hash of the file's contents, or why the rest couldn't be read
+}
+
+/*
+ * A large file being read into a rope on a background thread.
+ * The rope loaded so far is sent back after every chunk (clones of a rope share its text, so this is cheap),
+ * so the start of the file can be shown and scrolled through before the rest has been read
+ */
+#[derive(Debug)]
+pub struct FileLoader {
+    receiver: Receiver<LoadUpdate>,
+    len: u64,                              // Size of the file
+    bytes_read: u64,                       // Amount of the file in the text received so far
+    received: Rope,                        // Text received so far
+    num_taken: usize, // Number of characters of the received text handed out by poll
+    finished: Option<Result<u64, String>>, // Set once the background thread is done
+    pub file_encoding: Encoding,
+    pub file_format: FileFormat,
+}
+
+impl FileLoader {
+    /*
+     * Start reading a file. Its encoding (unless given) and line endings are guessed from the first chunk,
+     * which is read straight away so they are known before any text arrives
+     */
+    pub fn spawn(file_path: &Path, encoding: Option<Encoding>) -> io::Result<Self> {
+        let mut file = File::open(file_path)?;
+        let len = file.metadata()?.len();
+        let mut first_chunk = vec![];
+        (&mut file)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut first_chunk)?;
+
+        // The chunk may end part way through a character, which shouldn't make it look like another encoding
+        let sample = match str::from_utf8(&first_chunk) {
+            Err(e) if e.error_len().is_none() => &first_chunk[..e.valid_up_to()],
+            _ => &first_chunk,
+        };
+        let file_encoding = encoding.unwrap_or_else(|| Encoding::detect(sample));
+        let file_format = match file_encoding.decode_part(&first_chunk) {
+            Ok((text, _)) => FileFormat::detect(text.as_str()),
+            Err(_) => FileFormat::default(),
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let result = load(file, first_chunk, file_encoding, file_format, &sender);
+            let _ = sender.send(LoadUpdate::Finished(result));
+        });
+
+        Ok(Self {
+            receiver,
+            len,
+            bytes_read: 0,
+            received: Rope::new(),
+            num_taken: 0,
+            finished: None,
+            file_encoding,
+            file_format,
+        })
+    }
+
+    /*
+     * Take the text loaded so far, if more has arrived since the last call. Large amounts are handed out
+     * a part at a time, so the rope returned may stop short of what has been read.
+     * The second value is set once everything has been taken, to the hash of the file's contents or why it failed
+     */
+    pub fn poll(&mut self) -> (Option<Rope>, Option<Result<u64, String>>) {
+        while self.finished.is_none() {
+            match self.receiver.try_recv() {
+                Ok(LoadUpdate::Text(rope, bytes_read)) => {
+                    self.received = rope;
+                    self.bytes_read = bytes_read;
+                }
+                Ok(LoadUpdate::Finished(result)) => self.finished = Some(result),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = Some(Err(String::from("loading stopped")));
+                }
+            }
+        }
+
+        let num_received = self.received.len_chars();
+        let rope = (self.num_taken < num_received).then(|| {
+            let mut rope = self.received.clone();
+            let end = self.num_taken + MAX_CHARS_PER_POLL;
+            if end < num_received {
+                rope.remove(end..);
+            }
+            self.num_taken = rope.len_chars();
+            rope
+        });
+        let finished = match self.num_taken == num_received {
+            true => self.finished.take(),
+            false => None,
+        };
+        (rope, finished)
+    }
+
+    // Percentage of the file handed out by poll so far
+    pub fn progress(&self) -> u64 {
+        let num_received = self.received.len_chars();
+        if self.len == 0 || num_received == 0 {
+            return 0;
+        }
+        let taken = self.num_taken as f64 / num_received as f64;
+        (taken * self.bytes_read as f64 * 100.0 / self.len as f64) as u64
+    }
+}
+
+/*
+ * Read the rest of the file a chunk at a time, adding each to the rope and sending it back.
+ * Bytes of a character split between chunks, and a "\r" which may be followed by "\n", wait for the next chunk.
+ * Returns the hash of the file's contents, worked out the same way as fileio's (writing each part in turn)
+ */
+fn load(
+    mut file: File,
+    first_chunk: Vec<u8>,
+    file_encoding: Encoding,
+    file_format: FileFormat,
+    sender: &Sender<LoadUpdate>,
+) -> Result<u64, String> {
+    let mut rope = Rope::new();
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&first_chunk);
+    let mut bytes_read = first_chunk.len() as u64;
+    let mut pending = first_chunk
+        .strip_prefix(file_encoding.bom())
+        .unwrap_or(&first_chunk)
+        .to_vec();
+    let mut carried_cr = false;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let num_read = file.read(&mut chunk).map_err(|e| e.to_string())?;
+        let at_end = num_read == 0;
+
+        let (mut text, used) = file_encoding.decode_part(&pending)?;
+        pending.drain(..used);
+        if carried_cr {
+            text.insert(0, '\r');
+        }
+        carried_cr = !at_end && text.ends_with('\r');
+        if carried_cr {
+            text.pop();
+        }
+        rope.insert(
+            rope.len_chars(),
+            file_format.normalize(text.as_str()).as_str(),
+        );
+
+        if at_end {
+            if !pending.is_empty() {
+                return Err(format!(
+                    "not valid {}: file ends part way through a character",
+                    file_encoding.name()
+                ));
+            }
+            let _ = sender.send(LoadUpdate::Text(rope, bytes_read));
+            return Ok(hasher.finish());
+        }
+        // Receiver is gone once the buffer no longer wants the file
+        if sender
+            .send(LoadUpdate::Text(rope.clone(), bytes_read))
+            .is_err()
+        {
+            return Err(String::from("loading cancelled"));
+        }
+        hasher.write(&chunk[..num_read]);
+        bytes_read += num_read as u64;
+        pending.extend_from_slice(&chunk[..num_read]);
+    }
+}
@@ -1,19 +1,64 @@
+mod backup;
 mod controller;
+mod encoding;
+mod explorer;
+mod fileformat;
+mod fileio;
+mod finder;
+mod grep;
+mod job;
+mod loader;
 mod model;
+mod pattern;
+mod quickfix;
+mod search;
+mod session;
+mod swap;
+mod testrunner;
 mod view;
-use controller::App;
+mod watcher;
+mod window;
+mod wrap;
+use controller::{App, StartPosition};
+use model::{EditorModel, OpenError};
+use ratatui::layout::Direction;
 use ratatui::{Terminal, prelude::Backend};
-use std::fs;
-use std::io;
-use std::path::PathBuf;
+use session::Session;
+use std::env;
+use std::io::{self, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use structopt::StructOpt;
 use view::draw_ui;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "rust-vim")]
 struct Opt {
-    #[structopt(parse(from_os_str))]
-    file_name: PathBuf,
+    /// Files or directories to open, or - to read text piped into stdin. Files may be given as file:line:col,
+    /// and +N, + or +/pattern starts the first file at line N, its last line or the first match of the pattern
+    #[structopt(parse(from_os_str), required_unless = "session")]
+    files: Vec<PathBuf>,
+
+    /// Restore a session saved by :mksession
+    #[structopt(short = "S", parse(from_os_str))]
+    session: Option<PathBuf>,
+
+    /// Open the files read-only, so they are only written by :w!
+    #[structopt(short = "R")]
+    read_only: bool,
+
+    /// Run an ex command (ex. -c "set nu") once the files are loaded. May be given several times
+    #[structopt(short = "c", number_of_values = 1)]
+    commands: Vec<String>,
+
+    /// Open each file in its own window, one above another
+    #[structopt(short = "o", conflicts_with = "vsplit")]
+    split: bool,
+
+    /// Open each file in its own window, side by side
+    #[structopt(short = "O")]
+    vsplit: bool,
 }
 
 /*
@@ -23,36 +68,254 @@ fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<
     while app.running() {
         //app.update_term_size(term_size.height, term_size.width);
         terminal.draw(|frame| draw_ui(frame, app))?; // draw_ui will be a pub func from view to draw the ui
-        app.handle_events()?; // controller will process inputs
+        app.handle_events()?; // controller will process inputs, returning regularly while background jobs (ex. :make) run so their progress is redrawn
     }
     Ok(())
 }
 
-fn main() -> io::Result<()> {
+/*
+ * Load the file given on the command line, or a listing if it's a directory.
+ * Given "-", the text piped into stdin is read into an unnamed buffer, leaving keys to be read from the terminal.
+ * With read_only (-R), the buffer is only written when forced
+ */
+fn open_model(file_path: &Path, read_only: bool) -> Result<EditorModel, OpenError> {
+    if file_path == Path::new("-") {
+        let mut contents = vec![];
+        io::stdin().read_to_end(&mut contents)?;
+        let mut model = EditorModel::unnamed(&contents);
+        model.read_only = read_only;
+        return Ok(model);
+    }
+    if file_path.is_dir() {
+        return Ok(EditorModel::from_directory(file_path, false)?);
+    }
+    // Checked before creating the file, so a name which can't be opened doesn't leave an empty file behind
+    if file_path.to_str().is_none() {
+        return Err(OpenError::NonUtf8Path(file_path.to_path_buf()));
+    }
+    model::create_if_missing(file_path)?;
+    EditorModel::new(file_path, read_only)
+}
+
+/*
+ * Split a file given as file:line or file:line:col (as printed by compilers, even with a trailing colon)
+ * into the file and where to start in it. Files which exist under the full name are left alone
+ */
+fn split_location(file_path: &Path) -> (PathBuf, Option<StartPosition>) {
+    let Some(name) = file_path.to_str().filter(|_| !file_path.exists()) else {
+        return (file_path.to_path_buf(), None);
+    };
+    let mut rest = name.strip_suffix(':').unwrap_or(name);
+    let mut numbers = vec![];
+    while numbers.len() < 2
+        && let Some((head, tail)) = rest.rsplit_once(':')
+        && let Ok(number) = tail.parse::<usize>()
+    {
+        numbers.insert(0, number);
+        rest = head;
+    }
+    let start = match numbers[..] {
+        [line] => StartPosition::Line(line, 1),
+        [line, col] => StartPosition::Line(line, col),
+        _ => return (file_path.to_path_buf(), None),
+    };
+    if rest.is_empty() {
+        return (file_path.to_path_buf(), None);
+    }
+    (PathBuf::from(rest), Some(start))
+}
+
+fn main() -> ExitCode {
+    // Fatal errors are reported once the terminal is back to normal
+    match run() {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rust-vim: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> io::Result<()> {
     // Read arguments and open file
     let opts = Opt::from_args();
-    let file_path = opts.file_name;
-    if !file_path.exists() {
-        if let Some(parent) = file_path.parent()
-            && !parent.exists() {
-                fs::create_dir_all(parent)?;
-            }
-        fs::File::create(&file_path)?; // create an empty file
+    // A session starts from its working directory, with its first window's file
+    let session = match &opts.session {
+        Some(session_path) => {
+            let session = Session::read(session_path)?;
+            env::set_current_dir(&session.directory).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "could not change to session directory {}: {}",
+                        session.directory.display(),
+                        e
+                    ),
+                )
+            })?;
+            Some(session)
+        }
+        None => None,
+    };
+
+    // Pick out +N, + and +/pattern from the files. Anything else after a + is run as an ex command
+    let mut start = None;
+    let mut commands = vec![];
+    let mut file_args = vec![];
+    for arg in opts.files {
+        match arg.to_str().and_then(|arg| arg.strip_prefix('+')) {
+            Some("") => start = Some(StartPosition::LastLine),
+            Some(arg) => match (arg.strip_prefix('/'), arg.parse()) {
+                (Some(pattern), _) => start = Some(StartPosition::Pattern(String::from(pattern))),
+                (None, Ok(line)) => start = Some(StartPosition::Line(line, 1)),
+                (None, Err(_)) => commands.push(String::from(arg)),
+            },
+            None => file_args.push(split_location(&arg)),
+        }
+    }
+    commands.extend(opts.commands);
+    if let Some((_, first_start)) = file_args.first_mut()
+        && start.is_some()
+    {
+        *first_start = start.take();
+    }
+    let mut file_args = match &session {
+        Some(session) => vec![(PathBuf::from(&session.tabs[0].windows[0].file_name), None)],
+        None => file_args,
+    };
+
+    let split = if opts.split {
+        Some(Direction::Vertical)
+    } else if opts.vsplit {
+        Some(Direction::Horizontal)
+    } else {
+        None
+    };
+    // New windows are split off above (or left of) the current one, so the files are opened last to first
+    if split.is_some() {
+        file_args.reverse();
     }
+    let num_files = file_args.len();
 
-    // build model buffer
-    let mut model = model::EditorModel::new(file_path.to_str().unwrap());
+    // A file named more than once is only opened once, as opening it again would find its own swap file in use.
+    // Files which can't be opened are replaced by an empty buffer, explaining why in the message bar
+    let mut open_error = None;
+    let mut opened: Vec<PathBuf> = vec![];
+    let mut models = vec![];
+    let mut windows = vec![]; // Which model each file shows, and where it starts
+    for (file_path, start) in file_args {
+        let same_file = model::canonical_path(&file_path);
+        if let Some(idx) = opened.iter().position(|path| *path == same_file) {
+            windows.push((idx, start));
+            continue;
+        }
+        let model = match open_model(&file_path, opts.read_only) {
+            Ok(model) => model,
+            Err(e) => {
+                open_error.get_or_insert(format!(
+                    "Error: could not open {}: {}",
+                    file_path.display(),
+                    e
+                ));
+                EditorModel::empty(file_path.to_string_lossy().as_ref())
+            }
+        };
+        opened.push(same_file);
+        windows.push((models.len(), start));
+        models.push(model);
+    }
+    // Only +N, + or +/pattern were given
+    if models.is_empty() {
+        models.push(EditorModel::unnamed(&[]));
+        windows.push((0, start));
+    }
+    let mut models = models.into_iter();
+    let model = models.next().unwrap();
 
-    // Initialize terminal and build App structure containing app state
+    // Initialize terminal and build App structure containing app state.
+    // Whatever happens from here on, the terminal is restored before returning
     let mut terminal = ratatui::init();
-    let term_height = terminal.size()?.height;
-    let term_width = terminal.size()?.width;
-    let display_string = model.rope.to_string();
-    let mut app = App::new(&mut model, display_string.as_str(), term_height, term_width);
+    let app_result = (|| {
+        let term_height = terminal.size()?.height;
+        let term_width = terminal.size()?.width;
+        let mut app = App::new(model, term_height, term_width);
+        let buffers: Vec<_> = iter::once(0)
+            .chain(models.map(|model| app.add_file(model)))
+            .collect();
+        // The first file is in the window App starts with. The others get windows of their own when splitting,
+        // or otherwise start where they were asked to once they're shown
+        let mut windows = windows.into_iter();
+        if let Some((_, Some(start))) = windows.next() {
+            app.start_at(&start);
+        }
+        for (idx, start) in windows {
+            if let Err(msg) = app.show_file(buffers[idx], split, start) {
+                open_error.get_or_insert(String::from(msg));
+            }
+        }
+        if split.is_some() {
+            app.equalize_windows();
+        } else if num_files > 1 {
+            app.show_message(format!("{} files to edit", num_files).as_str());
+        }
+        if let Some(msg) = open_error {
+            app.show_message(msg.as_str());
+        }
+        if let Some(session) = session {
+            app.restore_session(&session);
+        }
+        for command in commands {
+            app.run_startup_command(command.as_str());
+        }
 
-    let app_result = run_app(&mut terminal, &mut app);
+        run_app(&mut terminal, &mut app)
+    })();
 
     // Restore the terminal on closure
     ratatui::restore();
     app_result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn split(name: &str) -> (PathBuf, Option<StartPosition>) {
+        split_location(Path::new(name))
+    }
+
+    #[test]
+    fn splits_locations_off_file_names() {
+        let file = PathBuf::from("src/no-such-file.rs");
+        assert_eq!(
+            split("src/no-such-file.rs:12"),
+            (file.clone(), Some(StartPosition::Line(12, 1)))
+        );
+        assert_eq!(
+            split("src/no-such-file.rs:12:3"),
+            (file.clone(), Some(StartPosition::Line(12, 3)))
+        );
+        assert_eq!(
+            split("src/no-such-file.rs:12:3:"),
+            (file.clone(), Some(StartPosition::Line(12, 3)))
+        );
+        assert_eq!(split("src/no-such-file.rs"), (file, None));
+    }
+
+    #[test]
+    fn leaves_names_without_a_file_alone() {
+        assert_eq!(split(":12"), (PathBuf::from(":12"), None));
+        assert_eq!(split("notes:draft"), (PathBuf::from("notes:draft"), None));
+    }
+
+    #[test]
+    fn leaves_existing_files_with_colons_alone() {
+        let dir = std::env::temp_dir().join(format!("rust-vim-main-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("log:12:3");
+        fs::write(&file_path, "").unwrap();
+        assert_eq!(split_location(&file_path), (file_path.clone(), None));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,32 +1,361 @@
-use core::ops::Range;
-use regex::Regex;
+use crate::backup::{self, BackupOptions};
+use crate::encoding::Encoding;
+use crate::explorer::{self, DirectoryListing};
+use crate::fileformat::FileFormat;
+use crate::fileio::{self, DiskState};
+use crate::loader::{self, FileLoader};
+use crate::quickfix::{ListSource, Location};
+use crate::swap::{self, ExistingSwap, SwapFile};
 use ropey::Rope;
+use similar::TextDiff;
+use std::cmp;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+/*
+ * Reasons a file can't be opened for editing
+ */
+#[derive(Debug)]
+pub enum OpenError {
+    NonUtf8Path(PathBuf), // File names are kept as text, so they must be valid UTF-8
+    IsDirectory,
+    PermissionDenied,
+    Io(io::Error),
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::NonUtf8Path(path) => {
+                write!(f, "file name is not valid UTF-8: {}", path.display())
+            }
+            OpenError::IsDirectory => write!(f, "is a directory"),
+            OpenError::PermissionDenied => write!(f, "permission denied"),
+            OpenError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for OpenError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => OpenError::PermissionDenied,
+            io::ErrorKind::IsADirectory => OpenError::IsDirectory,
+            _ => OpenError::Io(e),
+        }
+    }
+}
+
+// Edits remembered for windows to catch up with. Windows further behind re-wrap the whole buffer instead
+const MAX_EDITS: usize = 1000;
+
+/*
+ * Lines replaced by an edit: the removed lines starting at line became the added lines.
+ * Within them, the chars_removed characters starting at index start became chars_added characters
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct LineEdit {
+    pub line: usize,
+    pub removed: usize,
+    pub added: usize,
+    pub start: usize,
+    pub chars_removed: usize,
+    pub chars_added: usize,
+}
 
 #[derive(Debug)]
 pub struct EditorModel {
     pub rope: Rope,
     pub file_name: String,
+    canonical_path: Option<PathBuf>, // Where the file really is, so a path spelled another way finds the same buffer
+    pub modified: bool,              // Has the buffer changed since it was last loaded or saved?
+    pub directory: Option<DirectoryListing>, // Set if this buffer is a directory listing rather than a file
+    pub list: Option<ListSource>, // Set if this buffer shows a quickfix or location list, one entry per line
+    pub read_only: bool,
+    swap: Option<SwapFile>, // Where unsaved changes are kept in case the editor is killed
+    swap_on_change: bool, // Set for files opened read-only, whose swap file isn't made until they're first changed
+    pub existing_swap: Option<ExistingSwap>, // Swap file found from another process, until the user decides what to do with it
+    disk_state: Option<DiskState>,           // File as it was last read or written
+    pub file_format: FileFormat,             // Line endings the file is written with
+    pub file_encoding: Encoding,             // Character encoding the file is written with
+    edits: Vec<LineEdit>, // Most recent edits, replayed by windows to re-wrap only the lines they touched
+    num_edits: usize,     // Number of edits ever made, including those no longer remembered
+    pub large: bool, // Is the file big enough to turn off features which are slow on large files?
+    loader: Option<FileLoader>, // Set while the file is still being read in the background
 }
 
 impl EditorModel {
-    pub fn new(file_name: &str) -> Self {
+    /*
+     * Open a file. Files opened read-only (ex. with -R) are only overwritten when forced,
+     * and get no swap file until they're changed
+     */
+    pub fn new(file_path: &Path, read_only: bool) -> Result<Self, OpenError> {
+        let file_name = file_path
+            .to_str()
+            .ok_or_else(|| OpenError::NonUtf8Path(file_path.to_path_buf()))?;
+        if file_path.is_dir() {
+            return Err(OpenError::IsDirectory);
+        }
+        // Large files are read in the background. They get no swap file, as writing one would take as long as saving
+        if fs::metadata(file_path)?.len() >= loader::LARGE_FILE_SIZE {
+            let mut model = Self::empty(file_name);
+            model.canonical_path = Some(canonical_path(file_path));
+            model.read_only = read_only || !fileio::is_writable(file_path);
+            model.start_loading(None)?;
+            return Ok(model);
+        }
+        let contents = fs::read(file_path)?;
+        let (text, file_encoding, file_format) = decode(&contents, None)?;
+        // Another process's swap file is only worth asking about if it's still editing the file, or left unsaved changes.
+        // This editor's own belongs to a buffer which already has the file, and is taken over
+        let existing_swap = swap::find_existing(file_path).filter(|existing| {
+            !existing.is_ours() && (existing.running || existing.text.is_some())
+        });
+        Ok(Self {
+            rope: Rope::from_str(text.as_str()),
+            disk_state: DiskState::new(file_path, &contents),
+            file_format,
+            file_encoding,
+            file_name: String::from(file_name),
+            canonical_path: Some(canonical_path(file_path)),
+            modified: false,
+            directory: None,
+            list: None,
+            // Files that can't be written are only overwritten when forced
+            read_only: read_only || !fileio::is_writable(file_path),
+            swap: match existing_swap {
+                Some(_) => None,
+                None if read_only => None,
+                None => SwapFile::create(file_path),
+            },
+            swap_on_change: existing_swap.is_none() && read_only,
+            existing_swap,
+            edits: vec![],
+            num_edits: 0,
+            large: false,
+            loader: None,
+        })
+    }
+
+    /*
+     * Build an unnamed buffer holding text from elsewhere (ex. piped into stdin).
+     * It has no file to write to until it is given a name
+     */
+    pub fn unnamed(contents: &[u8]) -> Self {
+        // Reading with a guessed encoding never fails
+        let (text, file_encoding, file_format) = decode(contents, None).unwrap_or_default();
         Self {
-            rope: Rope::from_reader(BufReader::new(File::open(file_name).unwrap())).unwrap(),
+            rope: Rope::from_str(text.as_str()),
+            file_name: String::new(),
+            canonical_path: None,
+            modified: false,
+            directory: None,
+            list: None,
+            read_only: false,
+            swap: None,
+            swap_on_change: false,
+            existing_swap: None,
+            disk_state: None,
+            file_format,
+            file_encoding,
+            edits: vec![],
+            num_edits: 0,
+            large: false,
+            loader: None,
+        }
+    }
+
+    /*
+     * Build an empty buffer standing in for a file which couldn't be opened.
+     * It is read-only, so the file can't be overwritten by mistake
+     */
+    pub fn empty(file_name: &str) -> Self {
+        Self {
+            rope: Rope::new(),
             file_name: String::from(file_name),
+            canonical_path: None,
+            modified: false,
+            directory: None,
+            list: None,
+            read_only: true,
+            swap: None,
+            swap_on_change: false,
+            existing_swap: None,
+            disk_state: None,
+            file_format: FileFormat::default(),
+            file_encoding: Encoding::default(),
+            edits: vec![],
+            num_edits: 0,
+            large: false,
+            loader: None,
+        }
+    }
+
+    /*
+     * Build a buffer listing the contents of a directory
+     */
+    pub fn from_directory(dir_path: &Path, show_hidden: bool) -> io::Result<Self> {
+        let listing = DirectoryListing::read(dir_path, show_hidden)?;
+        Ok(Self {
+            rope: Rope::from_str(listing.to_text().as_str()),
+            file_name: explorer::display_path(&listing.path),
+            canonical_path: None,
+            modified: false,
+            directory: Some(listing),
+            list: None,
+            read_only: false,
+            swap: None,
+            swap_on_change: false,
+            existing_swap: None,
+            disk_state: None,
+            file_format: FileFormat::default(),
+            file_encoding: Encoding::default(),
+            edits: vec![],
+            num_edits: 0,
+            large: false,
+            loader: None,
+        })
+    }
+
+    /*
+     * Build an (initially empty) buffer showing a quickfix or location list
+     */
+    pub fn from_list(source: ListSource) -> Self {
+        Self {
+            rope: Rope::new(),
+            file_name: String::from(source.label()),
+            canonical_path: None,
+            modified: false,
+            directory: None,
+            list: Some(source),
+            read_only: false,
+            swap: None,
+            swap_on_change: false,
+            existing_swap: None,
+            disk_state: None,
+            file_format: FileFormat::default(),
+            file_encoding: Encoding::default(),
+            edits: vec![],
+            num_edits: 0,
+            large: false,
+            loader: None,
+        }
+    }
+
+    // Replace the entries shown by a list buffer
+    pub fn set_entries(&mut self, entries: &[Location]) {
+        let lines: Vec<String> = entries.iter().map(|entry| entry.to_line()).collect();
+        self.replace_text(lines.join("\n").as_str());
+    }
+
+    // Add entries to the end of a list buffer
+    pub fn append_entries(&mut self, entries: &[Location]) {
+        let last_line = self.rope.len_lines() - 1;
+        let len = self.rope.len_chars();
+        for entry in entries {
+            // Entry lines are never empty, so an empty buffer has no entries yet
+            if self.rope.len_chars() > 0 {
+                self.rope.insert_char(self.rope.len_chars(), '\n');
+            }
+            self.rope
+                .insert(self.rope.len_chars(), entry.to_line().as_str());
         }
+        self.record_edit(
+            last_line,
+            1,
+            self.rope.len_lines() - last_line,
+            len,
+            0,
+            self.rope.len_chars() - len,
+        );
+    }
+
+    // Has the buffer changed with no file to save it to?
+    pub fn is_unnamed_change(&self) -> bool {
+        self.modified && self.file_name.is_empty() && !self.is_listing()
+    }
+
+    // Name shown for the buffer, which may not have a file yet
+    pub fn display_name(&self) -> &str {
+        if self.file_name.is_empty() {
+            "[No Name]"
+        } else {
+            self.file_name.as_str()
+        }
+    }
+
+    /*
+     * Give an unnamed buffer a file to be written to, starting its swap file
+     */
+    pub fn set_file_name(&mut self, file_name: &str) {
+        self.file_name = String::from(file_name);
+        self.canonical_path = Some(canonical_path(Path::new(file_name)));
+        self.swap = SwapFile::create(Path::new(file_name));
+        if let Some(swap) = &mut self.swap
+            && self.modified
+        {
+            let _ = swap.write(Some(&self.rope));
+        }
+    }
+
+    // Is this buffer the given file, spelled as by canonical_path?
+    pub fn is_file(&self, canonical: &Path) -> bool {
+        self.canonical_path.as_deref() == Some(canonical)
+    }
+
+    // Is the given swap file this buffer's?
+    pub fn has_swap(&self, swap_path: &Path) -> bool {
+        self.swap
+            .as_ref()
+            .is_some_and(|swap| canonical_path(swap.path()) == canonical_path(swap_path))
+    }
+
+    // Follow the buffer's file to where it was moved (ex. renamed in a directory listing)
+    pub fn file_moved(&mut self, file_name: &str) {
+        self.file_name = String::from(file_name);
+        self.canonical_path = Some(canonical_path(Path::new(file_name)));
+    }
+
+    /*
+     * Listings are generated from the file system or a list of locations, so they can't be edited or written
+     */
+    pub fn is_listing(&self) -> bool {
+        self.directory.is_some() || self.list.is_some()
+    }
+
+    /*
+     * Character index of a (1-indexed) line and column, clamped to the end of the line
+     */
+    pub fn line_col_to_index(&self, line: usize, col: usize) -> usize {
+        let line_idx = cmp::min(line.saturating_sub(1), self.rope.len_lines() - 1);
+        let line_len = self.rope.line(line_idx).len_chars();
+        self.rope.line_to_char(line_idx) + cmp::min(col.saturating_sub(1), line_len)
+    }
+
+    // Re-read a directory listing after its entries changed
+    pub fn refresh_directory(&mut self, show_hidden: bool) -> io::Result<()> {
+        if let Some(listing) = &self.directory {
+            let listing = DirectoryListing::read(&listing.path, show_hidden)?;
+            self.replace_text(listing.to_text().as_str());
+            self.directory = Some(listing);
+        }
+        Ok(())
     }
 
     pub fn insert_char(&mut self, ch: char, char_idx: usize) {
+        let line = self.rope.char_to_line(char_idx);
         self.rope.insert_char(char_idx, ch);
+        self.record_edit(line, 1, if ch == '\n' { 2 } else { 1 }, char_idx, 0, 1);
+        self.record_change();
     }
 
     pub fn delete_char(&mut self, char_idx: usize) -> bool {
         if char_idx < self.rope.len_chars() {
-            self.rope.remove(char_idx..char_idx + 1);
+            self.remove(char_idx, char_idx + 1);
+            self.record_change();
             return true;
         }
         false
@@ -34,37 +363,488 @@ impl EditorModel {
 
     pub fn delete_range(&mut self, start_idx: usize, end_idx: usize) {
         if end_idx > start_idx && end_idx < self.rope.len_chars() {
-            self.rope.remove(start_idx..end_idx);
+            self.remove(start_idx, end_idx);
+            self.record_change();
         }
     }
 
     pub fn delete_to_end(&mut self, start_idx: usize) {
         if start_idx < self.rope.len_chars() {
-            self.rope.remove(start_idx..self.rope.len_chars());
+            self.remove(start_idx, self.rope.len_chars());
+            self.record_change();
+        }
+    }
+
+    // Replace text (ex. a match of :s) with other text, which may have line breaks of its own
+    pub fn replace_range(&mut self, start_idx: usize, end_idx: usize, text: &str) {
+        let first_line = self.rope.char_to_line(start_idx);
+        let last_line = self.rope.char_to_line(end_idx);
+        self.rope.remove(start_idx..end_idx);
+        self.rope.insert(start_idx, text);
+        self.record_edit(
+            first_line,
+            last_line - first_line + 1,
+            text.matches('\n').count() + 1,
+            start_idx,
+            end_idx - start_idx,
+            text.chars().count(),
+        );
+        self.record_change();
+    }
+
+    // Remove text, joining the lines it started and ended on
+    fn remove(&mut self, start_idx: usize, end_idx: usize) {
+        let first_line = self.rope.char_to_line(start_idx);
+        let last_line = self.rope.char_to_line(end_idx);
+        self.rope.remove(start_idx..end_idx);
+        self.record_edit(
+            first_line,
+            last_line - first_line + 1,
+            1,
+            start_idx,
+            end_idx - start_idx,
+            0,
+        );
+    }
+
+    // Replace the whole buffer, such as when its file is read again
+    fn replace_text(&mut self, text: &str) {
+        let (removed, chars_removed) = (self.rope.len_lines(), self.rope.len_chars());
+        self.rope = Rope::from_str(text);
+        self.record_edit(
+            0,
+            removed,
+            self.rope.len_lines(),
+            0,
+            chars_removed,
+            self.rope.len_chars(),
+        );
+    }
+
+    fn record_edit(
+        &mut self,
+        line: usize,
+        removed: usize,
+        added: usize,
+        start: usize,
+        chars_removed: usize,
+        chars_added: usize,
+    ) {
+        self.edits.push(LineEdit {
+            line,
+            removed,
+            added,
+            start,
+            chars_removed,
+            chars_added,
+        });
+        self.num_edits += 1;
+        if self.edits.len() > MAX_EDITS {
+            self.edits.drain(..MAX_EDITS / 2);
         }
     }
 
-    pub fn run_search(&mut self, search_query: &str) -> Vec<Range<usize>> {
-        let mut matches = vec![];
-        if search_query.is_empty() {
-            return matches;
+    pub fn num_edits(&self) -> usize {
+        self.num_edits
+    }
+
+    /*
+     * Edits made since the buffer had the given number of edits,
+     * or None if some of them are no longer remembered
+     */
+    pub fn edits_since(&self, num_edits: usize) -> Option<&[LineEdit]> {
+        let first_remembered = self.num_edits - self.edits.len();
+        if num_edits < first_remembered || num_edits > self.num_edits {
+            return None;
+        }
+        Some(&self.edits[num_edits - first_remembered..])
+    }
+
+    fn record_change(&mut self) {
+        self.modified = true;
+        if self.swap_on_change {
+            self.swap_on_change = false;
+            self.swap = SwapFile::create(Path::new(&self.file_name));
+        }
+        if let Some(swap) = &mut self.swap {
+            swap.record_change();
         }
-        let re = Regex::new(search_query).unwrap();
-        let lines = self.rope.len_lines();
-        for y in 0..lines {
-            let line = self.rope.line(y).to_string();
-            let idx = self.rope.line_to_char(y);
-            for regex_match in re.find_iter(&line) {
-                matches.push(Range {
-                    start: regex_match.start() + idx,
-                    end: regex_match.end() + idx,
-                });
+    }
+
+    // Number of changes not yet written to the swap file
+    pub fn pending_swap_changes(&self) -> usize {
+        self.swap.as_ref().map_or(0, |swap| swap.pending_changes())
+    }
+
+    // Write unsaved changes to the swap file
+    pub fn sync_swap(&mut self) {
+        if let Some(swap) = &mut self.swap
+            && swap.pending_changes() > 0
+        {
+            let _ = swap.write(Some(&self.rope));
+        }
+    }
+
+    /*
+     * Load the unsaved changes from another process's swap file, and take it over
+     */
+    pub fn recover_swap(&mut self) -> Result<(), &'static str> {
+        let Some(existing) = &self.existing_swap else {
+            return Err("Error: No swap file to recover from");
+        };
+        let Some(text) = &existing.text else {
+            return Err("Error: Swap file has no unsaved changes to recover");
+        };
+        let text = text.clone();
+        self.replace_text(text.as_str());
+        self.modified = true;
+        self.existing_swap = None;
+        self.swap = SwapFile::create(Path::new(&self.file_name));
+        if let Some(swap) = &mut self.swap {
+            let _ = swap.write(Some(&self.rope));
+        }
+        Ok(())
+    }
+
+    /*
+     * Delete another process's swap file, unless it is still editing the file
+     */
+    pub fn delete_swap(&mut self) -> Result<(), &'static str> {
+        if self
+            .existing_swap
+            .as_ref()
+            .is_some_and(|existing| existing.running)
+        {
+            return Err("Error: Swap file is still in use");
+        }
+        if let Some(existing) = self.existing_swap.take() {
+            let _ = fs::remove_file(existing.path);
+        }
+        self.swap = SwapFile::create(Path::new(&self.file_name));
+        Ok(())
+    }
+
+    // Leave another process's swap file alone, without writing any changes to the file
+    pub fn open_read_only(&mut self) {
+        self.existing_swap = None;
+        self.read_only = true;
+    }
+
+    // Remove the swap file once the buffer is no longer being edited
+    pub fn close_swap(&mut self) {
+        if let Some(swap) = self.swap.take() {
+            swap.remove();
+        }
+    }
+
+    /*
+     * Start reading a large file in the background, with the given encoding or the one it seems to use.
+     * Its text is added to the buffer as it arrives
+     */
+    fn start_loading(&mut self, encoding: Option<Encoding>) -> io::Result<()> {
+        let loader = FileLoader::spawn(Path::new(&self.file_name), encoding)?;
+        self.file_encoding = loader.file_encoding;
+        self.file_format = loader.file_format;
+        self.disk_state = None;
+        self.large = true;
+        self.loader = Some(loader);
+        Ok(())
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loader.is_some()
+    }
+
+    // Percentage of the file read so far, while it is still being loaded
+    pub fn load_progress(&self) -> Option<u64> {
+        self.loader.as_ref().map(|loader| loader.progress())
+    }
+
+    /*
+     * Add the text read in the background since the last call. Returns whether anything changed, and once
+     * loading has finished, whether the whole file was read. Files that couldn't be read to the end are made
+     * read-only, so the missing part isn't written over by mistake
+     */
+    pub fn poll_loading(&mut self) -> (bool, Option<Result<(), String>>) {
+        let Some(loader) = &mut self.loader else {
+            return (false, None);
+        };
+        let (rope, finished) = loader.poll();
+        let changed = rope.is_some();
+        if let Some(rope) = rope {
+            // Text is only ever added to the end while loading
+            let (last_line, len) = (self.rope.len_lines() - 1, self.rope.len_chars());
+            self.rope = rope;
+            self.record_edit(
+                last_line,
+                1,
+                self.rope.len_lines() - last_line,
+                len,
+                0,
+                self.rope.len_chars() - len,
+            );
+        }
+        let finished = finished.map(|result| {
+            self.loader = None;
+            match result {
+                Ok(hash) => {
+                    self.disk_state = DiskState::with_hash(Path::new(&self.file_name), hash);
+                    Ok(())
+                }
+                Err(e) => {
+                    self.read_only = true;
+                    Err(e)
+                }
             }
+        });
+        (changed, finished)
+    }
+
+    /*
+     * Change the line endings the file is written with, which counts as a change to the buffer
+     */
+    pub fn set_file_format(&mut self, file_format: FileFormat) -> Result<(), &'static str> {
+        if self.is_listing() {
+            return Err("Error: Cannot edit a listing");
+        }
+        if self.is_loading() {
+            return Err("Error: File is still loading");
+        }
+        if file_format != self.file_format {
+            self.file_format = file_format;
+            self.record_change();
         }
-        matches
+        Ok(())
+    }
+
+    /*
+     * Has another program changed the file since it was last read or written?
+     */
+    pub fn changed_on_disk(&self) -> bool {
+        self.disk_state
+            .as_ref()
+            .is_some_and(|disk_state| disk_state.changed(Path::new(&self.file_name)))
     }
 
-    pub fn save(&self) -> io::Result<()> {
-        fs::write(&self.file_name, self.rope.to_string())
+    /*
+     * Keep the buffer despite the file being changed by another program. The buffer no longer matches the file,
+     * so it counts as modified, and :w overwrites the file without needing to be forced
+     */
+    pub fn keep_changes(&mut self) {
+        self.disk_state = DiskState::read(Path::new(&self.file_name));
+        self.modified = true;
+    }
+
+    /*
+     * Unified diff from the file on disk to the buffer, one line per entry
+     */
+    pub fn diff_with_disk(&self) -> Vec<String> {
+        let disk_text = fs::read(&self.file_name)
+            .ok()
+            .and_then(|contents| decode(&contents, Some(self.file_encoding)).ok())
+            .map(|(text, _, _)| text)
+            .unwrap_or_default();
+        let buffer_text = self.rope.to_string();
+        let diff = TextDiff::from_lines(&disk_text, &buffer_text);
+        let text = diff
+            .unified_diff()
+            .header(
+                &format!("{} (on disk)", self.file_name),
+                &format!("{} (buffer)", self.file_name),
+            )
+            .to_string();
+        if text.is_empty() {
+            return vec![String::from("No differences")];
+        }
+        text.lines().map(String::from).collect()
+    }
+
+    /*
+     * Change the character encoding the file is written with, which counts as a change to the buffer
+     */
+    pub fn set_file_encoding(&mut self, file_encoding: Encoding) -> Result<(), &'static str> {
+        if self.is_listing() {
+            return Err("Error: Cannot edit a listing");
+        }
+        if self.is_loading() {
+            return Err("Error: File is still loading");
+        }
+        if file_encoding != self.file_encoding {
+            self.file_encoding = file_encoding;
+            self.record_change();
+        }
+        Ok(())
+    }
+
+    /*
+     * Replace the buffer with the file's current contents, discarding any unsaved changes.
+     * The file is read with the given encoding, or the one it seems to use.
+     * Returns a warning to show if the swap file couldn't be updated afterwards
+     */
+    pub fn reload(&mut self, encoding: Option<Encoding>) -> io::Result<Option<String>> {
+        if self.file_name.is_empty() {
+            return Err(io::Error::other("no file name"));
+        }
+        let file_path = Path::new(&self.file_name);
+        if fs::metadata(file_path)?.len() >= loader::LARGE_FILE_SIZE {
+            self.start_loading(encoding)?;
+            self.replace_text("");
+            self.modified = false;
+            return Ok(None);
+        }
+        let contents = fs::read(file_path)?;
+        let (text, file_encoding, file_format) = decode(&contents, encoding)?;
+        self.disk_state = DiskState::new(file_path, &contents);
+        self.replace_text(text.as_str());
+        self.file_format = file_format;
+        self.file_encoding = file_encoding;
+        self.modified = false;
+        self.large = false;
+        self.loader = None;
+        // The file was read, so failing to update the swap file is only worth a warning
+        let warning = match &mut self.swap {
+            Some(swap) => swap
+                .write(None)
+                .err()
+                .map(|e| format!("could not update swap file: {}", e)),
+            None => None,
+        };
+        Ok(warning)
+    }
+
+    /*
+     * Write the buffer to its file, first copying the file's previous contents to a backup if turned on.
+     * Read-only files, and files changed by another program since they were read, are only overwritten when forced.
+     * Returns a warning to show if the backup made while writing couldn't be made, or the swap file couldn't be updated
+     */
+    pub fn save(
+        &mut self,
+        backup_options: &BackupOptions,
+        force: bool,
+    ) -> io::Result<Option<String>> {
+        if self.is_listing() {
+            return Err(io::Error::other("cannot write a listing"));
+        }
+        if self.is_loading() {
+            return Err(io::Error::other("file is still loading"));
+        }
+        if self.file_name.is_empty() {
+            return Err(io::Error::other("no file name (use :w file)"));
+        }
+        if self.read_only && !force {
+            return Err(io::Error::other("file is read-only (add ! to override)"));
+        }
+        if !force && self.changed_on_disk() {
+            return Err(io::Error::other(
+                "file changed on disk since it was read (add ! to overwrite)",
+            ));
+        }
+        let file_path = Path::new(&self.file_name);
+        // A backup only made in case the write fails isn't worth copying a large file for
+        let mut backup_options = backup_options.clone();
+        backup_options.writebackup &= !self.large;
+        // Failing to make a backup only stops the write when one was asked to be kept
+        let mut warning = None;
+        let backup_path = match backup::create(file_path, &backup_options) {
+            Ok(backup_path) => backup_path,
+            Err(e) if backup_options.backup => {
+                return Err(io::Error::other(format!("could not make backup: {}", e)));
+            }
+            Err(e) => {
+                warning = Some(format!("no backup made while writing: {}", e));
+                None
+            }
+        };
+        let contents = self.encoded_contents()?;
+        fileio::write_atomic(file_path, &contents, force)?;
+        self.disk_state = DiskState::new(file_path, &contents);
+        // Backups only made in case the write failed aren't needed any more
+        if let Some(backup_path) = backup_path
+            && !backup_options.backup
+        {
+            let _ = fs::remove_file(backup_path);
+        }
+        self.modified = false;
+        // The file was written, so failing to update the swap file is only worth a warning
+        if let Some(swap) = &mut self.swap
+            && let Err(e) = swap.write(None)
+        {
+            let swap_warning = format!("could not update swap file: {}", e);
+            warning = Some(match warning {
+                Some(warning) => format!("{}; {}", warning, swap_warning),
+                None => swap_warning,
+            });
+        }
+        Ok(warning)
+    }
+
+    /*
+     * Write the buffer to another file, leaving the buffer's own file (and whether it's modified) alone
+     */
+    pub fn write_copy(&self, file_path: &Path) -> io::Result<()> {
+        if self.is_listing() {
+            return Err(io::Error::other("cannot write a listing"));
+        }
+        if self.is_loading() {
+            return Err(io::Error::other("file is still loading"));
+        }
+        fileio::write_atomic(file_path, &self.encoded_contents()?, false)
+    }
+
+    // Buffer's text as it is written to the file, in the file's encoding and line endings
+    fn encoded_contents(&self) -> io::Result<Vec<u8>> {
+        let text = self.file_format.apply(self.rope.to_string().as_str());
+        self.file_encoding
+            .encode(text.as_str())
+            .map_err(io::Error::other)
+    }
+}
+
+/*
+ * Text of a file as held in a buffer (UTF-8 with "\n" line endings), along with the encoding and line endings
+ * the file used. Reading with a guessed encoding never fails, but a given encoding may not fit the file
+ */
+fn decode(
+    contents: &[u8],
+    encoding: Option<Encoding>,
+) -> io::Result<(String, Encoding, FileFormat)> {
+    let encoding = encoding.unwrap_or_else(|| Encoding::detect(contents));
+    let text = encoding
+        .decode(contents)
+        .map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))?;
+    let file_format = FileFormat::detect(text.as_str());
+    Ok((file_format.normalize(text.as_str()), encoding, file_format))
+}
+
+/*
+ * Create an empty file (and any missing parent directories) if the given path doesn't exist yet
+ */
+/*
+ * The same path for every way of spelling a file's location (ex. ./src/x.rs and src/x.rs), used to tell
+ * whether it's already open. Files which don't exist yet are found through their directory
+ */
+pub fn canonical_path(file_path: &Path) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(file_path) {
+        return path;
+    }
+    let parent = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), file_path.file_name()) {
+        (Ok(parent), Some(name)) => parent.join(name),
+        _ => file_path.to_path_buf(),
+    }
+}
+
+pub fn create_if_missing(file_path: &Path) -> io::Result<()> {
+    if !file_path.exists() {
+        if let Some(parent) = file_path.parent()
+            && !parent.exists()
+        {
+            fs::create_dir_all(parent)?;
+        }
+        File::create(file_path)?; // create an empty file
     }
+    Ok(())
 }
@@ -0,0 +1,698 @@
+use core::ops::Range;
+use regex::Regex;
+use std::iter::{self, Peekable};
+use std::mem;
+use std::str::Chars;
+
+// Named group holding the part of a match between \zs and \ze
+const MATCH_GROUP: &str = "zs";
+
+/*
+ * A search pattern written in Vim's regex syntax, translated for the regex crate.
+ * Patterns looking around (\@=, \@<= ...) or back (\1) need a backtracking engine, which is only used for them
+ */
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    engine: Engine,
+    has_match_group: bool, // Matches are cut down to the text between \zs and \ze
+}
+
+#[derive(Debug, Clone)]
+enum Engine {
+    Plain(Regex),
+    Backtracking(fancy_regex::Regex),
+}
+
+/*
+ * How much of the pattern has a special meaning without a backslash, switched with \v, \m, \M and \V
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MagicLevel {
+    VeryMagic,   // Every punctuation character, ex. (a|b)+
+    Magic,       // Only ^ $ . * [ ~, the default
+    NoMagic,     // Only ^ $
+    VeryNoMagic, // Only ^ $ as well. Vim's \V also makes them literal away from the ends, which isn't needed here
+}
+
+/*
+ * Piece of a pattern, with its meaning in the current mode worked out
+ */
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),        // Regex text matching on its own (ex. a character or class)
+    Multi(String, char), // Quantifier for the atom before it, and the character it was written as
+    Look(&'static str),  // Turns the atom before it into a lookaround, ex. "(?="
+    Open(bool),          // Start of a group, and whether it captures
+    Close,
+    Alt,
+    Bol, // ^ and $ only anchor at the start or end of a branch, and are literal anywhere else
+    Eol,
+    Backref(usize),
+    MatchStart, // \zs
+    MatchEnd,   // \ze
+}
+
+/*
+ * Alternatives of a group being translated
+ */
+#[derive(Debug, Default)]
+struct Group {
+    number: Option<usize>, // Number of a capturing group
+    branches: Vec<String>,
+    atoms: Vec<String>, // Translated atoms of the branch still being read
+}
+
+impl Group {
+    fn finish(mut self) -> String {
+        self.branches.push(self.atoms.concat());
+        self.branches.join("|")
+    }
+}
+
+impl Pattern {
+    /*
+     * Translate a pattern in Vim's syntax. ^ and $ match at the start and end of every line
+     */
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self, String> {
+        let tokens = tokenize(pattern)?;
+        // Text after \ze is looked ahead at rather than matched, so the next match may start within it
+        let ends_early = tokens
+            .iter()
+            .position(|token| *token == Token::MatchEnd)
+            .is_some_and(|idx| idx + 1 < tokens.len());
+        let backtracking = ends_early
+            || tokens
+                .iter()
+                .any(|token| matches!(token, Token::Look(_) | Token::Backref(_)));
+        let has_match_group = tokens
+            .iter()
+            .any(|token| matches!(token, Token::MatchStart | Token::MatchEnd));
+        Self::build(
+            translate(&tokens)?,
+            ignore_case,
+            backtracking,
+            has_match_group,
+        )
+    }
+
+    // Pattern matching the text as it is, with no special characters
+    pub fn literal(text: &str, ignore_case: bool) -> Result<Self, String> {
+        Self::build(regex::escape(text), ignore_case, false, false)
+    }
+
+    fn build(
+        regex: String,
+        ignore_case: bool,
+        backtracking: bool,
+        has_match_group: bool,
+    ) -> Result<Self, String> {
+        let flags = if ignore_case { "(?mi)" } else { "(?m)" };
+        let regex = format!("{}{}", flags, regex);
+        let engine = if backtracking {
+            Engine::Backtracking(
+                fancy_regex::Regex::new(regex.as_str()).map_err(|e| invalid(&e.to_string()))?,
+            )
+        } else {
+            Engine::Plain(Regex::new(regex.as_str()).map_err(|e| error_message(&e))?)
+        };
+        Ok(Self {
+            engine,
+            has_match_group,
+        })
+    }
+
+    /*
+     * Byte ranges of the matches in the text, in order.
+     * The backtracking engine gives up on matches taking too long to find, ending the search there
+     */
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = Range<usize>> + 'a> {
+        match (&self.engine, self.has_match_group) {
+            (Engine::Plain(re), false) => Box::new(re.find_iter(text).map(|found| found.range())),
+            (Engine::Plain(re), true) => Box::new(
+                re.captures_iter(text)
+                    .filter_map(|captures| captures.name(MATCH_GROUP))
+                    .map(|found| found.range()),
+            ),
+            (Engine::Backtracking(re), false) => Box::new(
+                re.find_iter(text)
+                    .map_while(Result::ok)
+                    .map(|found| found.range()),
+            ),
+            (Engine::Backtracking(re), true) => Box::new(
+                re.captures_iter(text)
+                    .map_while(Result::ok)
+                    .filter_map(|captures| captures.name(MATCH_GROUP).map(|found| found.range())),
+            ),
+        }
+    }
+
+    /*
+     * Like find_iter, with the byte ranges of the groups \1 to \9 after each match's own (used by :s)
+     */
+    pub fn captures_iter<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> Box<dyn Iterator<Item = Vec<Option<Range<usize>>>> + 'a> {
+        match &self.engine {
+            Engine::Plain(re) => Box::new(re.captures_iter(text).filter_map(|captures| {
+                self.groups(captures.get(0).map(|found| found.range()), |name| {
+                    captures.name(name).map(|found| found.range())
+                })
+            })),
+            Engine::Backtracking(re) => Box::new(
+                re.captures_iter(text)
+                    .map_while(Result::ok)
+                    .filter_map(|captures| {
+                        self.groups(captures.get(0).map(|found| found.range()), |name| {
+                            captures.name(name).map(|found| found.range())
+                        })
+                    }),
+            ),
+        }
+    }
+
+    // The match (cut down to the text between \zs and \ze) followed by each numbered group, found by name
+    fn groups(
+        &self,
+        whole: Option<Range<usize>>,
+        group: impl Fn(&str) -> Option<Range<usize>>,
+    ) -> Option<Vec<Option<Range<usize>>>> {
+        let found = if self.has_match_group {
+            group(MATCH_GROUP)?
+        } else {
+            whole?
+        };
+        Some(
+            iter::once(Some(found))
+                .chain((1..=9).map(|number| group(format!("g{}", number).as_str())))
+                .collect(),
+        )
+    }
+}
+
+/*
+ * Message bar text for a regex which failed to compile.
+ * Regex errors span several lines, pointing out where the problem is, and only the last one fits
+ */
+fn error_message(e: &regex::Error) -> String {
+    let e = e.to_string();
+    let reason = e.lines().last().unwrap_or_default();
+    invalid(reason.trim_start_matches("error: "))
+}
+
+fn invalid(reason: &str) -> String {
+    format!("Error: invalid pattern: {}", reason)
+}
+
+/*
+ * Split a pattern into tokens, following the mode switches (\v, \m, \M, \V) along the way
+ */
+fn tokenize(pattern: &str) -> Result<Vec<Token>, String> {
+    let mut magic = MagicLevel::Magic;
+    let mut tokens = vec![];
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        // A backslash at the very end stands for itself
+        let (ch, escaped) = match ch {
+            '\\' => chars.next().map_or((ch, false), |next| (next, true)),
+            _ => (ch, false),
+        };
+
+        // Letters and digits after a backslash mean the same thing in every mode
+        if escaped && (ch.is_ascii_alphanumeric() || ch == '_') {
+            let token = match ch {
+                'v' | 'm' | 'M' | 'V' => {
+                    magic = match ch {
+                        'v' => MagicLevel::VeryMagic,
+                        'm' => MagicLevel::Magic,
+                        'M' => MagicLevel::NoMagic,
+                        _ => MagicLevel::VeryNoMagic,
+                    };
+                    continue;
+                }
+                '1'..='9' => Token::Backref(ch as usize - '0' as usize),
+                'z' => match chars.next() {
+                    Some('s') => Token::MatchStart,
+                    Some('e') => Token::MatchEnd,
+                    _ => return Err(invalid("unsupported \\z item")),
+                },
+                // Items also matching line breaks
+                '_' => match chars.next() {
+                    Some('.') => Token::Atom(String::from("(?s:.)")),
+                    Some('s') => Token::Atom(String::from("\\s")),
+                    _ => return Err(invalid("unsupported \\_ item")),
+                },
+                _ => Token::Atom(String::from(
+                    char_escape(ch).ok_or(invalid(format!("unsupported \\{}", ch).as_str()))?,
+                )),
+            };
+            tokens.push(token);
+            continue;
+        }
+
+        // Whether anything else has its special meaning depends on the mode, and is swapped by a backslash
+        let special = match ch {
+            '^' | '$' => !escaped,
+            '.' | '*' | '[' | '~' => {
+                escaped != matches!(magic, MagicLevel::VeryMagic | MagicLevel::Magic)
+            }
+            '(' | ')' | '|' | '{' | '+' | '?' | '=' | '@' | '<' | '>' | '%' | '&' => {
+                escaped != (magic == MagicLevel::VeryMagic)
+            }
+            _ => false,
+        };
+        if !special {
+            tokens.push(literal(ch));
+            continue;
+        }
+        let token = match ch {
+            '^' => Token::Bol,
+            '$' => Token::Eol,
+            '.' => Token::Atom(String::from(".")),
+            // There is no previous substitute string for ~ to stand for, so it matches itself
+            '~' => literal(ch),
+            '*' => Token::Multi(String::from("*"), ch),
+            '+' => Token::Multi(String::from("+"), ch),
+            '?' | '=' => Token::Multi(String::from("?"), ch),
+            '{' => Token::Multi(brace(&mut chars)?, ch),
+            // A [ without a closing ] stands for itself
+            '[' => {
+                let mut rest = chars.clone();
+                match collection(&mut rest) {
+                    Some(class) => {
+                        chars = rest;
+                        Token::Atom(class)
+                    }
+                    None => literal(ch),
+                }
+            }
+            '(' => Token::Open(true),
+            ')' => Token::Close,
+            '|' => Token::Alt,
+            '<' => Token::Atom(String::from("\\b{start}")),
+            '>' => Token::Atom(String::from("\\b{end}")),
+            '@' => Token::Look(lookaround(&mut chars)?),
+            '%' => match chars.next() {
+                Some('(') => Token::Open(false),
+                Some('^') => Token::Atom(String::from("\\A")),
+                Some('$') => Token::Atom(String::from("\\z")),
+                _ => return Err(invalid("unsupported \\% item")),
+            },
+            _ => return Err(invalid(format!("unsupported \\{}", ch).as_str())),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn literal(ch: char) -> Token {
+    Token::Atom(regex::escape(ch.to_string().as_str()))
+}
+
+/*
+ * Regex for a backslash and letter, such as \d or \t. Classes which don't match a character
+ * don't match line breaks either, like in Vim
+ */
+fn char_escape(ch: char) -> Option<&'static str> {
+    let class = match ch {
+        'n' => "\\n",
+        't' => "\\t",
+        'e' => "\\x1B",
+        'r' => "\\r",
+        's' => "[ \\t]",
+        'S' => "[^ \\t\\n]",
+        'd' => "[0-9]",
+        'D' => "[^0-9\\n]",
+        'w' => "[0-9A-Za-z_]",
+        'W' => "[^0-9A-Za-z_\\n]",
+        'a' => "[A-Za-z]",
+        'A' => "[^A-Za-z\\n]",
+        'l' => "[a-z]",
+        'L' => "[^a-z\\n]",
+        'u' => "[A-Z]",
+        'U' => "[^A-Z\\n]",
+        'x' => "[0-9A-Fa-f]",
+        'X' => "[^0-9A-Fa-f\\n]",
+        'o' => "[0-7]",
+        'O' => "[^0-7\\n]",
+        'h' => "[A-Za-z_]",
+        'H' => "[^A-Za-z_\\n]",
+        _ => return None,
+    };
+    Some(class)
+}
+
+/*
+ * Quantifier for the body of \{n,m} (up to the closing } or \}). \{-n,m} matches as few as possible,
+ * and leaving out n or m leaves that end open
+ */
+fn brace(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut body = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some('\\') if chars.next_if_eq(&'}').is_some() => break,
+            Some(ch) if ch.is_ascii_digit() || ch == ',' || ch == '-' => body.push(ch),
+            _ => return Err(invalid("unclosed \\{")),
+        }
+    }
+    let (lazy, body) = match body.strip_prefix('-') {
+        Some(body) => (true, body),
+        None => (false, body.as_str()),
+    };
+    let quantifier = match body.split_once(',') {
+        None if body.is_empty() => String::from("*"),
+        None => format!("{{{}}}", body),
+        Some(("", "")) => String::from("*"),
+        Some((min, max)) => format!("{{{},{}}}", if min.is_empty() { "0" } else { min }, max),
+    };
+    Ok(if lazy { quantifier + "?" } else { quantifier })
+}
+
+/*
+ * Translate a collection (ex. [a-z] or [^[:space:]]) after its [, or None if it isn't closed.
+ * Negated collections don't match line breaks, like in Vim
+ */
+fn collection(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut class = String::from("[");
+    if chars.next_if_eq(&'^').is_some() {
+        class.push_str("^\\n");
+    }
+    // A ] straight after the [ is part of the collection
+    if chars.next_if_eq(&']').is_some() {
+        class.push_str("\\]");
+    }
+    loop {
+        match chars.next()? {
+            ']' => break,
+            '-' => class.push('-'),
+            // Named classes, ex. [:alpha:]
+            '[' => {
+                let rest: String = chars.clone().take_while(|ch| *ch != ']').collect();
+                match rest
+                    .strip_prefix(':')
+                    .and_then(|rest| rest.strip_suffix(':'))
+                {
+                    Some(name) if name.chars().all(|ch| ch.is_ascii_lowercase()) => {
+                        class.push_str(format!("[:{}:]", name).as_str());
+                        chars.nth(rest.chars().count());
+                    }
+                    _ => class.push_str("\\["),
+                }
+            }
+            '\\' => match chars.next()? {
+                'n' => class.push_str("\\n"),
+                't' => class.push_str("\\t"),
+                'e' => class.push_str("\\x1B"),
+                'r' => class.push_str("\\r"),
+                escaped @ ('\\' | ']' | '^' | '-') => {
+                    class.push_str(regex::escape(escaped.to_string().as_str()).as_str())
+                }
+                // Other backslashes stand for themselves
+                ch => {
+                    class.push_str("\\\\");
+                    class.push_str(regex::escape(ch.to_string().as_str()).as_str());
+                }
+            },
+            ch => class.push_str(regex::escape(ch.to_string().as_str()).as_str()),
+        }
+    }
+    class.push(']');
+    Some(class)
+}
+
+/*
+ * Kind of lookaround after \@: = and ! look ahead, <= and <! look behind, and > matches without backtracking.
+ * A limit on how far to look behind (ex. \@123<=) is ignored
+ */
+fn lookaround(chars: &mut Peekable<Chars>) -> Result<&'static str, String> {
+    while chars.next_if(|ch| ch.is_ascii_digit()).is_some() {}
+    let look = match (chars.next(), chars.peek()) {
+        (Some('='), _) => "(?=",
+        (Some('!'), _) => "(?!",
+        (Some('>'), _) => "(?>",
+        (Some('<'), Some('=')) => "(?<=",
+        (Some('<'), Some('!')) => "(?<!",
+        _ => return Err(invalid("unsupported \\@ item")),
+    };
+    if look.starts_with("(?<") {
+        chars.next();
+    }
+    Ok(look)
+}
+
+/*
+ * Put the tokens back together as a regex. Capturing groups are named after their number,
+ * so wrapping the text between \zs and \ze in a group of its own doesn't change what \1 refers to.
+ * The text after \ze becomes a lookahead, as in Vim it only has to follow the match
+ */
+fn translate(tokens: &[Token]) -> Result<String, String> {
+    let mut groups = vec![Group::default()];
+    let mut num_groups = 0;
+    // Atoms of the pattern before \zs and \ze
+    let (mut match_start, mut match_end) = (None, None);
+    for (idx, token) in tokens.iter().enumerate() {
+        let nested = groups.len() > 1;
+        let group = groups.last_mut().unwrap();
+        match token {
+            Token::Atom(atom) => group.atoms.push(atom.clone()),
+            Token::Multi(quantifier, ch) => match group.atoms.pop() {
+                Some(atom) => group.atoms.push(format!("(?:{}){}", atom, quantifier)),
+                // Nothing to repeat (ex. * at the start of the pattern), so it stands for itself
+                None => group.atoms.push(regex::escape(ch.to_string().as_str())),
+            },
+            Token::Look(look) => {
+                let atom = group.atoms.pop().ok_or(invalid("\\@ follows nothing"))?;
+                group.atoms.push(format!("{}{})", look, atom));
+            }
+            Token::Open(capturing) => {
+                let number = capturing.then(|| {
+                    num_groups += 1;
+                    num_groups
+                });
+                groups.push(Group {
+                    number,
+                    ..Group::default()
+                });
+            }
+            Token::Close => {
+                if groups.len() == 1 {
+                    return Err(invalid("unmatched \\)"));
+                }
+                let group = groups.pop().unwrap();
+                let atom = match group.number {
+                    Some(number) => format!("(?P<g{}>{})", number, group.finish()),
+                    None => format!("(?:{})", group.finish()),
+                };
+                groups.last_mut().unwrap().atoms.push(atom);
+            }
+            Token::Alt => {
+                let branch = mem::take(&mut group.atoms).concat();
+                group.branches.push(branch);
+            }
+            Token::Bol => {
+                let anchor = group.atoms.is_empty();
+                group
+                    .atoms
+                    .push(String::from(if anchor { "^" } else { "\\^" }));
+            }
+            Token::Eol => {
+                let anchor = matches!(
+                    tokens.get(idx + 1),
+                    None | Some(Token::Close | Token::Alt | Token::MatchEnd)
+                );
+                group
+                    .atoms
+                    .push(String::from(if anchor { "$" } else { "\\$" }));
+            }
+            Token::Backref(number) => {
+                if *number > num_groups {
+                    return Err(invalid(
+                        format!("no group {} for \\{}", number, number).as_str(),
+                    ));
+                }
+                group.atoms.push(format!("\\k<g{}>", number));
+            }
+            Token::MatchStart | Token::MatchEnd => {
+                if nested || !group.branches.is_empty() {
+                    return Err(invalid(
+                        "\\zs and \\ze can't be inside a group or after \\|",
+                    ));
+                }
+                let mark = Some(group.atoms.len());
+                match token {
+                    Token::MatchStart => match_start = mark,
+                    _ => match_end = mark,
+                }
+            }
+        }
+    }
+    if groups.len() > 1 {
+        return Err(invalid("unmatched \\("));
+    }
+    let group = groups.pop().unwrap();
+    if match_start.is_none() && match_end.is_none() {
+        return Ok(group.finish());
+    }
+    if !group.branches.is_empty() {
+        return Err(invalid(
+            "\\zs and \\ze can't be inside a group or after \\|",
+        ));
+    }
+    let atoms = &group.atoms;
+    let start = match_start.unwrap_or(0);
+    let end = match_end.unwrap_or(atoms.len()).max(start);
+    let suffix = atoms[end..].concat();
+    Ok(format!(
+        "(?:{})(?P<{}>{}){}",
+        atoms[..start].concat(),
+        MATCH_GROUP,
+        atoms[start..end].concat(),
+        if suffix.is_empty() {
+            suffix
+        } else {
+            format!("(?={})", suffix)
+        }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Text of each match of a Vim pattern
+    fn found<'a>(pattern: &str, text: &'a str) -> Vec<&'a str> {
+        let pattern = Pattern::new(pattern, false).unwrap();
+        pattern.find_iter(text).map(|range| &text[range]).collect()
+    }
+
+    fn error(pattern: &str) -> String {
+        Pattern::new(pattern, false).unwrap_err()
+    }
+
+    #[test]
+    fn magic_levels() {
+        // Magic: only . * [ ~ ^ $ are special without a backslash
+        assert_eq!(found("a.c", "abc a.c"), ["abc", "a.c"]);
+        assert_eq!(found("ab*", "a abb"), ["a", "abb"]);
+        assert_eq!(found("a+", "aa a+"), ["a+"]);
+        assert_eq!(found("a\\+", "aa a+"), ["aa", "a"]);
+        assert_eq!(found("\\(ab\\)\\{2}", "abab ab"), ["abab"]);
+        // Very magic: every punctuation character is special
+        assert_eq!(found("\\v(ab)+", "abab ab"), ["abab", "ab"]);
+        assert_eq!(found("\\va|b", "abc"), ["a", "b"]);
+        assert_eq!(found("\\v\\(a\\)", "(a) a"), ["(a)"]);
+        // No magic: . and * need a backslash
+        assert_eq!(found("\\M.*", "ab .*"), [".*"]);
+        assert_eq!(found("\\Ma\\.", "ab a."), ["ab", "a."]);
+        // Very no magic: everything is literal
+        assert_eq!(found("\\V.*", "ab .*"), [".*"]);
+        assert_eq!(found("\\V[a]", "a [a]"), ["[a]"]);
+        // Modes can change part way through
+        assert_eq!(found("a.\\V.", "abc ab."), ["ab."]);
+    }
+
+    #[test]
+    fn anchors() {
+        assert_eq!(found("^a", "ab\nab"), ["a", "a"]);
+        assert_eq!(found("b$", "ab\nab"), ["b", "b"]);
+        // Away from the ends of a branch, ^ and $ are literal
+        assert_eq!(found("a^b$c", "a^b$c abc"), ["a^b$c"]);
+        assert_eq!(found("x$\\|^y", "ax\ny"), ["x", "y"]);
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(found("\\<foo\\>", "foo food foo"), ["foo", "foo"]);
+        assert_eq!(found("\\<foo", "foo food afoo"), ["foo", "foo"]);
+        assert_eq!(found("\\vfoo>", "foo afoo food"), ["foo", "foo"]);
+    }
+
+    #[test]
+    fn multis() {
+        assert_eq!(found("a\\{2,3}", "a aa aaaa"), ["aa", "aaa"]);
+        assert_eq!(found("a\\{2}", "aaaaa"), ["aa", "aa"]);
+        assert_eq!(found("a\\{,2}b", "aaab"), ["aab"]);
+        // \{-n,m} matches as few as possible
+        assert_eq!(found("a\\{-1,}", "aaa"), ["a", "a", "a"]);
+        assert_eq!(found("<.\\{-}>", "<a><b>"), ["<a>", "<b>"]);
+        assert_eq!(found("\\va{-2,}", "aaaaa"), ["aa", "aa"]);
+        assert_eq!(found("a\\=b", "b ab"), ["b", "ab"]);
+        assert_eq!(found("a\\?b", "b ab"), ["b", "ab"]);
+        // Nothing to repeat, so it stands for itself
+        assert_eq!(found("*a", "a *a"), ["*a"]);
+    }
+
+    #[test]
+    fn collections_and_classes() {
+        assert_eq!(found("[abc]\\+", "xabcx"), ["abc"]);
+        // Negated collections don't cross line breaks
+        assert_eq!(found("[^a]\\+", "bb\ncc"), ["bb", "cc"]);
+        assert_eq!(found("[[:digit:]x]\\+", "a1x2b"), ["1x2"]);
+        assert_eq!(found("\\d\\+", "a12b3"), ["12", "3"]);
+        assert_eq!(found("\\a\\+", "ab1cd"), ["ab", "cd"]);
+        // An unclosed [ is literal
+        assert_eq!(found("[", "a[b"), ["["]);
+        assert_eq!(found("\\%(ab\\)*c", "ababc c"), ["ababc", "c"]);
+    }
+
+    #[test]
+    fn match_start_and_end() {
+        assert_eq!(found("foo\\zsbar", "foobar bar"), ["bar"]);
+        assert_eq!(found("foo\\zebar", "foobar foo"), ["foo"]);
+        assert_eq!(found("a\\zsb\\zec", "abc ab bc"), ["b"]);
+        // Text after \ze is only looked at, so the next match can start in it
+        assert_eq!(found("a\\zeaa", "aaaa"), ["a", "a"]);
+        assert_eq!(found("foo\\ze$", "foo\nfoobar"), ["foo"]);
+        // Groups keep their numbers around the match
+        assert_eq!(found("\\(a\\)\\zs\\1", "aa ab"), ["a"]);
+    }
+
+    #[test]
+    fn backreferences() {
+        assert_eq!(found("\\(a\\|b\\)\\1", "ab aa bb"), ["aa", "bb"]);
+        assert_eq!(
+            found("\\v(\\w+) \\1", "the the cat cat dog"),
+            ["the the", "cat cat"]
+        );
+        // Non-capturing groups don't count
+        assert_eq!(found("\\%(x\\)\\(y\\)\\1", "xyy xyx"), ["xyy"]);
+    }
+
+    #[test]
+    fn lookaround() {
+        assert_eq!(found("foo\\(bar\\)\\@=", "foobar foobaz"), ["foo"]);
+        assert_eq!(found("foo\\(bar\\)\\@!", "foobar foobaz"), ["foo"]);
+        assert_eq!(found("\\(foo\\)\\@<=bar", "foobar bazbar"), ["bar"]);
+        assert_eq!(found("\\(foo\\)\\@<!bar", "foobar bazbar"), ["bar"]);
+        assert_eq!(found("\\v(foo)@<!bar", "foobar xbar"), ["bar"]);
+    }
+
+    #[test]
+    fn case() {
+        let pattern = Pattern::new("foo", true).unwrap();
+        assert_eq!(pattern.find_iter("FOO foo").count(), 2);
+        let pattern = Pattern::literal("a.b", false).unwrap();
+        assert_eq!(
+            pattern.find_iter("a.b axb a.b").collect::<Vec<_>>(),
+            [0..3, 8..11]
+        );
+    }
+
+    #[test]
+    fn captures() {
+        let pattern = Pattern::new("\\(a\\)\\(b\\)\\=\\zsc", false).unwrap();
+        let captures: Vec<_> = pattern.captures_iter("abc ac").collect();
+        assert_eq!(captures[0][..3], [Some(2..3), Some(0..1), Some(1..2)]);
+        assert_eq!(captures[1][..3], [Some(5..6), Some(4..5), None]);
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(error("\\(").contains("unmatched \\("));
+        assert!(error("\\)").contains("unmatched \\)"));
+        assert!(error("\\1").contains("no group 1"));
+        assert!(error("a\\|b\\zsc").contains("\\zs and \\ze"));
+        assert!(error("\\(a\\zsb\\)").contains("\\zs and \\ze"));
+    }
+}
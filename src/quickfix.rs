@@ -0,0 +1,219 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Lines of compiler/tool output naming a location: "file:line:col: message" (column optional). Paths may start with a drive (ex. C:\)
+static ERROR_FORMAT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^\s*(?:-->\s*)?((?:[A-Za-z]:[\\/])?[^:\s][^:]*):(\d+):(?:(\d+):?)?\s*(.*)$")
+        .unwrap()
+});
+// Lines starting a compiler message whose location follows on a later line (ex. "error[E0308]: mismatched types")
+static MESSAGE_FORMAT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:error|warning)(?:\[\w+\])?:").unwrap());
+
+/*
+ * A position in a file, along with the text to show for it (ex. the line containing a match or an error message)
+ */
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub path: String,
+    pub line: usize, // 1-indexed file line
+    pub col: usize,  // 1-indexed character column
+    pub text: String,
+}
+
+impl Location {
+    // How the location is listed in a list window
+    pub fn to_line(&self) -> String {
+        format!("{}:{}:{}: {}", self.path, self.line, self.col, self.text)
+    }
+
+    // Compiler messages name their level first (ex. "warning: unused variable")
+    pub fn is_warning(&self) -> bool {
+        self.text.starts_with("warning")
+    }
+}
+
+/*
+ * Which list a command or list window refers to:
+ * the global quickfix list, or the location list belonging to the window with the given id
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListSource {
+    Quickfix,
+    Location(usize),
+}
+
+impl ListSource {
+    pub fn label(&self) -> &str {
+        match self {
+            ListSource::Quickfix => "Quickfix List",
+            ListSource::Location(_) => "Location List",
+        }
+    }
+}
+
+/*
+ * A list of locations to step through (ex. compiler errors or search results)
+ */
+#[derive(Debug, Clone, Default)]
+pub struct QuickfixList {
+    pub title: String, // Command which filled the list
+    pub entries: Vec<Location>,
+    pub current: Option<usize>, // Index of the entry last jumped to
+}
+
+impl QuickfixList {
+    pub fn new(title: &str, entries: Vec<Location>) -> Self {
+        Self {
+            title: String::from(title),
+            entries,
+            current: None,
+        }
+    }
+
+    /*
+     * Index of the entry after (or before) the current one
+     */
+    pub fn step(&self, forwards: bool) -> Result<usize, &'static str> {
+        if self.entries.is_empty() {
+            return Err("Error: No entries");
+        }
+        match (self.current, forwards) {
+            (None, _) => Ok(0),
+            (Some(current), true) if current + 1 < self.entries.len() => Ok(current + 1),
+            (Some(current), false) if current > 0 => Ok(current - 1),
+            _ => Err("Error: No more items"),
+        }
+    }
+}
+
+/*
+ * Collect every location named in some tool output. Lines without a location are skipped.
+ * Locations with no message of their own (ex. cargo's "--> file:line:col") take the message they follow
+ */
+pub fn parse_errors(text: &str) -> Vec<Location> {
+    let mut message = "";
+    text.lines()
+        .filter_map(|line| {
+            if MESSAGE_FORMAT.is_match(line) {
+                message = line;
+                return None;
+            }
+            let captures = ERROR_FORMAT.captures(line)?;
+            let text = match captures[4].trim() {
+                "" => message,
+                text => text,
+            };
+            Some(Location {
+                path: String::from(&captures[1]),
+                line: captures[2].parse().ok()?,
+                col: captures
+                    .get(3)
+                    .and_then(|col| col.as_str().parse().ok())
+                    .unwrap_or(1),
+                text: String::from(text),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<(String, usize, usize, String, bool)> {
+        parse_errors(text)
+            .into_iter()
+            .map(|location| {
+                let is_warning = location.is_warning();
+                (
+                    location.path,
+                    location.line,
+                    location.col,
+                    location.text,
+                    is_warning,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_cargo_locations() {
+        let output = "error[E0425]: cannot find value `x` in this scope\n  --> src/main.rs:3:5\n   |\n3  |     x\n   |     ^ not found in this scope\n\nwarning: unused variable: `y`\n --> src/lib.rs:12:9\n";
+        assert_eq!(
+            parse(output),
+            [
+                (
+                    String::from("src/main.rs"),
+                    3,
+                    5,
+                    String::from("error[E0425]: cannot find value `x` in this scope"),
+                    false
+                ),
+                (
+                    String::from("src/lib.rs"),
+                    12,
+                    9,
+                    String::from("warning: unused variable: `y`"),
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_gcc_locations() {
+        let output = "main.c: In function 'main':\nmain.c:12:3: error: expected ';' before '}' token\nmain.c:4:7: warning: unused variable 'y'\n";
+        assert_eq!(
+            parse(output),
+            [
+                (
+                    String::from("main.c"),
+                    12,
+                    3,
+                    String::from("error: expected ';' before '}' token"),
+                    false
+                ),
+                (
+                    String::from("main.c"),
+                    4,
+                    7,
+                    String::from("warning: unused variable 'y'"),
+                    true
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_locations_without_a_column() {
+        assert_eq!(
+            parse("script.py:7: bad indentation\nnot a location\n"),
+            [(
+                String::from("script.py"),
+                7,
+                1,
+                String::from("bad indentation"),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn keeps_windows_drives_in_paths() {
+        let output = "C:\\src\\main.rs:12:5: error: boom\n  --> D:/proj/lib.rs:3:1\n";
+        assert_eq!(
+            parse(output),
+            [
+                (
+                    String::from("C:\\src\\main.rs"),
+                    12,
+                    5,
+                    String::from("error: boom"),
+                    false
+                ),
+                (String::from("D:/proj/lib.rs"), 3, 1, String::new(), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use crate::model::LineEdit;
+use crate::pattern::Pattern;
+use core::ops::Range;
+use ropey::Rope;
+use std::cmp;
+use std::str::Chars;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+// Characters searched at a time. Matches are sent back after each block
+const BLOCK_SIZE: usize = 256 * 1024;
+// Lines a match may run on for past the block it starts in. Matches spanning more lines are cut short
+const MAX_MATCH_LINES: usize = 100;
+
+/*
+ * Settings for how search patterns are matched
+ */
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub ignorecase: bool, // Letters match either case
+    pub smartcase: bool,  // With ignorecase, patterns with an uppercase letter still match case
+    pub literal: bool,    // Patterns are plain text rather than regexes
+}
+
+/*
+ * Build the pattern to search for, in Vim's regex syntax (or as plain text with the literal option).
+ * ^ and $ match at the start and end of every line, and patterns may match across lines (ex. "foo\nbar").
+ * \c anywhere in the pattern ignores case and \C matches case, whatever the options say.
+ * Invalid patterns give the reason, ready for the message bar
+ */
+pub fn compile(pattern: &str, options: SearchOptions) -> Result<Pattern, String> {
+    let (pattern, case_override) = strip_case_flags(pattern);
+    let ignore_case = case_override
+        .unwrap_or(options.ignorecase && !(options.smartcase && has_uppercase(pattern.as_str())));
+    if options.literal {
+        Pattern::literal(pattern.as_str(), ignore_case)
+    } else {
+        Pattern::new(pattern.as_str(), ignore_case)
+    }
+}
+
+/*
+ * Remove \c and \C from a pattern, returning whether the last one asks to ignore case.
+ * Escaped backslashes (\\c) are left alone
+ */
+fn strip_case_flags(pattern: &str) -> (String, Option<bool>) {
+    let mut stripped = String::with_capacity(pattern.len());
+    let mut case_override = None;
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            stripped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('c') => case_override = Some(true),
+            Some('C') => case_override = Some(false),
+            Some(escaped) => {
+                stripped.push(ch);
+                stripped.push(escaped);
+            }
+            None => stripped.push(ch),
+        }
+    }
+    (stripped, case_override)
+}
+
+// Does the pattern contain an uppercase letter? Escapes like \S or \W don't count
+fn has_uppercase(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if ch.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/*
+ * A :s command, ex. :s/foo/bar/g on the current line or :%s/foo/bar/ on every line.
+ * Any punctuation can stand in for the /, and is part of the pattern or replacement when escaped
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Substitute {
+    pub whole_file: bool,
+    pub pattern: String, // Empty to use the last search
+    replacement: String,
+    pub global: bool, // Replace every match on a line, not just the first
+}
+
+impl Substitute {
+    // None for commands other than :s, and Err for an :s command which can't be understood
+    pub fn parse(command: &str) -> Option<Result<Self, String>> {
+        let (whole_file, rest) = match command.strip_prefix(":%") {
+            Some(rest) => (true, rest),
+            None => (false, command.strip_prefix(':')?),
+        };
+        let rest = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))?;
+        let mut chars = rest.chars();
+        let delimiter = chars.next()?;
+        if delimiter.is_alphanumeric() || delimiter.is_whitespace() {
+            return None;
+        }
+        if matches!(delimiter, '\\' | '"' | '|') {
+            return Some(Err(format!(
+                "Error: {} can't separate a substitution",
+                delimiter
+            )));
+        }
+        let pattern = Self::part(&mut chars, delimiter);
+        let replacement = Self::part(&mut chars, delimiter);
+        let global = match chars.as_str() {
+            "" => false,
+            "g" => true,
+            flags => return Some(Err(format!("Error: unknown flags {}", flags))),
+        };
+        Some(Ok(Self {
+            whole_file,
+            pattern,
+            replacement,
+            global,
+        }))
+    }
+
+    // Text up to the next delimiter (or the end), with escaped delimiters unescaped
+    fn part(chars: &mut Chars, delimiter: char) -> String {
+        let mut part = String::new();
+        while let Some(ch) = chars.next() {
+            if ch == delimiter {
+                break;
+            }
+            if ch != '\\' {
+                part.push(ch);
+                continue;
+            }
+            match chars.next() {
+                Some(escaped) if escaped == delimiter => part.push(escaped),
+                Some(escaped) => {
+                    part.push(ch);
+                    part.push(escaped);
+                }
+                None => part.push(ch),
+            }
+        }
+        part
+    }
+
+    /*
+     * Text replacing a match, given the text searched and the match and group ranges from Pattern::captures_iter.
+     * & and \0 stand for the whole match, \1 to \9 for its groups, \r (or \n) for a line break and \t for a tab
+     */
+    pub fn expand(&self, text: &str, groups: &[Option<Range<usize>>]) -> String {
+        let group = |number: usize| {
+            groups
+                .get(number)
+                .cloned()
+                .flatten()
+                .map_or("", |range| &text[range])
+        };
+        let mut expanded = String::new();
+        let mut chars = self.replacement.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '&' => expanded.push_str(group(0)),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => {
+                        expanded.push_str(group(digit as usize - '0' as usize));
+                    }
+                    Some('r' | 'n') => expanded.push('\n'),
+                    Some('t') => expanded.push('\t'),
+                    Some(escaped) => expanded.push(escaped),
+                    None => expanded.push(ch),
+                },
+                _ => expanded.push(ch),
+            }
+        }
+        expanded
+    }
+}
+
+/*
+ * A search through a buffer running on a background thread, over a snapshot of its text
+ * (clones of a rope share its text, so taking one is cheap). Matches are sent back a block at a time,
+ * so the first ones can be shown before the search finishes
+ */
+#[derive(Debug)]
+pub struct SearchJob {
+    receiver: Receiver<Vec<Range<usize>>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl SearchJob {
+    pub fn spawn(re: Pattern, rope: Rope) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let job_cancelled = Arc::clone(&cancelled);
+
+        thread::spawn(move || {
+            find_matches(&rope, &re, 0..rope.len_chars(), |matches| {
+                // Receiver is gone once the results are no longer wanted
+                !job_cancelled.load(Ordering::Relaxed) && sender.send(matches).is_ok()
+            });
+        });
+
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    /*
+     * Collect the matches found since the last call, in order.
+     * The second value is false once the search has finished
+     */
+    pub fn poll(&self) -> (Vec<Range<usize>>, bool) {
+        let mut matches = vec![];
+        loop {
+            match self.receiver.try_recv() {
+                Ok(block_matches) => matches.extend(block_matches),
+                Err(TryRecvError::Empty) => return (matches, true),
+                Err(TryRecvError::Disconnected) => return (matches, false),
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/*
+ * Search the characters in range for matches, a block of lines at a time, passing each block's matches
+ * to found until it returns false. Each block is searched along with the lines after it, so matches starting
+ * in the block may run on past it. The range should start at the start of a line, so ^ only matches where it should
+ */
+pub fn find_matches(
+    rope: &Rope,
+    re: &Pattern,
+    range: Range<usize>,
+    mut found: impl FnMut(Vec<Range<usize>>) -> bool,
+) {
+    let mut start = range.start;
+    while start < range.end {
+        // Blocks hold whole lines, and at least one of them
+        let first_line = rope.char_to_line(start);
+        let end_line = rope.char_to_line(cmp::min(start + BLOCK_SIZE, range.end)) + 1;
+        let end = cmp::min(line_start(rope, end_line), range.end);
+        // Search from the start of the line, as a match running on from the last block may have ended part way through it
+        let text_start = rope.line_to_char(first_line);
+        let text_end = line_start(rope, end_line + MAX_MATCH_LINES);
+        let text = rope.slice(text_start..text_end).to_string();
+
+        let mut matches = vec![];
+        let mut next_start = end;
+        let (mut byte_idx, mut char_idx) = (0, text_start);
+        for found_range in re.find_iter(&text) {
+            char_idx += text[byte_idx..found_range.start].chars().count();
+            byte_idx = found_range.start;
+            // Empty matches at the end of the text still count, as nothing comes after them
+            if char_idx > end || (char_idx == end && end < rope.len_chars()) {
+                break;
+            }
+            if char_idx < start {
+                continue;
+            }
+            let match_end = char_idx + text[found_range].chars().count();
+            matches.push(char_idx..match_end);
+            next_start = cmp::max(next_start, match_end);
+        }
+        if !found(matches) {
+            return;
+        }
+        start = next_start;
+    }
+}
+
+/*
+ * Bring the matches in a buffer up to date after edits, without searching the whole buffer again.
+ * Matches are moved along with the text around them, and the text near each edit is searched again
+ * (starting far enough back to find matches spanning several lines which the edit completed)
+ */
+pub fn update_matches(
+    matches: &mut Vec<Range<usize>>,
+    rope: &Rope,
+    re: &Pattern,
+    edits: &[LineEdit],
+) {
+    // Text changed by the edits, as it is after them
+    let mut changed: Vec<Range<usize>> = vec![];
+    for edit in edits {
+        let removed_end = edit.start + edit.chars_removed;
+        let shift = |idx: usize| idx - edit.chars_removed + edit.chars_added;
+        // Matches touching the edit are found again below
+        matches.retain(|range| range.end < edit.start || range.start > removed_end);
+        let first_after = matches.partition_point(|range| range.start <= removed_end);
+        for range in matches[first_after..].iter_mut() {
+            *range = shift(range.start)..shift(range.end);
+        }
+        let mut edited = edit.start..edit.start + edit.chars_added;
+        changed.retain(|range| {
+            let touches = range.end >= edit.start && range.start <= removed_end;
+            if touches {
+                edited.start = cmp::min(edited.start, range.start);
+                edited.end = cmp::max(edited.end, shift(cmp::max(range.end, removed_end)));
+            }
+            !touches
+        });
+        for range in changed.iter_mut().filter(|range| range.start > removed_end) {
+            *range = shift(range.start)..shift(range.end);
+        }
+        changed.push(edited);
+    }
+    changed.sort_by_key(|range| range.start);
+
+    for edited in changed {
+        // Whole lines around the edit, widened to take in any match crossing their ends
+        let first_line = rope
+            .char_to_line(edited.start)
+            .saturating_sub(MAX_MATCH_LINES);
+        let mut start = rope.line_to_char(first_line);
+        let mut end = line_start(rope, rope.char_to_line(edited.end) + 1);
+        let first = matches.partition_point(|range| range.end < start);
+        if let Some(range) = matches.get(first)
+            && range.start < start
+        {
+            start = rope.line_to_char(rope.char_to_line(range.start));
+        }
+        let last = matches.partition_point(|range| range.start < end);
+        if last > first {
+            end = cmp::max(end, matches[last - 1].end);
+        }
+
+        // A new match may run on past the lines searched, over matches found before. Those may have hidden
+        // other matches, so keep searching until the new matches end where the old ones did
+        let mut found;
+        let mut last;
+        loop {
+            found = vec![];
+            find_matches(rope, re, start..end, |block_matches| {
+                found.extend(block_matches);
+                true
+            });
+            let found_end = found.last().map_or(end, |range| cmp::max(range.end, end));
+            last = match found_end == rope.len_chars() {
+                true => matches.len(),
+                false => matches.partition_point(|range| range.start < found_end),
+            };
+            match matches[..last].last() {
+                Some(range) if range.end > end => end = range.end,
+                _ => break,
+            }
+        }
+        let first = matches.partition_point(|range| range.start < start);
+        matches.splice(first..cmp::max(first, last), found);
+    }
+}
+
+// Character index a line starts at, or the end of the text for lines past the last
+fn line_start(rope: &Rope, line_idx: usize) -> usize {
+    if line_idx >= rope.len_lines() {
+        rope.len_chars()
+    } else {
+        rope.line_to_char(line_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_default(pattern: &str) -> Pattern {
+        compile(pattern, SearchOptions::default()).unwrap()
+    }
+
+    fn all_matches(rope: &Rope, re: &Pattern) -> Vec<Range<usize>> {
+        let mut matches = vec![];
+        find_matches(rope, re, 0..rope.len_chars(), |found| {
+            matches.extend(found);
+            true
+        });
+        matches
+    }
+
+    // Replace the chars_removed characters at start with text, recording the edit like EditorModel does
+    fn apply_edit(rope: &mut Rope, start: usize, chars_removed: usize, text: &str) -> LineEdit {
+        let line = rope.char_to_line(start);
+        let removed = rope.char_to_line(start + chars_removed) - line + 1;
+        rope.remove(start..start + chars_removed);
+        rope.insert(start, text);
+        let chars_added = text.chars().count();
+        LineEdit {
+            line,
+            removed,
+            added: rope.char_to_line(start + chars_added) - line + 1,
+            start,
+            chars_removed,
+            chars_added,
+        }
+    }
+
+    // Each match of the :s command's pattern in the text, replaced
+    fn substituted(command: &str, text: &str) -> Vec<String> {
+        let substitute = Substitute::parse(command).unwrap().unwrap();
+        let re = compile_default(substitute.pattern.as_str());
+        re.captures_iter(text)
+            .map(|groups| substitute.expand(text, &groups))
+            .collect()
+    }
+
+    #[test]
+    fn parses_substitute_commands() {
+        let substitute = Substitute::parse(":%s/a\\/b/c d/g").unwrap().unwrap();
+        assert!(substitute.whole_file && substitute.global);
+        assert_eq!(substitute.pattern, "a/b");
+        assert_eq!(substitute.replacement, "c d");
+        let substitute = Substitute::parse(":s#a/b#\\#").unwrap().unwrap();
+        assert!(!substitute.whole_file && !substitute.global);
+        assert_eq!(substitute.pattern, "a/b");
+        assert_eq!(substitute.replacement, "#");
+        // A missing replacement deletes the match
+        assert_eq!(Substitute::parse(":s/a").unwrap().unwrap().replacement, "");
+        assert!(Substitute::parse(":s/a/b/x").unwrap().is_err());
+        assert!(Substitute::parse(":s|a|b|").unwrap().is_err());
+        for command in [":set nu", ":sp", ":s", ":w", ":%"] {
+            assert!(Substitute::parse(command).is_none(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn expands_replacements() {
+        assert_eq!(substituted(":s/o\\+/[&]/", "foo bo"), ["[oo]", "[o]"]);
+        assert_eq!(
+            substituted(":s/\\(\\w\\+\\) \\(\\w\\+\\)/\\2 \\1/", "one two"),
+            ["two one"]
+        );
+        assert_eq!(substituted(":s/, /\\r\\t\\&/", "a, b"), ["\n\t&"]);
+        assert_eq!(substituted(":s/a\\zsb/\\0\\0/", "ab b"), ["bb"]);
+    }
+
+    #[test]
+    fn finds_matches_on_each_line() {
+        let rope = Rope::from_str("foo bar\nbar foo\nbar\n");
+        assert_eq!(all_matches(&rope, &compile_default("foo")), [0..3, 12..15]);
+        assert_eq!(
+            all_matches(&rope, &compile_default("^bar")),
+            [8..11, 16..19]
+        );
+        assert_eq!(all_matches(&rope, &compile_default("bar$")), [4..7, 16..19]);
+    }
+
+    #[test]
+    fn finds_matches_across_lines() {
+        let rope = Rope::from_str("one\ntwo\none\ntwo\n");
+        assert_eq!(
+            all_matches(&rope, &compile_default("one\\ntwo")),
+            [0..7, 8..15]
+        );
+        assert_eq!(
+            all_matches(&rope, &compile_default("e\\n\\a")),
+            [2..5, 10..13]
+        );
+    }
+
+    #[test]
+    fn finds_matches_across_block_boundaries() {
+        // Lines of varying length, so block ends fall at different places within the repeated text
+        let mut text = String::new();
+        let mut line = 0;
+        while text.len() < 3 * BLOCK_SIZE {
+            text.push_str(&"x".repeat(line % 37));
+            text.push_str(if line % 5 == 0 { "start\nend\n" } else { "\n" });
+            line += 1;
+        }
+        let rope = Rope::from_str(&text);
+        let re = compile_default("start\\nend");
+        let expected: Vec<_> = text
+            .match_indices("start\nend")
+            .map(|(idx, found)| idx..idx + found.len())
+            .collect();
+        assert_eq!(all_matches(&rope, &re), expected);
+    }
+
+    #[test]
+    fn stops_when_told_to() {
+        let rope = Rope::from_str(&"foo\n".repeat(BLOCK_SIZE));
+        let mut blocks = 0;
+        find_matches(&rope, &compile_default("foo"), 0..rope.len_chars(), |_| {
+            blocks += 1;
+            false
+        });
+        assert_eq!(blocks, 1);
+    }
+
+    #[test]
+    fn updates_matches_around_edits() {
+        let mut rope = Rope::from_str("foo bar\nbaz\nfoo\nfoo\n");
+        let re = compile_default("foo");
+        let mut matches = all_matches(&rope, &re);
+        // Breaking the first match shifts the second one back
+        let edits = [apply_edit(&mut rope, 1, 1, "")];
+        update_matches(&mut matches, &rope, &re, &edits);
+        assert_eq!(matches, [11..14, 15..18]);
+        // Completing a match, and adding one before the others
+        let edits = [
+            apply_edit(&mut rope, 1, 0, "o"),
+            apply_edit(&mut rope, 8, 0, "foo"),
+        ];
+        update_matches(&mut matches, &rope, &re, &edits);
+        assert_eq!(matches, all_matches(&rope, &re));
+        assert_eq!(matches, [0..3, 8..11, 15..18, 19..22]);
+    }
+
+    #[test]
+    fn updates_matches_spanning_lines() {
+        let mut rope = Rope::from_str("a\nb\na\nc\na\nb\n");
+        let re = compile_default("a\\nb");
+        let mut matches = all_matches(&rope, &re);
+        assert_eq!(matches, [0..3, 8..11]);
+        // Changing the line after a match's start completes a match spanning both
+        let edits = [apply_edit(&mut rope, 6, 1, "b")];
+        update_matches(&mut matches, &rope, &re, &edits);
+        assert_eq!(matches, [0..3, 4..7, 8..11]);
+    }
+
+    #[test]
+    fn updates_matches_like_searching_again() {
+        // Random edits to random text, checked against searching the whole text again
+        let mut seed: u64 = 12345;
+        let mut random = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        let chars = ['a', 'b', '\n', 'c'];
+        for pattern in ["ab", "a\\nb", "a\\+", "^b", "b$", "a.*b", "x*"] {
+            let re = compile_default(pattern);
+            for _ in 0..200 {
+                let text: String = (0..random(40)).map(|_| chars[random(4)]).collect();
+                let mut rope = Rope::from_str(&text);
+                let mut matches = all_matches(&rope, &re);
+                let mut edits = vec![];
+                for _ in 0..random(4) + 1 {
+                    let len = rope.len_chars();
+                    let start = random(len + 1);
+                    let chars_removed = random(len - start + 1).min(3);
+                    let added: String = (0..random(3)).map(|_| chars[random(4)]).collect();
+                    edits.push(apply_edit(&mut rope, start, chars_removed, &added));
+                }
+                update_matches(&mut matches, &rope, &re, &edits);
+                assert_eq!(
+                    matches,
+                    all_matches(&rope, &re),
+                    "{:?} in {:?}, edited to {:?}",
+                    pattern,
+                    text,
+                    rope.to_string()
+                );
+            }
+        }
+    }
+}
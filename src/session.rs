@@ -0,0 +1,325 @@
+use crate::fileio;
+use crate::window::LayoutNode;
+use ratatui::layout::Direction;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Written by :mksession when no file is given
+pub const DEFAULT_SESSION_FILE: &str = "Session.rvim";
+
+/*
+ * Where a window was, and how it was set up
+ */
+#[derive(Debug)]
+pub struct WindowState {
+    pub file_name: String,
+    pub line: usize,     // Cursor line (1-indexed)
+    pub col: usize,      // Cursor column in characters (1-indexed)
+    pub top_line: usize, // File line shown at the top of the window
+    pub show_line_nums: bool,
+}
+
+/*
+ * A tab page's windows, listed in layout order. Leaves of the layout are indexes into the windows
+ */
+#[derive(Debug)]
+pub struct TabState {
+    pub windows: Vec<WindowState>,
+    pub layout: LayoutNode,
+    pub active_window: usize,
+}
+
+/*
+ * Everything needed to pick up editing where it was left: working directory, options, buffers and tab pages.
+ * Saved as plain text with one command per line, ex.
+ *
+ *   cd /home/user/project
+ *   set makeprg=cargo build
+ *   buffer src/main.rs
+ *   tabpage 1
+ *     split vertical 1
+ *       window 40 12 5 1 number src/main.rs
+ *       window 40 1 1 1 nonumber README.md
+ *   tabnext 1
+ *
+ * Windows are given as: weight, cursor line and column, top line, line numbers, then the file name
+ */
+#[derive(Debug)]
+pub struct Session {
+    pub directory: PathBuf,
+    pub options: Vec<String>, // Arguments to :set (ex. "makeprg=cargo build" or "nobackup")
+    pub buffers: Vec<String>, // Every file open when the session was saved, including those not shown in a window
+    pub tabs: Vec<TabState>,
+    pub active_tab: usize,
+}
+
+impl Session {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Self::parse(text.as_str()).map_err(|msg| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), msg),
+            )
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fileio::write_atomic(path, self.to_text().as_bytes(), false)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("\" rust-vim session, restore with: rust-vim -S <file>\n");
+        let _ = writeln!(text, "cd {}", self.directory.display());
+        for option in &self.options {
+            let _ = writeln!(text, "set {}", option);
+        }
+        for buffer in &self.buffers {
+            let _ = writeln!(text, "buffer {}", buffer);
+        }
+        for tab in &self.tabs {
+            let _ = writeln!(text, "tabpage {}", tab.active_window + 1);
+            write_node(&mut text, &tab.layout, 1, 1, &tab.windows);
+        }
+        let _ = writeln!(text, "tabnext {}", self.active_tab + 1);
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut session = Session {
+            directory: PathBuf::new(),
+            options: vec![],
+            buffers: vec![],
+            tabs: vec![],
+            active_tab: 0,
+        };
+        // Each line with its number and indentation, skipping blank lines and comments
+        let lines: Vec<(usize, usize, &str)> = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('"'))
+            .map(|(idx, line)| {
+                let indent = (line.len() - line.trim_start().len()) / 2;
+                (idx + 1, indent, line.trim())
+            })
+            .collect();
+
+        let mut pos = 0;
+        while pos < lines.len() {
+            let (line_num, _, line) = lines[pos];
+            let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let error = |msg: &str| format!("line {}: {}", line_num, msg);
+            match command {
+                "cd" => session.directory = PathBuf::from(arg),
+                "set" => session.options.push(String::from(arg)),
+                "buffer" => session.buffers.push(String::from(arg)),
+                "tabpage" => {
+                    let active_window =
+                        parse_number(arg).ok_or(error("expected a window number"))?;
+                    let mut windows = vec![];
+                    pos += 1;
+                    let (layout, _) = parse_node(&lines, &mut pos, 1, &mut windows)?;
+                    if active_window == 0 || active_window > windows.len() {
+                        return Err(error("no such window"));
+                    }
+                    session.tabs.push(TabState {
+                        windows,
+                        layout,
+                        active_window: active_window - 1,
+                    });
+                    continue;
+                }
+                "tabnext" => {
+                    session.active_tab = parse_number(arg)
+                        .filter(|tab| *tab > 0)
+                        .ok_or(error("expected a tab page number"))?
+                        - 1;
+                }
+                _ => return Err(error("unknown command")),
+            }
+            pos += 1;
+        }
+
+        if session.tabs.is_empty() {
+            return Err(String::from("no tab pages"));
+        }
+        session.active_tab = session.active_tab.min(session.tabs.len() - 1);
+        Ok(session)
+    }
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    text.trim().parse().ok()
+}
+
+// Weights share out a split's space, and must fit the layout's u16s
+fn parse_weight(text: &str) -> Option<u16> {
+    text.trim().parse().ok()
+}
+
+/*
+ * Write a layout node and its children, one per line, indented by depth
+ */
+fn write_node(
+    text: &mut String,
+    node: &LayoutNode,
+    weight: u16,
+    depth: usize,
+    windows: &[WindowState],
+) {
+    let indent = "  ".repeat(depth);
+    match node {
+        LayoutNode::Leaf(idx) => {
+            let window = &windows[*idx];
+            let _ = writeln!(
+                text,
+                "{}window {} {} {} {} {} {}",
+                indent,
+                weight,
+                window.line,
+                window.col,
+                window.top_line,
+                if window.show_line_nums {
+                    "number"
+                } else {
+                    "nonumber"
+                },
+                window.file_name
+            );
+        }
+        LayoutNode::Split {
+            direction,
+            children,
+        } => {
+            let direction = match direction {
+                Direction::Vertical => "vertical",
+                Direction::Horizontal => "horizontal",
+            };
+            let _ = writeln!(text, "{}split {} {}", indent, direction, weight);
+            for (child, child_weight) in children {
+                write_node(text, child, *child_weight, depth + 1, windows);
+            }
+        }
+    }
+}
+
+/*
+ * Read the layout node starting at pos, along with its weight, adding the windows it holds in order
+ */
+fn parse_node(
+    lines: &[(usize, usize, &str)],
+    pos: &mut usize,
+    depth: usize,
+    windows: &mut Vec<WindowState>,
+) -> Result<(LayoutNode, u16), String> {
+    let Some(&(line_num, indent, line)) = lines.get(*pos) else {
+        return Err(String::from("tab page has no windows"));
+    };
+    let error = |msg: &str| format!("line {}: {}", line_num, msg);
+    if indent != depth {
+        return Err(error("expected a window or split"));
+    }
+    *pos += 1;
+    let fields: Vec<&str> = line.splitn(7, ' ').collect();
+    match fields[..] {
+        [
+            "window",
+            weight,
+            cursor_line,
+            cursor_col,
+            top_line,
+            line_nums,
+            file_name,
+        ] => {
+            let weight = parse_weight(weight).ok_or(error("expected a weight up to 65535"))?;
+            let numbers = [cursor_line, cursor_col, top_line].map(parse_number);
+            let [Some(line), Some(col), Some(top_line)] = numbers else {
+                return Err(error("expected numbers for the window's position"));
+            };
+            windows.push(WindowState {
+                file_name: String::from(file_name),
+                line,
+                col,
+                top_line,
+                show_line_nums: line_nums == "number",
+            });
+            Ok((LayoutNode::Leaf(windows.len() - 1), weight))
+        }
+        ["split", direction, weight] => {
+            let direction = match direction {
+                "vertical" => Direction::Vertical,
+                "horizontal" => Direction::Horizontal,
+                _ => return Err(error("expected vertical or horizontal")),
+            };
+            let weight = parse_weight(weight).ok_or(error("expected a weight up to 65535"))?;
+            let mut children = vec![];
+            while lines
+                .get(*pos)
+                .is_some_and(|(_, indent, _)| *indent == depth + 1)
+            {
+                children.push(parse_node(lines, pos, depth + 1, windows)?);
+            }
+            match children.len() {
+                0 => Err(error("split has no windows")),
+                1 => Ok((children.pop().unwrap().0, weight)),
+                _ => Ok((
+                    LayoutNode::Split {
+                        direction,
+                        children,
+                    },
+                    weight,
+                )),
+            }
+        }
+        _ => Err(error("expected a window or split")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SESSION: &str = "\" rust-vim session, restore with: rust-vim -S <file>
+cd /home/user/project
+set makeprg=cargo build
+set nobackup
+buffer src/main.rs
+buffer notes with spaces.txt
+tabpage 2
+  split vertical 1
+    window 40 12 5 1 number src/main.rs
+    split horizontal 60
+      window 3 1 1 1 nonumber README.md
+      window 1 7 2 3 number notes with spaces.txt
+tabpage 1
+  window 1 1 1 1 nonumber src/main.rs
+tabnext 2
+";
+
+    #[test]
+    fn writes_what_it_parses() {
+        let session = Session::parse(SESSION).unwrap();
+        assert_eq!(session.directory, PathBuf::from("/home/user/project"));
+        assert_eq!(session.options, ["makeprg=cargo build", "nobackup"]);
+        assert_eq!(session.tabs.len(), 2);
+        assert_eq!(session.active_tab, 1);
+        let window = &session.tabs[0].windows[2];
+        assert_eq!(window.file_name, "notes with spaces.txt");
+        assert_eq!((window.line, window.col, window.top_line), (7, 2, 3));
+        assert_eq!(session.to_text(), SESSION);
+    }
+
+    #[test]
+    fn rejects_weights_too_big_for_the_layout() {
+        let text = "tabpage 1\n  window 65536 1 1 1 nonumber a.txt\n";
+        assert_eq!(
+            Session::parse(text).unwrap_err(),
+            "line 2: expected a weight up to 65535"
+        );
+        let text = "tabpage 1\n  split vertical 70000\n    window 1 1 1 1 nonumber a.txt\n";
+        assert!(Session::parse(text).is_err());
+    }
+}
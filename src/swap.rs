@@ -0,0 +1,181 @@
+use crate::fileio;
+use ropey::Rope;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+// Unsaved changes are written to the swap file after this many changes, even while still typing
+pub const UPDATE_COUNT: usize = 200;
+// or once typing has paused for this long
+pub const UPDATE_TIME: Duration = Duration::from_secs(4);
+
+// First line of every swap file, so files written by other programs aren't mistaken for one
+const HEADER: &str = "rust-vim swap file";
+
+/*
+ * Swap file for a file being edited, kept beside it (ex. .main.rs.rvim.swp for main.rs).
+ * It records which process is editing the file, followed by the buffer's text whenever it has unsaved changes,
+ * so the changes can be recovered if the editor is killed before saving
+ */
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+    pending_changes: usize, // Changes made since the swap file was last written
+}
+
+/*
+ * Swap file found when opening a file, left by another rust-vim process
+ */
+#[derive(Debug)]
+pub struct ExistingSwap {
+    pub path: PathBuf,
+    pub pid: u32,
+    pub running: bool,        // Is that process still editing the file?
+    pub text: Option<String>, // Unsaved changes it holds, if any
+}
+
+pub fn swap_path(file_path: &Path) -> Option<PathBuf> {
+    let file_name = file_path.file_name()?.to_string_lossy();
+    Some(file_path.with_file_name(format!(".{}.rvim.swp", file_name)))
+}
+
+/*
+ * Look for a swap file left by another process for the given file
+ */
+pub fn find_existing(file_path: &Path) -> Option<ExistingSwap> {
+    let path = swap_path(file_path)?;
+    let contents = fs::read_to_string(&path).ok()?;
+    let mut parts = contents.splitn(4, '\n');
+    let (Some(HEADER), Some(pid), Some(state)) = (parts.next(), parts.next(), parts.next()) else {
+        return None;
+    };
+    let pid = pid.strip_prefix("pid ")?.parse().ok()?;
+    Some(ExistingSwap {
+        path,
+        pid,
+        running: is_running(pid),
+        text: match state {
+            "changed" => Some(String::from(parts.next().unwrap_or_default())),
+            _ => None,
+        },
+    })
+}
+
+impl ExistingSwap {
+    // Was the swap file made by this editor, for a buffer already showing the file?
+    pub fn is_ours(&self) -> bool {
+        self.pid == process::id()
+    }
+}
+
+/*
+ * Check whether a process still exists, without affecting it. Signal 0 only checks whether it could be sent,
+ * and processes belonging to other users refuse it (EPERM) while still running
+ */
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: kill with signal 0 sends nothing, only reporting whether the process exists
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// There's no portable way to check, so swap files are only reported as running for this process
+#[cfg(not(unix))]
+fn is_running(pid: u32) -> bool {
+    pid == process::id()
+}
+
+impl SwapFile {
+    /*
+     * Claim the swap file for the given file, replacing any left behind.
+     * Files in directories which can't be written to go without one
+     */
+    pub fn create(file_path: &Path) -> Option<Self> {
+        let mut swap = Self {
+            path: swap_path(file_path)?,
+            pending_changes: 0,
+        };
+        swap.write(None).ok()?;
+        Some(swap)
+    }
+
+    pub fn record_change(&mut self) {
+        self.pending_changes += 1;
+    }
+
+    pub fn pending_changes(&self) -> usize {
+        self.pending_changes
+    }
+
+    /*
+     * Rewrite the swap file with the buffer's text, or without any text once it has been saved.
+     * The old swap file is replaced whole, so a crash part way through still leaves it to recover from
+     */
+    pub fn write(&mut self, text: Option<&Rope>) -> io::Result<()> {
+        let state = if text.is_some() { "changed" } else { "saved" };
+        let mut contents = format!("{}\npid {}\n{}\n", HEADER, process::id(), state).into_bytes();
+        if let Some(text) = text {
+            for chunk in text.chunks() {
+                contents.extend_from_slice(chunk.as_bytes());
+            }
+        }
+        fileio::write_atomic(&self.path, &contents, false)?;
+        self.pending_changes = 0;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn remove(&self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_and_finds_swap_files() {
+        let dir = std::env::temp_dir().join(format!("rust-vim-swap-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("notes.txt");
+        let mut swap = SwapFile::create(&file_path).unwrap();
+        let existing = find_existing(&file_path).unwrap();
+        assert!(existing.is_ours() && existing.running);
+        assert_eq!(existing.text, None);
+
+        swap.write(Some(&Rope::from_str("unsaved\nchanges\n")))
+            .unwrap();
+        let existing = find_existing(&file_path).unwrap();
+        assert_eq!(existing.text.as_deref(), Some("unsaved\nchanges\n"));
+        // Only the swap file itself is left behind, with no temporary files from writing it
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        swap.remove();
+        assert!(find_existing(&file_path).is_none());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_processes_are_running() {
+        assert!(is_running(process::id()));
+        // Larger than any process id
+        assert!(!is_running(u32::MAX));
+    }
+
+    // init always runs, and belongs to root, so other users can't signal it
+    #[cfg(unix)]
+    #[test]
+    fn counts_other_users_processes_as_running() {
+        assert!(is_running(1));
+    }
+}
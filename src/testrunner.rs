@@ -0,0 +1,315 @@
+use crate::explorer;
+use crate::quickfix::{self, Location};
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+
+// Start of a function or module declaration (ex. "pub(crate) async fn name" or "mod tests")
+static ITEM: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|extern\s+"[^"]*")\s+)*(fn|mod)\s+([A-Za-z_][A-Za-z0-9_]*)"#).unwrap()
+});
+// Attributes marking test functions (ex. #[test] or #[tokio::test])
+static TEST_ATTRIBUTE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#\[\s*(?:[\w:]+::)?test\s*\]").unwrap());
+// Where a test panicked: "panicked at src/lib.rs:10:5:" (or "panicked at 'message', src/lib.rs:10:5" on older toolchains)
+static PANIC_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"panicked at (?:'.*', )?([^:\s][^:]*):(\d+):(\d+)").unwrap());
+
+/*
+ * A function or module enclosing the cursor
+ */
+#[derive(Debug)]
+struct Item {
+    is_module: bool,
+    name: String,
+    is_test: bool, // Function marked with a test attribute
+}
+
+/*
+ * Which tests to run, and where to run cargo from
+ */
+#[derive(Debug)]
+pub struct TestTarget {
+    pub crate_root: PathBuf, // Directory holding the crate's Cargo.toml
+    target: Option<String>, // Test target to build (ex. "--test integration" for tests/integration.rs)
+    filter: String,         // Path of the test (ex. "model::tests::saves_file")
+    exact: bool, // Only run the test whose path is the filter, rather than every test under it
+}
+
+impl TestTarget {
+    pub fn command(&self) -> String {
+        let mut command = String::from("cargo test --message-format=short");
+        if let Some(target) = &self.target {
+            command.push(' ');
+            command.push_str(target);
+        }
+        if !self.filter.is_empty() {
+            command.push(' ');
+            command.push_str(&self.filter);
+        }
+        if self.exact {
+            command.push_str(" -- --exact");
+        }
+        command
+    }
+}
+
+/*
+ * Find the test function (or failing that, the test module) enclosing the given (1-indexed) line of a Rust file
+ */
+pub fn find_test(file_path: &Path, text: &str, cursor_line: usize) -> Option<TestTarget> {
+    let file_path = file_path.canonicalize().ok()?;
+    let crate_root = file_path
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.join("Cargo.toml").is_file())?
+        .to_path_buf();
+    let (target, mut modules) = file_module_path(file_path.strip_prefix(&crate_root).ok()?)?;
+
+    let items = enclosing_items(text, cursor_line);
+    // Prefer the innermost test function, then the innermost module
+    let test_fn = items
+        .iter()
+        .rposition(|item| !item.is_module && item.is_test);
+    let end = match test_fn {
+        Some(pos) => pos,
+        None => items
+            .iter()
+            .rposition(|item| item.is_module)
+            .map_or(0, |pos| pos + 1),
+    };
+    modules.extend(
+        items[..end]
+            .iter()
+            .filter(|item| item.is_module)
+            .map(|item| item.name.clone()),
+    );
+    if let Some(pos) = test_fn {
+        modules.push(items[pos].name.clone());
+    }
+    if modules.is_empty() && target.is_none() {
+        return None;
+    }
+    Some(TestTarget {
+        crate_root,
+        target,
+        filter: modules.join("::"),
+        exact: test_fn.is_some(),
+    })
+}
+
+/*
+ * Module path of a file relative to its crate root (ex. src/model/window.rs is model::window),
+ * and the test target it belongs to if it isn't part of the library or main binary
+ */
+fn file_module_path(relative: &Path) -> Option<(Option<String>, Vec<String>)> {
+    let parts: Vec<&str> = relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    let stem = |part: &str| String::from(part.strip_suffix(".rs").unwrap_or(part));
+    match parts[..] {
+        // Integration tests and extra binaries are built as their own crates
+        ["tests", file, ..] => Some((Some(format!("--test {}", stem(file))), vec![])),
+        ["src", "bin", file, ..] => Some((Some(format!("--bin {}", stem(file))), vec![])),
+        ["src", "main.rs" | "lib.rs"] => Some((None, vec![])),
+        ["src", ref modules @ ..] => {
+            let mut modules: Vec<String> = modules.iter().map(|part| stem(part)).collect();
+            if modules.last().is_some_and(|module| module == "mod") {
+                modules.pop();
+            }
+            Some((None, modules))
+        }
+        _ => None,
+    }
+}
+
+/*
+ * Functions and modules whose bodies contain the given line, outermost first.
+ * Braces are counted on each line with comments and string contents removed, which is enough for typical code
+ */
+fn enclosing_items(text: &str, cursor_line: usize) -> Vec<Item> {
+    let mut stack: Vec<(Item, usize)> = vec![]; // Each item with the brace depth inside its body
+    let mut pending: Option<Item> = None; // Item declared but whose body hasn't opened yet
+    let mut is_test = false;
+    let mut depth = 0;
+    for line in text.lines().take(cursor_line) {
+        let code = strip_literals(line);
+        if TEST_ATTRIBUTE.is_match(&code) {
+            is_test = true;
+        }
+        if let Some(captures) = ITEM.captures(&code) {
+            pending = Some(Item {
+                is_module: &captures[1] == "mod",
+                name: String::from(&captures[2]),
+                is_test,
+            });
+            is_test = false;
+        }
+        for ch in code.chars() {
+            match ch {
+                '{' => {
+                    depth += 1;
+                    if let Some(item) = pending.take() {
+                        stack.push((item, depth));
+                    }
+                }
+                '}' => {
+                    while stack
+                        .last()
+                        .is_some_and(|(_, item_depth)| *item_depth >= depth)
+                    {
+                        stack.pop();
+                    }
+                    depth = depth.saturating_sub(1);
+                }
+                // Declarations without a body (ex. "mod model;")
+                ';' => pending = None,
+                _ => {}
+            }
+        }
+    }
+    stack.into_iter().map(|(item, _)| item).collect()
+}
+
+// Remove comments, and the contents of strings and brace characters, from a line of code
+fn strip_literals(line: &str) -> String {
+    let line = line.replace("'{'", "").replace("'}'", "");
+    let mut code = String::new();
+    let mut in_string = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => {
+                in_string = !in_string;
+                code.push(ch);
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => break,
+            _ if in_string => {}
+            _ => code.push(ch),
+        }
+    }
+    code
+}
+
+/*
+ * Output of a test run shown in the results popup
+ */
+#[derive(Debug)]
+pub struct TestRun {
+    pub command: String,
+    crate_root: PathBuf,
+    pub lines: Vec<String>,
+    pub locations: Vec<Option<Location>>, // Failure (or compile error) location named on each line
+    pub selected: usize,
+    follow: bool, // Keep the last line selected as output arrives, until the user moves the selection
+    in_warning: bool, // Lines belong to a compiler warning, whose locations aren't failures
+    pub passed: Option<bool>, // Set once the run has finished
+}
+
+impl TestRun {
+    pub fn new(target: &TestTarget) -> Self {
+        Self {
+            command: target.command(),
+            crate_root: target.crate_root.clone(),
+            lines: vec![],
+            locations: vec![],
+            selected: 0,
+            follow: true,
+            in_warning: false,
+            passed: None,
+        }
+    }
+
+    pub fn push_lines(&mut self, lines: Vec<String>) {
+        for line in lines {
+            if line.starts_with("warning") {
+                self.in_warning = true;
+            } else if line.starts_with("error") {
+                self.in_warning = false;
+            }
+            let location = self.find_location(&line);
+            self.lines.push(line);
+            self.locations.push(location);
+        }
+        if self.follow {
+            self.selected = self.lines.len().saturating_sub(1);
+        }
+    }
+
+    // Once finished, start at the first failure
+    pub fn finish(&mut self, passed: bool) {
+        self.passed = Some(passed);
+        if self.follow
+            && let Some(first) = self
+                .locations
+                .iter()
+                .position(|location| location.is_some())
+        {
+            self.selected = first;
+        }
+    }
+
+    pub fn num_failures(&self) -> usize {
+        self.locations
+            .iter()
+            .filter(|location| location.is_some())
+            .count()
+    }
+
+    pub fn select_next(&mut self) {
+        self.follow = false;
+        if self.selected + 1 < self.lines.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        self.follow = false;
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    // Move the selection to the next (or previous) line with a location
+    pub fn select_failure(&mut self, forwards: bool) -> Result<(), &'static str> {
+        self.follow = false;
+        let found = if forwards {
+            (self.selected + 1..self.lines.len()).find(|idx| self.locations[*idx].is_some())
+        } else {
+            (0..self.selected)
+                .rev()
+                .find(|idx| self.locations[*idx].is_some())
+        };
+        self.selected = found.ok_or("Error: No more failures")?;
+        Ok(())
+    }
+
+    pub fn selected_location(&self) -> Option<&Location> {
+        self.locations.get(self.selected)?.as_ref()
+    }
+
+    /*
+     * Location named by a line of output: where a test panicked, or a compile error.
+     * Paths are relative to the crate root, so they are made relative to the working directory instead
+     */
+    fn find_location(&self, line: &str) -> Option<Location> {
+        let mut location = match PANIC_LOCATION.captures(line) {
+            Some(captures) => Location {
+                path: String::from(&captures[1]),
+                line: captures[2].parse().ok()?,
+                col: captures[3].parse().ok()?,
+                text: String::from(line.trim()),
+            },
+            None => quickfix::parse_errors(line)
+                .pop()
+                .filter(|location| !self.in_warning && !location.is_warning())?,
+        };
+        location.path = explorer::display_path(&self.crate_root.join(&location.path));
+        Some(location)
+    }
+}
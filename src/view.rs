@@ -1,7 +1,9 @@
 use crate::{
     App,
-    controller::{Mode, QuitSelection},
+    controller::{FileChangedSelection, Mode, QuitSelection, SwapSelection},
+    window::Window,
 };
+use core::ops::Range;
 use count_digits::{self, CountDigits};
 use crossterm::{cursor::SetCursorStyle, execute};
 use ratatui::{
@@ -11,9 +13,9 @@ use ratatui::{
     style::{Color, Modifier, Style, Stylize},
     symbols::border,
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListState, Paragraph, Wrap},
 };
-use std::cmp::max;
+use std::cmp::{self, max};
 use std::io::stdout;
 
 const LEFT_HELP_TEXT: &str = "Normal Mode:
@@ -21,17 +23,50 @@ Move the cursor with arrow keys or hjkl
 [i] to start editing text in Insertion Mode
 [:] to start typing in Command Mode
 [/] to start a query in Search Input Mode
+[?] to start a query searching backward
+[n][N] to jump to the next/previous search match
 [Esc] to turn off Search Highlights
+[g][t] / [g][T] to go to the next/previous tab page
+[Ctrl-P] to open the fuzzy file finder
+[Ctrl-C] to stop a running build, test run or search
 
 Command Mode:
 [Esc] to cancel and return to Normal Mode
 [Enter] to submit the command
 Commands:
 :q => Quit editing
+:q! => Close even if changes would be hidden, or quit without saving
 :w => Write to file
+:w file => Write to another file (names unnamed buffers)
+:w! => Write even if read-only or changed on disk
 :wq => Write to file, then quit
 :num => Toggle line numbers
-:dd => Delete current line of file";
+:dd => Delete current line of file
+:s/pat/rep/[g] => Replace on this line (:%s on all)
+:sp [file] => Split window horizontally
+:vs [file] => Split window vertically
+:close => Close current window
+:only => Close all other windows
+:tabnew [file] => Open a new tab page
+:tabclose => Close current tab page
+:tabn / :tabp => Next/previous tab page
+:Files => Open the fuzzy file finder
+:grep pat [path] => Search files for a regex
+:copen / :cclose => Open/close the quickfix list
+:cn / :cp / :cc N => Next/previous/Nth entry
+:cexpr text / :cfile [file] => Read entries
+:lgrep, :lopen, :lne, ... => Same for location lists
+:make [args] => Build in the background
+:set makeprg=cmd => Change the build command
+:TestNearest => Run the test around the cursor
+:TestResults => Show the last test run's output
+:mksession [file] => Save the session (rust-vim -S file)
+:set backup => Keep a backup of files when writing
+:set autoread => Reload files changed elsewhere
+:set ff=unix|dos|mac => Change the line endings
+:set fenc=enc => Change the file's encoding
+:e [++enc=enc] [file] => Open or re-read a file
+:view [file] => Open a file read-only";
 
 const RIGHT_HELP_TEXT: &str = "Insertion Mode:
 Move the cursor with arrow keys
@@ -43,15 +78,55 @@ Type to insert characters at the cursor location
 
 Search Input Mode:
 [Esc] to cancel and return to Normal Mode
-[Enter] to submit the search query and highlight matches";
+[Enter] to submit the search query and highlight matches
+Matches are highlighted while typing (:set noincsearch to turn off)
 
-pub const MAX_HELP_SCROLL: u16 = 14;
+Window Commands (Normal Mode, [Ctrl-W] then):
+[h][j][k][l] or arrows to move to a neighbouring window
+[w][W] to cycle through windows
+[s][v] to split horizontally/vertically
+[+][-] to change height, [<][>] to change width
+[=] to make all windows equal size
+[c] to close, [q] to quit, [o] to close all others
+[r][R] to rotate windows downwards/upwards
+
+Directory Browser (rust-vim <dir> or :Explore [dir]):
+[Enter] to open the file or directory under the cursor
+[-] to go up to the parent directory
+[%] to create a file, [d] to create a directory
+[R] to rename, [D] to delete the entry under the cursor
+[g][h] to show/hide hidden files
+
+File Finder ([Ctrl-P] or :Files):
+Type to filter files in the project by fuzzy match
+[^][v] or [Ctrl-P][Ctrl-N] to move the highlight
+[Enter] to open the highlighted file
+[Esc] to close the finder
+
+Quickfix / Location List Windows:
+[Enter] to jump to the entry under the cursor
+
+Test Results (:TestNearest or :TestResults):
+[^][v] or [j][k] to move the highlight
+[n][N] to highlight the next/previous failure
+[Enter] to jump to the highlighted failure
+[Esc] to close the popup (tests keep running)
+[Ctrl-C] to stop the tests
+
+Swap File Popup (file open elsewhere, or unsaved changes found):
+[<][>] to select, [Enter] to confirm, or pick with
+[r]ecover, [o]pen read-only, [d]elete swap file, [a]bort
+
+File Changed Popup (another program changed an open file):
+[<][>] to select, [Enter] to confirm, or pick with
+[r]eload, [k]eep mine, [d]iff against the file
+[^][v] to scroll the diff";
+
+pub const MAX_HELP_SCROLL: u16 = 70;
 
 pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     // For the main content section of UI
     let file_name = app.get_filename();
-    let display_lines = app.get_content();
-    let show_line_num = app.get_show_line_num();
     let show_highlights = app.get_show_highlights();
 
     // For message bar of UI
@@ -59,9 +134,8 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     let ui_message = app.get_msg_display();
 
     // For cursor location section of UI
-    let scroll_amount = app.get_scroll_amount();
     let cursor_pos = app.get_cursor_pos();
-    let curr_row = display_lines[(scroll_amount + cursor_pos.0) as usize - 1].line_num;
+    let curr_row = app.get_cursor_line_num();
     let curr_col = app.get_cursor_inline_index();
 
     // Other important items used for View UI
@@ -69,10 +143,16 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     let help_scroll = app.get_scroll_help_amount();
     let quit_selection = app.get_quit_selection();
 
-    let layout = Layout::default()
+    let tab_line_height = app.get_show_tab_line() as u16;
+    let outer_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Min(1), Constraint::Length(2)])
+        .constraints(vec![
+            Constraint::Length(tab_line_height),
+            Constraint::Min(1),
+            Constraint::Length(2),
+        ])
         .split(frame.area());
+    let layout = &outer_layout[1..];
 
     // Used for cursor coordinates, command line, and error messages
     let bottom_layout = Layout::default()
@@ -81,59 +161,61 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
             // usize to u16 conversion and vice versa should be safe, since the number of digits in the cursor should be small
             Constraint::Length(5 + max(curr_row.count_digits(), curr_col.count_digits()) as u16),
             Constraint::Min(12),
+            // Wide enough for "Loading 100%" while a large file is still being read
+            Constraint::Length(if app.get_load_progress().is_some() {
+                13
+            } else {
+                10
+            }),
         ])
         .split(layout[1]);
 
-    // File Content
-    let title = Line::from(file_name.bold());
-    let content_block = Block::bordered().title(title).border_set(border::THICK);
-    let mut display_content: Vec<Line> = Vec::new();
-    for line in display_lines.iter() {
-        let mut display_line = vec![];
-
-        // Format line numbers with yellow color
-        let mut line_content_index = 0;
-        if show_line_num {
-            line_content_index = line.line_content.find('|').unwrap();
-            display_line.push(Span::styled(
-                &line.line_content[..line_content_index],
-                Style::default().fg(Color::Yellow),
-            ));
+    // Tab line, labelling each tab page with the file name of its active window
+    if tab_line_height > 0 {
+        let active_tab = app.get_active_tab();
+        let mut tab_labels = vec![];
+        for (tab_idx, (tab_file_name, modified)) in app.get_tab_labels().into_iter().enumerate() {
+            // Mark tabs with unsaved changes
+            let label = if modified {
+                format!(" {} {} + ", tab_idx + 1, tab_file_name)
+            } else {
+                format!(" {} {} ", tab_idx + 1, tab_file_name)
+            };
+            let style = if tab_idx == active_tab {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(Color::Black).bg(Color::Gray)
+            };
+            tab_labels.push(Span::styled(label, style));
+            tab_labels.push(Span::raw(" "));
         }
+        frame.render_widget(Paragraph::new(Line::from(tab_labels)), outer_layout[0]);
+    }
 
-        // Highlight search matches if present
-        if show_highlights {
-            let mut curr_index = line_content_index;
-            // Iterate over the line's highlighted ranges
-            for highlight_range in &line.highlight_ranges {
-                // normal white text (not search match)
-                display_line.push(Span::raw(
-                    &line.line_content[curr_index..highlight_range.start],
-                ));
-                // highlighted range
-                display_line.push(Span::styled(
-                    &line.line_content[highlight_range.start..highlight_range.end],
-                    Style::default().fg(Color::White).bg(Color::Cyan),
-                ));
-                curr_index = highlight_range.end;
-            }
-            // Add last substring as normal text
-            display_line.push(Span::raw(&line.line_content[curr_index..]));
-        } else {
-            // No search matches, display as normal white text
-            display_line.push(Span::styled(
-                &line.line_content[line_content_index..],
-                Style::default(),
-            ));
+    // File Content, one bordered block per window
+    let mut active_area = layout[0];
+    for (window_id, area) in app.get_window_areas(layout[0]) {
+        let window = app.get_window(window_id);
+        let is_active = window_id == app.get_active_window_id();
+        if is_active {
+            active_area = area;
         }
-        display_content.push(display_line.into());
+        draw_window(
+            frame,
+            window,
+            app.get_buffer_name(window.buffer),
+            area,
+            is_active,
+            // Only the active window's cursor can be at a match
+            show_highlights.then(|| {
+                (
+                    app.get_match_ranges(window.buffer),
+                    app.get_current_match().filter(|_| is_active),
+                )
+            }),
+            &app.get_marks(window.buffer),
+        );
     }
-    let display_content: Text = display_content.into();
-
-    let content = Paragraph::new(display_content)
-        .block(content_block)
-        .scroll((scroll_amount, 0));
-    frame.render_widget(content, layout[0]);
 
     // Cursor Location
     let cursor_row_text = format!("row: {}", curr_row);
@@ -152,15 +234,48 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
     .centered();
     // Second line contains user input, or messages to user
     // Highlight error messages in red
-    let ui_text: Line = if ui_message.starts_with("Error") {
-        Line::styled(ui_message, Style::default().bg(Color::Red)).centered()
+    let ui_text = if ui_message.starts_with("Error") {
+        Span::styled(ui_message, Style::default().bg(Color::Red))
     } else {
-        Line::styled(ui_message, Style::default()).centered()
+        Span::styled(ui_message, Style::default())
     };
+    // Followed by which search match the cursor is at (ex. [3/17])
+    let ui_text: Line = match app.get_match_counter() {
+        Some(counter) => Line::from(vec![
+            ui_text,
+            Span::styled(format!(" {}", counter), Style::default().fg(Color::Cyan)),
+        ]),
+        None => Line::from(ui_text),
+    }
+    .centered();
     let ui_content: Text = vec![mode_text, ui_text].into();
     let ui_block = Block::new().borders(Borders::LEFT);
     frame.render_widget(Paragraph::new(ui_content).block(ui_block), bottom_layout[1]);
 
+    // File details of the active buffer
+    let file_info_block = Block::new().borders(Borders::LEFT);
+    frame.render_widget(
+        Paragraph::new(vec![
+            Line::from(vec![
+                Span::raw(app.get_file_format()),
+                if app.get_read_only() {
+                    Span::styled(" [RO]", Style::default().fg(Color::LightRed))
+                } else {
+                    Span::raw("")
+                },
+            ]),
+            match app.get_load_progress() {
+                Some(progress) => Line::styled(
+                    format!("Loading {}%", progress),
+                    Style::default().fg(Color::Yellow),
+                ),
+                None => Line::raw(app.get_file_encoding()),
+            },
+        ])
+        .block(file_info_block),
+        bottom_layout[2],
+    );
+
     // Render Help pop-up if in Help mode
     if let Mode::Help = app_mode {
         let help_popup_block = Block::bordered()
@@ -207,7 +322,15 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
             .split(area);
 
         // Header Text of quit popup
-        let header_text = format!("Quit without saving to {file_name}?");
+        // Changes with no file name are written with :w file rather than saved
+        let unnamed_changes = app.get_unnamed_changes();
+        let header_text = if unnamed_changes {
+            String::from(
+                "Some changes have no file name. Quit without saving, or write them with :w file?",
+            )
+        } else {
+            format!("Quit without saving to {file_name}?")
+        };
         frame.render_widget(
             Paragraph::new(header_text)
                 .centered()
@@ -236,10 +359,14 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
             .block(Block::bordered().border_set(border::ROUNDED))
             .centered()
             .style(Style::default().fg(Color::LightRed).bg(Color::DarkGray));
-        let mut save_and_quit_box = Paragraph::new("Save & Quit")
-            .block(Block::bordered().border_set(border::ROUNDED))
-            .centered()
-            .style(Style::default().fg(Color::LightGreen).bg(Color::DarkGray));
+        let mut save_and_quit_box = Paragraph::new(if unnamed_changes {
+            "Write with :w file"
+        } else {
+            "Save & Quit"
+        })
+        .block(Block::bordered().border_set(border::ROUNDED))
+        .centered()
+        .style(Style::default().fg(Color::LightGreen).bg(Color::DarkGray));
 
         // Update styling for the selected box to make it more vibrant
         match quit_selection {
@@ -278,20 +405,462 @@ pub fn draw_ui(frame: &mut Frame, app: &mut App) {
         frame.render_widget(save_and_quit_box, selection_layout[0]);
     }
 
+    // Render swap file popup if another process's swap file was found for the file being opened
+    if let Mode::Swap = app_mode
+        && let Some(existing) = app.get_existing_swap()
+    {
+        let swap_popup_block = Block::bordered()
+            .border_set(border::THICK)
+            .style(Style::default().bg(Color::DarkGray));
+        let area = popup_area(frame.area(), 90, 60);
+        frame.render_widget(Clear, area);
+        frame.render_widget(swap_popup_block, area);
+
+        let swap_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints([Constraint::Percentage(30), Constraint::Min(5)])
+            .split(area);
+
+        // Header Text explaining where the swap file came from
+        let detail = if existing.running {
+            format!(
+                "rust-vim (process {}) is already editing this file. Editing it here too may lose changes.",
+                existing.pid
+            )
+        } else {
+            format!(
+                "It was left by rust-vim (process {}), which was closed without saving its changes.",
+                existing.pid
+            )
+        };
+        let header_text = vec![
+            Line::styled(
+                format!("Found a swap file for {file_name}"),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Line::raw(detail),
+        ];
+        frame.render_widget(
+            Paragraph::new(header_text)
+                .centered()
+                .wrap(Wrap { trim: true }),
+            swap_layout[0],
+        );
+
+        // Four selection segments corresponding to the swap file options
+        let selection_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(1)
+            .constraints([Constraint::Fill(1); 4])
+            .spacing(1)
+            .split(swap_layout[1]);
+        let swap_selection = app.get_swap_selection();
+        let options = [
+            (
+                SwapSelection::Recover,
+                "Recover",
+                Color::LightGreen,
+                Color::Green,
+                existing.text.is_some(),
+            ),
+            (
+                SwapSelection::ReadOnly,
+                "Open Read-Only",
+                Color::LightBlue,
+                Color::Blue,
+                true,
+            ),
+            (
+                SwapSelection::Delete,
+                "Delete Swap File",
+                Color::LightRed,
+                Color::Red,
+                !existing.running,
+            ),
+            (
+                SwapSelection::Abort,
+                "Abort",
+                Color::LightYellow,
+                Color::Yellow,
+                true,
+            ),
+        ];
+        for ((selection, label, fg, bg, available), chunk) in
+            options.into_iter().zip(selection_layout.iter())
+        {
+            // Options which can't be used are greyed out, and the selected option is made more vibrant
+            let option_box = if selection == swap_selection {
+                Paragraph::new(label)
+                    .block(Block::bordered().border_set(border::THICK))
+                    .style(
+                        Style::default()
+                            .fg(Color::White)
+                            .bg(bg)
+                            .add_modifier(Modifier::BOLD),
+                    )
+            } else {
+                Paragraph::new(label)
+                    .block(Block::bordered().border_set(border::ROUNDED))
+                    .style(
+                        Style::default()
+                            .fg(if available { fg } else { Color::Gray })
+                            .bg(Color::DarkGray),
+                    )
+            };
+            frame.render_widget(option_box.centered(), *chunk);
+        }
+    }
+
+    // Render file changed popup if another program changed an open file, with the diff once asked for
+    if let Mode::FileChanged = app_mode
+        && let Some(changed_file) = app.get_changed_file()
+    {
+        let file_diff = app.get_file_diff();
+        let changed_popup_block = Block::bordered()
+            .border_set(border::THICK)
+            .style(Style::default().bg(Color::DarkGray));
+        let area = if file_diff.is_empty() {
+            popup_area(frame.area(), 90, 60)
+        } else {
+            popup_area(frame.area(), 90, 80)
+        };
+        frame.render_widget(Clear, area);
+        frame.render_widget(changed_popup_block, area);
+
+        let changed_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .margin(1)
+            .constraints(if file_diff.is_empty() {
+                [
+                    Constraint::Percentage(30),
+                    Constraint::Length(0),
+                    Constraint::Min(5),
+                ]
+            } else {
+                [
+                    Constraint::Length(2),
+                    Constraint::Min(3),
+                    Constraint::Length(5),
+                ]
+            })
+            .split(area);
+
+        // Header Text explaining what happened
+        let header_text = vec![
+            Line::styled(
+                format!("{changed_file} changed on disk"),
+                Style::default().add_modifier(Modifier::BOLD),
+            ),
+            Line::raw("Another program changed the file since it was opened or last written."),
+        ];
+        frame.render_widget(
+            Paragraph::new(header_text)
+                .centered()
+                .wrap(Wrap { trim: true }),
+            changed_layout[0],
+        );
+
+        // Differences from the file on disk to the buffer, with removed lines in red and added lines in green
+        let diff_lines: Vec<Line> = file_diff
+            .iter()
+            .map(|line| {
+                let color = match line.chars().next() {
+                    Some('-') => Color::LightRed,
+                    Some('+') => Color::LightGreen,
+                    Some('@') => Color::LightCyan,
+                    _ => Color::White,
+                };
+                Line::styled(line.as_str(), Style::default().fg(color))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(diff_lines)
+                .block(Block::new().borders(Borders::TOP | Borders::BOTTOM))
+                .scroll((app.get_scroll_diff_amount(), 0)),
+            changed_layout[1],
+        );
+
+        // Three selection segments corresponding to the options
+        let selection_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(1)
+            .constraints([Constraint::Fill(1); 3])
+            .spacing(1)
+            .split(changed_layout[2]);
+        let file_changed_selection = app.get_file_changed_selection();
+        let options = [
+            (
+                FileChangedSelection::Reload,
+                "Reload",
+                Color::LightGreen,
+                Color::Green,
+            ),
+            (
+                FileChangedSelection::KeepMine,
+                "Keep Mine",
+                Color::LightYellow,
+                Color::Yellow,
+            ),
+            (
+                FileChangedSelection::Diff,
+                "Diff",
+                Color::LightBlue,
+                Color::Blue,
+            ),
+        ];
+        for ((selection, label, fg, bg), chunk) in options.into_iter().zip(selection_layout.iter())
+        {
+            let option_box = if selection == file_changed_selection {
+                Paragraph::new(label)
+                    .block(Block::bordered().border_set(border::THICK))
+                    .style(
+                        Style::default()
+                            .fg(Color::White)
+                            .bg(bg)
+                            .add_modifier(Modifier::BOLD),
+                    )
+            } else {
+                Paragraph::new(label)
+                    .block(Block::bordered().border_set(border::ROUNDED))
+                    .style(Style::default().fg(fg).bg(Color::DarkGray))
+            };
+            frame.render_widget(option_box.centered(), *chunk);
+        }
+    }
+
+    // Render file finder popup with its matches on the left, and a preview of the highlighted file on the right
+    if let Mode::Finder = app_mode
+        && let Some(finder) = app.get_finder()
+    {
+        let finder_popup_block = Block::bordered()
+            .title(format!(
+                "Find File ({}/{})",
+                finder.matches.len(),
+                finder.num_files()
+            ))
+            .border_set(border::THICK);
+        let area = popup_area(frame.area(), 90, 80);
+        frame.render_widget(Clear, area);
+        frame.render_widget(finder_popup_block, area);
+
+        let finder_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .margin(1)
+            .spacing(1)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(area);
+        let match_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(2), Constraint::Min(1)])
+            .split(finder_chunks[0]);
+
+        // Query being typed
+        let query_line = Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(finder.query.as_str()),
+        ]);
+        frame.render_widget(
+            Paragraph::new(query_line).block(Block::new().borders(Borders::BOTTOM)),
+            match_chunks[0],
+        );
+
+        // Matching paths, keeping the highlighted one in view
+        let match_list = List::new(finder.matches.iter().map(|path| path.as_str()))
+            .highlight_style(
+                Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            );
+        let mut list_state = ListState::default().with_selected(Some(finder.selected));
+        frame.render_stateful_widget(match_list, match_chunks[1], &mut list_state);
+
+        // Preview of the highlighted file
+        let preview_block = Block::new()
+            .borders(Borders::LEFT)
+            .title(finder.selected_path().unwrap_or_default());
+        let preview_lines: Vec<Line> = finder
+            .preview
+            .iter()
+            .map(|line| Line::raw(line.as_str()))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(preview_lines).block(preview_block),
+            finder_chunks[1],
+        );
+    }
+
+    // Render test results popup, with the lines naming a failure location in red
+    if let Mode::TestResults = app_mode
+        && let Some(test_run) = app.get_test_run()
+    {
+        let status = match test_run.passed {
+            None => "running...",
+            Some(true) => "passed",
+            Some(false) => "FAILED",
+        };
+        let test_popup_block = Block::bordered()
+            .title(format!("{} ({})", test_run.command, status))
+            .border_set(border::THICK);
+        let area = popup_area(frame.area(), 90, 80);
+        frame.render_widget(Clear, area);
+
+        let output_lines =
+            test_run
+                .lines
+                .iter()
+                .zip(&test_run.locations)
+                .map(|(line, location)| match location {
+                    Some(_) => Line::styled(line.as_str(), Style::default().fg(Color::Red)),
+                    None => Line::raw(line.as_str()),
+                });
+        let output_list = List::new(output_lines)
+            .block(test_popup_block)
+            .highlight_style(
+                Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            );
+        let mut list_state = ListState::default().with_selected(Some(test_run.selected));
+        frame.render_stateful_widget(output_list, area, &mut list_state);
+    }
+
     // Render cursor if not in pop-up modes
     match app_mode {
-        Mode::Normal | Mode::Command | Mode::SearchInput => {
+        Mode::Normal | Mode::Command | Mode::SearchInput | Mode::Prompt => {
             execute!(stdout(), SetCursorStyle::BlinkingBlock).unwrap();
-            frame.set_cursor_position(Position::new(cursor_pos.1, cursor_pos.0));
+            frame.set_cursor_position(Position::new(
+                active_area.x + cursor_pos.1,
+                active_area.y + cursor_pos.0,
+            ));
         }
         Mode::Insert => {
             execute!(stdout(), SetCursorStyle::BlinkingBar).unwrap();
-            frame.set_cursor_position(Position::new(cursor_pos.1, cursor_pos.0));
+            frame.set_cursor_position(Position::new(
+                active_area.x + cursor_pos.1,
+                active_area.y + cursor_pos.0,
+            ));
         }
         _ => {}
     }
 }
 
+/*
+ * Draws a window's wrapped file contents inside a bordered block titled with its file name.
+ * The window with focus gets a thick border
+ */
+fn draw_window(
+    frame: &mut Frame,
+    window: &Window,
+    file_name: &str,
+    area: Rect,
+    is_active: bool,
+    highlights: Option<(&[Range<usize>], Option<usize>)>, // Set when search matches are highlighted, to the matches and the start of the one under the cursor (if any)
+    marks: &[(usize, bool)],
+) {
+    let title = Line::from(file_name.bold());
+    let border_set = if is_active {
+        border::THICK
+    } else {
+        border::PLAIN
+    };
+    let content_block = Block::bordered().title(title).border_set(border_set);
+    // Only the rows in view are wrapped and built
+    let visible_rows = area.height.saturating_sub(2) as usize;
+    window
+        .display_content
+        .wrap_rows(window.scroll_amount, visible_rows);
+    let last_row = cmp::min(
+        window.scroll_amount + visible_rows,
+        window.display_content.num_rows(),
+    );
+    let display_lines: Vec<_> = (window.scroll_amount..last_row)
+        .map(|row| {
+            let match_ranges = highlights.map_or(&[][..], |(match_ranges, _)| match_ranges);
+            window.display_content.display_line(row, match_ranges)
+        })
+        .collect();
+    let mut display_content: Vec<Line> = Vec::new();
+    for line in display_lines.iter() {
+        let mut display_line = vec![];
+
+        // Lines with build errors are marked red, and lines with only warnings yellow
+        let mark_color = marks
+            .iter()
+            .filter(|(line_num, _)| *line_num == line.line_num)
+            .map(|(_, is_warning)| {
+                if *is_warning {
+                    Color::Yellow
+                } else {
+                    Color::Red
+                }
+            })
+            .min_by_key(|color| *color != Color::Red);
+
+        // Format line numbers with yellow color
+        let mut line_content_index = 0;
+        if window.show_line_nums {
+            line_content_index = line.line_content.find('|').unwrap();
+            let style = match mark_color {
+                Some(color) => Style::default().fg(Color::Black).bg(color),
+                None => Style::default().fg(Color::Yellow),
+            };
+            display_line.push(Span::styled(
+                &line.line_content[..line_content_index],
+                style,
+            ));
+        }
+
+        // Highlight search matches if present
+        if let Some((_, current_match)) = highlights {
+            let mut curr_index = line_content_index;
+            // Iterate over the line's highlighted ranges
+            for (highlight_range, match_start) in
+                line.highlight_ranges.iter().zip(&line.highlight_starts)
+            {
+                // normal white text (not search match)
+                display_line.push(Span::raw(
+                    &line.line_content[curr_index..highlight_range.start],
+                ));
+                // highlighted range, standing out more for the match under the cursor
+                let style = if current_match == Some(*match_start) {
+                    Style::default().fg(Color::Black).bg(Color::Yellow)
+                } else {
+                    Style::default().fg(Color::White).bg(Color::Cyan)
+                };
+                display_line.push(Span::styled(
+                    &line.line_content[highlight_range.start..highlight_range.end],
+                    style,
+                ));
+                curr_index = highlight_range.end;
+            }
+            // Add last substring as normal text
+            display_line.push(Span::raw(&line.line_content[curr_index..]));
+        } else {
+            // No search matches, display as normal white text
+            display_line.push(Span::styled(
+                &line.line_content[line_content_index..],
+                Style::default(),
+            ));
+        }
+        if let Some(color) = mark_color {
+            for span in display_line.iter_mut().skip(window.show_line_nums as usize) {
+                span.style = span
+                    .style
+                    .add_modifier(Modifier::UNDERLINED)
+                    .underline_color(color);
+            }
+        }
+        display_content.push(display_line.into());
+    }
+    let display_content: Text = display_content.into();
+
+    let content = Paragraph::new(display_content).block(content_block);
+    frame.render_widget(content, area);
+}
+
 // helper function to create a centered rect using up certain percentage of the available rect `r`
 fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let vertical = Layout::vertical([Constraint::Percentage(percent_y)]).flex(Flex::Center);
@@ -0,0 +1,69 @@
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/*
+ * Watches the directories of open files for changes made by other programs (ex. formatters or git checkout).
+ * Directories are watched rather than the files themselves, since many programs save by replacing the file
+ */
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<Event>>,
+    watched: HashSet<PathBuf>, // Directories being watched
+}
+
+// RecommendedWatcher doesn't implement Debug on every platform
+impl std::fmt::Debug for FileWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileWatcher")
+            .field("watched", &self.watched)
+            .finish()
+    }
+}
+
+impl FileWatcher {
+    /*
+     * Start the watcher, which fails on systems without a file notification API (or out of watches)
+     */
+    pub fn new() -> Option<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender).ok()?;
+        Some(Self {
+            watcher,
+            receiver,
+            watched: HashSet::new(),
+        })
+    }
+
+    // Start watching the directory holding a file, if it isn't already
+    pub fn watch(&mut self, file_path: &Path) {
+        let Some(dir) = file_path
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+        else {
+            return;
+        };
+        if !self.watched.contains(&dir)
+            && self
+                .watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .is_ok()
+        {
+            self.watched.insert(dir);
+        }
+    }
+
+    /*
+     * Paths created or changed since the last call
+     */
+    pub fn poll(&self) -> HashSet<PathBuf> {
+        self.receiver
+            .try_iter()
+            .filter_map(|event| event.ok())
+            .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+            .flat_map(|event| event.paths)
+            .collect()
+    }
+}
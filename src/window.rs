@@ -0,0 +1,692 @@
+use crate::controller::{DisplayLine, Mode};
+use crate::model::EditorModel;
+use crate::quickfix::QuickfixList;
+use crate::wrap::WrapCache;
+use count_digits::CountDigits;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use std::cmp;
+use std::mem;
+use unicode_display_width::width;
+
+// Smallest window that can still show a border and one line of text
+pub const MIN_WINDOW_HEIGHT: u16 = 3;
+pub const MIN_WINDOW_WIDTH: u16 = 8;
+
+/*
+ * A single viewport onto a buffer. Each window keeps its own wrapped content,
+ * scroll amount and cursor, so several windows can show the same buffer at different places
+ */
+#[derive(Debug)]
+pub struct Window {
+    pub id: usize,
+    pub buffer: usize, // Index of the buffer (EditorModel) shown in this window
+    pub display_content: WrapCache, // The buffer's content wrapped to the window's width, built into DisplayLines as they're needed
+    pub scroll_amount: usize,       // How many display rows did we scroll down?
+    pub cursor_pos: (u16, u16), // cursor position in the window. (y, x), or (row, col), with 1,1 being the top-left corner (1 not 0 due to border)
+    pub size: (u16, u16),       // Window size including its border (Num rows, num cols)
+    pub show_line_nums: bool,
+    pub location_list: Option<QuickfixList>, // Locations belonging to this window alone
+}
+
+impl Window {
+    pub fn new(id: usize, buffer: usize, size: (u16, u16), show_line_nums: bool) -> Self {
+        Self {
+            id,
+            buffer,
+            display_content: WrapCache::new(),
+            scroll_amount: 0,
+            cursor_pos: (1, 1),
+            size,
+            show_line_nums,
+            location_list: None,
+        }
+    }
+
+    /*
+     * Used to find the display row under the cursor using cursor coordinates and scroll amount
+     */
+    pub fn get_cursor_display_row(&self) -> usize {
+        // display rows are 0-indexed, cursor_pos is 1-indexed
+        self.scroll_amount + self.cursor_pos.0 as usize - 1
+    }
+
+    // The display row the cursor is on
+    pub fn get_cursor_line(&self) -> DisplayLine {
+        self.display_content
+            .display_line(self.get_cursor_display_row(), &[])
+    }
+
+    // Number of display rows the buffer's content takes up
+    pub fn num_rows(&self) -> usize {
+        self.display_content.num_rows()
+    }
+
+    /*
+     * Used to get which column of the file line the cursor is currently located at
+     */
+    pub fn get_cursor_inline_index(&self, mode: &Mode) -> usize {
+        // Get the number of non-character columns in the current line
+        let line = self.get_cursor_line();
+        let invalid_cols = &line.invalid_cols;
+        let num_skipped_cols = invalid_cols
+            .iter()
+            .filter(|col| col < &&self.cursor_pos.1)
+            .count();
+
+        // Sum the inline index of the displayed line the cursor is on, with the cursor position, and subtract non-char columns
+        let mut index = line.inline_index + (self.cursor_pos.1 as usize) - num_skipped_cols;
+        if let Mode::Insert = mode {
+            index -= 1; // Insertion mode has a thinner cursor that can move into 0 indexing
+        }
+        if self.show_line_nums {
+            index -= line.line_num.count_digits() + 1; // subtract the line number characters
+        }
+        index
+    }
+
+    /*
+     * Used to get the character index of the cursor in the entire file
+     */
+    pub fn get_cursor_file_index(&self, mode: &Mode) -> usize {
+        let line = self.get_cursor_line();
+        let invalid_cols = &line.invalid_cols;
+        let num_skipped_cols = invalid_cols
+            .iter()
+            .filter(|col| col < &&self.cursor_pos.1)
+            .count();
+
+        // Sum the infile index of the displayed line the cursor is on, with the cursor position, and subtract non-char columns
+        let mut index = line.infile_index + (self.cursor_pos.1 as usize) - num_skipped_cols;
+        if let Mode::Insert = mode {
+            index -= 1; // Insertion mode has a thinner cursor that can move into 0 indexing
+        }
+        if self.show_line_nums {
+            index -= line.line_num.count_digits() + 1; // subtract the line number characters
+        }
+        index
+    }
+
+    /*
+     * Used to re-wrap the displayed text after it's been updated. Only lines edited since the last re-wrap are wrapped again,
+     * along with what's in view and a screenful below it, so scrolling down doesn't wrap a line at a time
+     */
+    pub fn wrap_text(&mut self, model: &EditorModel) {
+        self.scroll_amount = self.display_content.update(
+            model,
+            self.buffer,
+            self.size.1,
+            self.show_line_nums,
+            self.scroll_amount,
+        );
+        let visible_rows = self.size.0.saturating_sub(2) as usize;
+        self.display_content
+            .wrap_rows(self.scroll_amount, 2 * visible_rows);
+    }
+
+    /*
+     * Defines the boundaries of the cursor in the window
+     */
+    pub fn top_cursor_bound(&self) -> u16 {
+        1
+    }
+    pub fn bottom_cursor_bound(&self) -> u16 {
+        // -2 because borders
+        self.size.0 - 2
+    }
+    pub fn left_cursor_bound(&self) -> u16 {
+        if self.show_line_nums {
+            return self.get_cursor_line().line_num.count_digits() as u16 + 2;
+        }
+        1
+    }
+    pub fn right_cursor_bound(&self) -> u16 {
+        // - 2 because borders
+        self.size.1 - 2
+    }
+
+    /*
+     * Keeps the cursor and scroll amount in a legal position after the window was resized,
+     * or its content was re-wrapped (possibly by an edit made from another window)
+     */
+    pub fn clamp_cursor(&mut self, mode: &Mode) {
+        // Content may have shrunk, don't scroll past the end of the file
+        let visible_rows = (self.bottom_cursor_bound() - self.top_cursor_bound() + 1) as usize;
+        let max_scroll = self.num_rows().saturating_sub(visible_rows);
+        if self.scroll_amount > max_scroll {
+            self.scroll_amount = max_scroll;
+        }
+
+        // Update cursor position if the window shrunk
+        if self.cursor_pos.1 > self.right_cursor_bound() {
+            self.cursor_pos.1 = self.right_cursor_bound();
+        }
+        if self.cursor_pos.0 > self.bottom_cursor_bound() {
+            self.cursor_pos.0 = self.bottom_cursor_bound();
+        }
+
+        // Edge case if enlarging the window and unwrapping displayed text reduced number of rows occupied by text
+        while self.get_cursor_display_row() >= self.num_rows() {
+            self.cursor_pos.0 -= 1;
+        }
+
+        // Ensure the cursor stays in a valid location
+        self.snap_cursor(mode);
+        self.slip_cursor();
+    }
+
+    // Scroll content up, but do not let it scroll out of bounds
+    pub fn scroll_up(&mut self) -> Result<(), &'static str> {
+        if self.scroll_amount > 0 {
+            // A line which hadn't been wrapped may take up more rows than it was counted as, its last row comes into view
+            self.scroll_amount -= 1;
+            self.scroll_amount += self.display_content.wrap_row(self.scroll_amount);
+            Ok(())
+        } else {
+            Err("Error: Start of file reached")
+        }
+    }
+    // Scroll content down, but do not let it scroll out of bounds
+    pub fn scroll_down(&mut self) -> Result<(), &'static str> {
+        let max_scroll_amount =
+            self.num_rows() - (self.bottom_cursor_bound() - self.top_cursor_bound() + 1) as usize;
+        if self.scroll_amount < max_scroll_amount {
+            self.scroll_amount += 1;
+            Ok(())
+        } else {
+            Err("Error: End of file reached")
+        }
+    }
+
+    pub fn cursor_up(&mut self, mode: &Mode) -> Result<(), &'static str> {
+        // Check if there is room to move the cursor upwards
+        if self.cursor_pos.0 > self.top_cursor_bound() {
+            // Move up, and adjust cursor to a viable position
+            self.cursor_pos.0 -= 1;
+            self.snap_cursor(mode);
+            self.slip_cursor();
+            Ok(())
+        } else {
+            // If at top bound, try to scroll content instead of moving cursor
+            self.scroll_up()
+        }
+    }
+
+    pub fn cursor_down(&mut self, mode: &Mode) -> Result<(), &'static str> {
+        // Edge case: small file, big window. End of file was reached
+        if (self.cursor_pos.0 as usize) == self.num_rows() {
+            return Err("Error: End of file reached");
+        }
+
+        // Check if there is room to move cursor downwards
+        if self.cursor_pos.0 < self.bottom_cursor_bound() {
+            // Move down, and adjust cursor to a viable position
+            self.cursor_pos.0 += 1;
+            self.snap_cursor(mode);
+            self.slip_cursor();
+            Ok(())
+        } else {
+            // If at bottom bound, try to scroll content instead of moving cursor
+            self.scroll_down()
+        }
+    }
+
+    pub fn cursor_right(&mut self, mode: &Mode) -> Result<(), &'static str> {
+        let line = self.get_cursor_line();
+
+        // If cursor will move into the middle of a wide character (ex tab space) 'slip' it rightwards until the next character is valid
+        let invalid_cols = &line.invalid_cols;
+        while invalid_cols.contains(&(self.cursor_pos.1 + 1)) {
+            self.cursor_pos.1 += 1;
+        }
+
+        let mut bound = width(&line.line_content);
+        // Allow the cursor to move to the end of the line if in insertion mode
+        if let Mode::Insert = mode {
+            bound += 1;
+        }
+
+        // If cursor is at or past the right boundary (end of the line), move to the start of the next line if available
+        if self.cursor_pos.1 as u64 >= bound {
+            // Edge case: small file, big window. End of file was reached
+            if (self.get_cursor_display_row() + 1) == self.num_rows() {
+                return Err("Error: End of file reached");
+            }
+
+            // If scrolling needed, try to do so
+            if self.cursor_pos.0 == self.bottom_cursor_bound() {
+                self.scroll_down()?;
+            } else {
+                // Only move down if did not scroll
+                self.cursor_pos.0 += 1;
+            }
+            self.cursor_pos.1 = self.left_cursor_bound(); // Move to start of next line
+        } else {
+            self.cursor_pos.1 += 1; // Move cursor one step to the right
+        }
+        Ok(())
+    }
+
+    pub fn cursor_left(&mut self, mode: &Mode) -> Result<(), &'static str> {
+        // If the cursor is at the start of the line, move to the end of the previous line if available
+        if self.cursor_pos.1 == self.left_cursor_bound() {
+            // If scrolling needed, try to do so
+            if self.cursor_pos.0 == self.top_cursor_bound() {
+                self.scroll_up()?;
+            } else {
+                // Only move up if did not scroll
+                self.cursor_pos.0 -= 1;
+            }
+            // Get end of line coordinates
+            let line = self.get_cursor_line().line_content;
+            let mut bound = width(&line);
+
+            // Allow the cursor to move one space further if in insertion mode
+            if let Mode::Insert = mode {
+                bound += 1;
+            }
+
+            self.cursor_pos.1 = bound as u16; // Move to end of prev line
+        } else {
+            self.cursor_pos.1 -= 1; // Move cursor one step to the left
+        }
+        self.slip_cursor();
+        Ok(())
+    }
+
+    // Snap cursor to end of line after moving up/down into a shorter line of text
+    pub fn snap_cursor(&mut self, mode: &Mode) {
+        // Get end of line coordinates
+        let line = self.get_cursor_line().line_content;
+        let mut bound = cmp::max(width(&line), 1);
+
+        // Allow the cursor to move to the end of the line if in insertion mode
+        if let Mode::Insert = mode
+            && !line.is_empty()
+        {
+            bound += 1;
+        }
+
+        // Snap cursor to end of line after moving up
+        let line_len = bound as u16;
+        if line_len < self.cursor_pos.1 {
+            self.cursor_pos.1 = line_len;
+        }
+    }
+
+    /*
+     * Place the cursor on the character at the given file index, scrolling it into view if needed.
+     * Used to jump straight to a location (ex. a search result)
+     */
+    pub fn move_cursor_to_index(&mut self, file_index: usize, mode: &Mode) {
+        // Last displayed row starting at or before the index
+        let mut row = self.display_content.row_of_index(file_index);
+
+        // Scroll so the row is roughly centred, unless it's already visible
+        let visible_rows = (self.bottom_cursor_bound() - self.top_cursor_bound() + 1) as usize;
+        let scroll = self.scroll_amount;
+        if row < scroll || row >= scroll + visible_rows {
+            // Wrapping the lines scrolled into view can push the row further down, so centre it again until it stays put
+            loop {
+                let max_scroll = self.num_rows().saturating_sub(visible_rows);
+                self.scroll_amount = cmp::min(row.saturating_sub(visible_rows / 2), max_scroll);
+                self.display_content
+                    .wrap_rows(self.scroll_amount, visible_rows);
+                let wrapped_row = self.display_content.row_of_index(file_index);
+                if wrapped_row == row {
+                    break;
+                }
+                row = wrapped_row;
+            }
+        }
+        self.cursor_pos.0 = (row - self.scroll_amount) as u16 + 1;
+
+        // Find the column showing the character, skipping the line number and wide character padding.
+        // Outside of insertion mode the cursor's file index counts the character under it
+        let target = match mode {
+            Mode::Insert => file_index,
+            _ => file_index + 1,
+        };
+        self.cursor_pos.1 = self.left_cursor_bound();
+        let invalid_cols = self.display_content.display_line(row, &[]).invalid_cols;
+        for col in self.left_cursor_bound()..=self.right_cursor_bound() {
+            if invalid_cols.contains(&col) {
+                continue;
+            }
+            self.cursor_pos.1 = col;
+            if self.get_cursor_file_index(mode) >= target {
+                break;
+            }
+        }
+        self.snap_cursor(mode);
+        self.slip_cursor();
+    }
+
+    // If cursor just moved into the middle of a wide character (ex tab space) 'slip' it leftwards to valid space
+    // Also used to keep the cursor out of the line numbers
+    pub fn slip_cursor(&mut self) {
+        let invalid_cols = self.get_cursor_line().invalid_cols;
+        while invalid_cols.contains(&self.cursor_pos.1) {
+            self.cursor_pos.1 -= 1;
+        }
+
+        // Ensure cursor shifts out of the line number region
+        while self.cursor_pos.1 < self.left_cursor_bound() {
+            self.cursor_pos.1 += 1;
+        }
+    }
+}
+
+/*
+ * Tree describing how the content area is divided between windows.
+ * Splits store a weight per child, which is roughly the child's size in cells along the split direction
+ */
+#[derive(Debug, Clone)]
+pub enum LayoutNode {
+    Leaf(usize), // Window id
+    Split {
+        direction: Direction, // Vertical stacks children top to bottom, Horizontal places them side by side
+        children: Vec<(LayoutNode, u16)>,
+    },
+}
+
+impl LayoutNode {
+    pub fn contains(&self, window_id: usize) -> bool {
+        match self {
+            LayoutNode::Leaf(id) => *id == window_id,
+            LayoutNode::Split { children, .. } => {
+                children.iter().any(|(child, _)| child.contains(window_id))
+            }
+        }
+    }
+
+    // Window ids in layout order (top to bottom, left to right)
+    pub fn window_ids(&self) -> Vec<usize> {
+        match self {
+            LayoutNode::Leaf(id) => vec![*id],
+            LayoutNode::Split { children, .. } => children
+                .iter()
+                .flat_map(|(child, _)| child.window_ids())
+                .collect(),
+        }
+    }
+
+    /*
+     * Divide the given area between the windows of this tree
+     */
+    pub fn areas(&self, area: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            LayoutNode::Leaf(id) => vec![(*id, area)],
+            LayoutNode::Split {
+                direction,
+                children,
+            } => {
+                let chunks = Layout::default()
+                    .direction(*direction)
+                    .constraints(children.iter().map(|(_, weight)| Constraint::Fill(*weight)))
+                    .split(area);
+                children
+                    .iter()
+                    .zip(chunks.iter())
+                    .flat_map(|((child, _), chunk)| child.areas(*chunk))
+                    .collect()
+            }
+        }
+    }
+
+    /*
+     * Split the target window in the given direction, placing the new window before it (above or to the left).
+     * extent is the target window's current size along the split direction
+     */
+    pub fn split(
+        &mut self,
+        target: usize,
+        new_id: usize,
+        direction: Direction,
+        extent: u16,
+    ) -> bool {
+        match self {
+            LayoutNode::Leaf(id) => {
+                if *id != target {
+                    return false;
+                }
+                *self = LayoutNode::Split {
+                    direction,
+                    children: vec![
+                        (LayoutNode::Leaf(new_id), cmp::max(extent - extent / 2, 1)),
+                        (LayoutNode::Leaf(target), cmp::max(extent / 2, 1)),
+                    ],
+                };
+                true
+            }
+            LayoutNode::Split {
+                direction: split_direction,
+                children,
+            } => {
+                // Splitting along the same direction just adds a sibling
+                if *split_direction == direction
+                    && let Some(pos) = children.iter().position(
+                        |(child, _)| matches!(child, LayoutNode::Leaf(id) if *id == target),
+                    )
+                {
+                    let weight = children[pos].1;
+                    children[pos].1 = cmp::max(weight / 2, 1);
+                    children.insert(
+                        pos,
+                        (LayoutNode::Leaf(new_id), cmp::max(weight - weight / 2, 1)),
+                    );
+                    return true;
+                }
+                children
+                    .iter_mut()
+                    .any(|(child, _)| child.split(target, new_id, direction, extent))
+            }
+        }
+    }
+
+    /*
+     * Remove the target window, giving its space to a neighbouring sibling.
+     * Splits left with a single child collapse into that child
+     */
+    pub fn remove(&mut self, target: usize) -> bool {
+        let LayoutNode::Split { children, .. } = self else {
+            return false;
+        };
+        if let Some(pos) = children
+            .iter()
+            .position(|(child, _)| matches!(child, LayoutNode::Leaf(id) if *id == target))
+        {
+            let (_, weight) = children.remove(pos);
+            let neighbour = pos.saturating_sub(1);
+            children[neighbour].1 += weight;
+            if children.len() == 1 {
+                let (only_child, _) = children.remove(0);
+                *self = only_child;
+            }
+            return true;
+        }
+        children.iter_mut().any(|(child, _)| child.remove(target))
+    }
+
+    // Replace every window id (ex. when saving or restoring a session, which numbers windows in layout order)
+    pub fn map_ids(&mut self, map: &impl Fn(usize) -> usize) {
+        match self {
+            LayoutNode::Leaf(id) => *id = map(*id),
+            LayoutNode::Split { children, .. } => {
+                for (child, _) in children.iter_mut() {
+                    child.map_ids(map);
+                }
+            }
+        }
+    }
+
+    // Exchange the places of two windows
+    pub fn swap(&mut self, a: usize, b: usize) {
+        match self {
+            LayoutNode::Leaf(id) if *id == a => *id = b,
+            LayoutNode::Leaf(id) if *id == b => *id = a,
+            LayoutNode::Leaf(_) => {}
+            LayoutNode::Split { children, .. } => {
+                for (child, _) in children.iter_mut() {
+                    child.swap(a, b);
+                }
+            }
+        }
+    }
+
+    /*
+     * Add a window along the whole bottom of the layout, taking its height from the windows above.
+     * total is the height of the area being divided
+     */
+    pub fn split_bottom(&mut self, new_id: usize, extent: u16, total: u16) {
+        let above = mem::replace(self, LayoutNode::Leaf(new_id));
+        *self = LayoutNode::Split {
+            direction: Direction::Vertical,
+            children: vec![
+                (above, cmp::max(total.saturating_sub(extent), 1)),
+                (LayoutNode::Leaf(new_id), extent),
+            ],
+        };
+    }
+
+    /*
+     * Grow (or shrink for negative delta) the target window along the given direction,
+     * taking space from its next sibling (or previous sibling for the last window)
+     */
+    pub fn resize(&mut self, target: usize, direction: Direction, delta: i32, min: u16) -> bool {
+        let LayoutNode::Split {
+            direction: split_direction,
+            children,
+        } = self
+        else {
+            return false;
+        };
+        let Some(pos) = children
+            .iter()
+            .position(|(child, _)| child.contains(target))
+        else {
+            return false;
+        };
+        // Prefer the split closest to the window
+        if children[pos].0.resize(target, direction, delta, min) {
+            return true;
+        }
+        if *split_direction != direction || children.len() < 2 {
+            return false;
+        }
+        let other = if pos + 1 < children.len() {
+            pos + 1
+        } else {
+            pos - 1
+        };
+        // Don't shrink either window below the minimum size
+        let max_grow = children[other].1 as i32 - min as i32;
+        let max_shrink = children[pos].1 as i32 - min as i32;
+        let delta = delta.min(max_grow).max(-max_shrink);
+        children[pos].1 = (children[pos].1 as i32 + delta) as u16;
+        children[other].1 = (children[other].1 as i32 - delta) as u16;
+        true
+    }
+
+    /*
+     * Give every window of each split an equal share of the given area
+     */
+    pub fn equalize(&mut self, area: Rect) {
+        if let LayoutNode::Split {
+            direction,
+            children,
+        } = self
+        {
+            let chunks = Layout::default()
+                .direction(*direction)
+                .constraints(children.iter().map(|_| Constraint::Fill(1)))
+                .split(area);
+            for ((child, weight), chunk) in children.iter_mut().zip(chunks.iter()) {
+                *weight = match direction {
+                    Direction::Vertical => cmp::max(chunk.height, 1),
+                    Direction::Horizontal => cmp::max(chunk.width, 1),
+                };
+                child.equalize(*chunk);
+            }
+        }
+    }
+
+    /*
+     * Rotate the windows in the same row/column as the target window.
+     * Only possible when none of those windows are split further
+     */
+    pub fn rotate(&mut self, target: usize, forwards: bool) -> Result<(), &'static str> {
+        let LayoutNode::Split { children, .. } = self else {
+            return Err("Error: No other windows to rotate");
+        };
+        if children
+            .iter()
+            .any(|(child, _)| matches!(child, LayoutNode::Leaf(id) if *id == target))
+        {
+            if children
+                .iter()
+                .any(|(child, _)| matches!(child, LayoutNode::Split { .. }))
+            {
+                return Err("Error: Cannot rotate when another window is split");
+            }
+            let mut ids: Vec<usize> = children
+                .iter()
+                .flat_map(|(child, _)| child.window_ids())
+                .collect();
+            if forwards {
+                ids.rotate_right(1);
+            } else {
+                ids.rotate_left(1);
+            }
+            for ((child, _), id) in children.iter_mut().zip(ids) {
+                *child = LayoutNode::Leaf(id);
+            }
+            return Ok(());
+        }
+        match children
+            .iter_mut()
+            .find(|(child, _)| child.contains(target))
+        {
+            Some((child, _)) => child.rotate(target, forwards),
+            None => Err("Error: No other windows to rotate"),
+        }
+    }
+}
+
+/*
+ * A tab page holds its own window layout, so several layouts can be kept side by side
+ */
+#[derive(Debug)]
+pub struct TabPage {
+    pub windows: Vec<Window>, // Every window shown in this tab's content area
+    pub layout: LayoutNode,   // How the content area is split between windows
+    pub active_window: usize, // Id of the window with focus
+    pub previous_window: Option<usize>, // Id of the window which had focus before it
+}
+
+impl TabPage {
+    pub fn new(window: Window) -> Self {
+        Self {
+            layout: LayoutNode::Leaf(window.id),
+            active_window: window.id,
+            previous_window: None,
+            windows: vec![window],
+        }
+    }
+
+    /*
+     * Used to access the window with focus
+     */
+    pub fn active_index(&self) -> usize {
+        self.windows
+            .iter()
+            .position(|window| window.id == self.active_window)
+            .unwrap()
+    }
+    pub fn win(&self) -> &Window {
+        &self.windows[self.active_index()]
+    }
+    pub fn win_mut(&mut self) -> &mut Window {
+        let idx = self.active_index();
+        &mut self.windows[idx]
+    }
+}
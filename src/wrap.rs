@@ -0,0 +1,571 @@
+use crate::controller::DisplayLine;
+use crate::model::EditorModel;
+use core::ops::Range;
+use count_digits::CountDigits;
+use ropey::{Rope, RopeSlice};
+use std::cell::RefCell;
+use std::cmp;
+use unicode_display_width::width;
+
+const TAB_SIZE: u16 = 4;
+// Lines kept in each chunk of LineRows, which edits split and merge chunks to stay close to
+const CHUNK_LINES: usize = 256;
+
+/*
+ * A window's soft-wrapped view of its buffer. A file line is only broken into display rows once something asks
+ * for its rows (ex. it's drawn, or the cursor moves onto it), and until then it is counted as a single row.
+ * What was worked out is kept until an edit touches that line, so typing only re-wraps the edited lines.
+ * Rows are only turned into DisplayLines when they are needed, so drawing a window only builds the rows it shows
+ */
+#[derive(Debug)]
+pub struct WrapCache {
+    text: Rope, // Buffer contents the rows were worked out for. Clones of a rope share its text, so this is cheap
+    buffer: usize,
+    num_edits: Option<usize>, // How many of the buffer's edits the cache has caught up with, None until first wrapped
+    width: u16,               // Window width, including its border
+    show_line_nums: bool,
+    rows: RefCell<LineRows>, // Where each file line breaks into rows. Lines are wrapped as they're asked about, hence the RefCell
+}
+
+impl WrapCache {
+    // An empty buffer, showing a single empty row
+    pub fn new() -> Self {
+        Self {
+            text: Rope::new(),
+            buffer: 0,
+            num_edits: None,
+            width: 0,
+            show_line_nums: false,
+            rows: RefCell::new(LineRows::unwrapped(1)),
+        }
+    }
+
+    /*
+     * Catch up with changes to the buffer shown, forgetting how the lines edited since the last update were wrapped.
+     * Everything is forgotten when the window shows another buffer, changes size or toggles line numbers,
+     * or has fallen too far behind the buffer's edits. Nothing is wrapped here, lines are wrapped when asked about.
+     * Returns the row to scroll to so the window still starts at the same row of the same file line,
+     * as the lines above it may now be counted as a different number of rows
+     */
+    pub fn update(
+        &mut self,
+        model: &EditorModel,
+        buffer: usize,
+        width: u16,
+        show_line_nums: bool,
+        top_row: usize,
+    ) -> usize {
+        let top = (self.num_edits.is_some() && self.buffer == buffer).then(|| {
+            let (line_idx, first_row) = self.rows.get_mut().line_at_row(top_row);
+            (line_idx, top_row - first_row)
+        });
+        let edits = self
+            .num_edits
+            .filter(|_| {
+                self.buffer == buffer
+                    && self.width == width
+                    && self.show_line_nums == show_line_nums
+            })
+            .and_then(|num_edits| model.edits_since(num_edits));
+        let rows = self.rows.get_mut();
+        match edits {
+            Some(edits) => {
+                for edit in edits {
+                    let start = cmp::min(edit.line, rows.len());
+                    let end = cmp::min(edit.line + edit.removed, rows.len());
+                    rows.splice(start..end, edit.added);
+
+                    // Lines after the edit are renumbered, which changes the room left beside numbers gaining or losing a digit
+                    let shift = edit.added.abs_diff(edit.removed);
+                    if show_line_nums && shift > 0 {
+                        let mut power = 10;
+                        while power <= rows.len() + shift {
+                            let first =
+                                cmp::max((power - 1).saturating_sub(shift), start + edit.added);
+                            let last = cmp::min(power + shift, rows.len());
+                            rows.forget(first..last);
+                            power *= 10;
+                        }
+                    }
+                }
+            }
+            None => *rows = LineRows::unwrapped(model.rope.len_lines()),
+        }
+        self.text = model.rope.clone();
+        self.buffer = buffer;
+        self.num_edits = Some(model.num_edits());
+        self.width = width;
+        self.show_line_nums = show_line_nums;
+
+        match top {
+            Some((line_idx, row_in_line)) => {
+                let line_idx = cmp::min(line_idx, self.text.len_lines() - 1);
+                let num_breaks = self.breaks(line_idx).len();
+                self.rows.borrow().first_row(line_idx) + cmp::min(row_in_line, num_breaks)
+            }
+            None => top_row,
+        }
+    }
+
+    /*
+     * Wrap the lines shown on a number of rows starting from the given row. Lines above it are left alone,
+     * so the row keeps showing the same line
+     */
+    pub fn wrap_rows(&self, first_row: usize, num_rows: usize) {
+        let (mut line_idx, mut row) = self.rows.borrow().line_at_row(first_row);
+        while line_idx < self.text.len_lines() && row < first_row + num_rows {
+            row += self.breaks(line_idx).len() + 1;
+            line_idx += 1;
+        }
+    }
+
+    /*
+     * Wrap the line a row is on, returning how many more rows than the one it was counted as it takes up.
+     * Used when scrolling up onto a line, whose last row is the one which should come into view
+     */
+    pub fn wrap_row(&self, row: usize) -> usize {
+        let (line_idx, _) = self.rows.borrow().line_at_row(row);
+        if self.rows.borrow().get(line_idx).is_some() {
+            return 0;
+        }
+        self.breaks(line_idx).len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.borrow().num_rows()
+    }
+
+    // Display row the character at a file index is shown on
+    pub fn row_of_index(&self, file_index: usize) -> usize {
+        let file_index = cmp::min(file_index, self.text.len_chars());
+        let line_idx = self.text.char_to_line(file_index);
+        let inline_index = file_index - self.text.line_to_char(line_idx);
+        let row_in_line = self
+            .breaks(line_idx)
+            .partition_point(|start| *start <= inline_index);
+        self.rows.borrow().first_row(line_idx) + row_in_line
+    }
+
+    // Display row a (1-indexed) file line starts on
+    pub fn row_of_line(&self, line_num: usize) -> usize {
+        let rows = self.rows.borrow();
+        rows.first_row(cmp::min(line_num.saturating_sub(1), rows.len()))
+    }
+
+    /*
+     * Build the DisplayLine for a row, with its line number, tabs expanded to spaces and the given search matches marked
+     */
+    pub fn display_line(&self, row: usize, match_ranges: &[Range<usize>]) -> DisplayLine {
+        // A line not yet wrapped is counted as one row, so the row is its first and stays so once it's wrapped
+        let (line_idx, first_row) = self.rows.borrow().line_at_row(row);
+        let breaks = self.breaks(line_idx);
+        let line = self.text.line(line_idx);
+        let row_in_line = row - first_row;
+        let start = match row_in_line {
+            0 => 0,
+            _ => breaks[row_in_line - 1],
+        };
+        let end = breaks.get(row_in_line).copied().unwrap_or(line_len(line));
+        let infile_index = self.text.line_to_char(line_idx) + start;
+
+        let mut display_line = DisplayLine::new(line_idx + 1, infile_index, start);
+        let mut total_char_width = 0;
+        if self.show_line_nums {
+            display_line.line_content = format!("{}|", line_idx + 1);
+            total_char_width = self.gutter_width(line_idx);
+        }
+        // Byte offset in line_content of each character shown, used to mark search matches
+        let mut byte_offsets = vec![];
+        for character in line.slice(start..end).chars() {
+            byte_offsets.push(display_line.line_content.len());
+            let char_width = display_width(character, total_char_width);
+            if character == '\t' {
+                // Render tab space as a set of spaces
+                for _ in 0..char_width {
+                    display_line.line_content.push(' ');
+                }
+            } else {
+                display_line.line_content.push(character);
+            }
+            // Keep track of 'invalid columns' for cursor due to tabs and wide characters
+            for i in 1..char_width {
+                display_line.invalid_cols.push(total_char_width + 1 + i);
+            }
+            total_char_width += char_width;
+        }
+        byte_offsets.push(display_line.line_content.len());
+
+        // Mark the parts of search matches shown on this row
+        let row_end = infile_index + end - start;
+        let first_match = match_ranges.partition_point(|range| range.end <= infile_index);
+        for range in match_ranges[first_match..]
+            .iter()
+            .take_while(|range| range.start < row_end)
+        {
+            let match_start = range.start.saturating_sub(infile_index);
+            let match_end = cmp::min(range.end, row_end) - infile_index;
+            if match_start < match_end {
+                display_line.highlight_ranges.push(Range {
+                    start: byte_offsets[match_start],
+                    end: byte_offsets[match_end],
+                });
+                display_line.highlight_starts.push(range.start);
+            }
+        }
+        display_line
+    }
+
+    // Character indices (in the line) of each row of a file line after its first, wrapping the line if it isn't yet
+    fn breaks(&self, line_idx: usize) -> Vec<usize> {
+        if let Some(breaks) = self.rows.borrow().get(line_idx) {
+            return breaks.to_vec();
+        }
+        let breaks = wrap_line(
+            self.text.line(line_idx),
+            self.gutter_width(line_idx),
+            self.width.saturating_sub(2), // to accomodate the two borders
+        );
+        self.rows.borrow_mut().set(line_idx, breaks.clone());
+        breaks
+    }
+
+    // Columns taken up by a line's number, followed by a "|"
+    fn gutter_width(&self, line_idx: usize) -> u16 {
+        if self.show_line_nums {
+            (line_idx + 1).count_digits() as u16 + 1
+        } else {
+            0
+        }
+    }
+}
+
+/*
+ * Where each file line breaks into rows, kept in chunks of lines which each count the rows they take up.
+ * Finding the row a line starts on (or the line a row is on) adds up whole chunks before looking inside one,
+ * and an edit only has to move the lines of the chunks it touches
+ */
+#[derive(Debug)]
+struct LineRows {
+    chunks: Vec<Chunk>,
+}
+
+#[derive(Debug)]
+struct Chunk {
+    line_breaks: Vec<Option<Vec<usize>>>, // For each line, the character index (in the line) of each row after its first. None until wrapped
+    num_rows: usize,                      // Rows taken up by the chunk's lines
+}
+
+impl LineRows {
+    // Lines which are all yet to be wrapped
+    fn unwrapped(num_lines: usize) -> Self {
+        let mut rows = Self { chunks: vec![] };
+        rows.splice(0..0, num_lines);
+        rows
+    }
+
+    fn len(&self) -> usize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.line_breaks.len())
+            .sum()
+    }
+
+    fn num_rows(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.num_rows).sum()
+    }
+
+    // Chunk holding a line, and where the line is in it. A line past the end is placed after the last chunk's lines
+    fn locate(&self, mut line_idx: usize) -> (usize, usize) {
+        for (chunk_idx, chunk) in self.chunks.iter().enumerate() {
+            if line_idx < chunk.line_breaks.len() {
+                return (chunk_idx, line_idx);
+            }
+            line_idx -= chunk.line_breaks.len();
+        }
+        match self.chunks.last() {
+            Some(chunk) => (self.chunks.len() - 1, chunk.line_breaks.len()),
+            None => (0, 0),
+        }
+    }
+
+    // Where a line breaks into rows, or None if it hasn't been wrapped
+    fn get(&self, line_idx: usize) -> Option<&[usize]> {
+        let (chunk_idx, idx) = self.locate(line_idx);
+        self.chunks[chunk_idx].line_breaks[idx].as_deref()
+    }
+
+    fn set(&mut self, line_idx: usize, breaks: Vec<usize>) {
+        let (chunk_idx, idx) = self.locate(line_idx);
+        let chunk = &mut self.chunks[chunk_idx];
+        chunk.num_rows -= line_rows(&chunk.line_breaks[idx]);
+        chunk.num_rows += breaks.len() + 1;
+        chunk.line_breaks[idx] = Some(breaks);
+    }
+
+    // Forget how lines were wrapped, so they're wrapped again when next asked about
+    fn forget(&mut self, lines: Range<usize>) {
+        let (chunk_idx, mut idx) = self.locate(lines.start);
+        let mut remaining = lines.len();
+        for chunk in &mut self.chunks[chunk_idx..] {
+            for breaks in chunk.line_breaks.iter_mut().skip(idx).take(remaining) {
+                chunk.num_rows -= line_rows(breaks);
+                *breaks = None;
+                chunk.num_rows += 1;
+                remaining -= 1;
+            }
+            if remaining == 0 {
+                break;
+            }
+            idx = 0;
+        }
+    }
+
+    // Display row a line starts on. The line after the last starts on the total number of rows
+    fn first_row(&self, mut line_idx: usize) -> usize {
+        let mut row = 0;
+        for chunk in &self.chunks {
+            if line_idx < chunk.line_breaks.len() {
+                return row
+                    + chunk.line_breaks[..line_idx]
+                        .iter()
+                        .map(line_rows)
+                        .sum::<usize>();
+            }
+            line_idx -= chunk.line_breaks.len();
+            row += chunk.num_rows;
+        }
+        row
+    }
+
+    // Line a display row is on, and the row it starts on. Rows past the end are counted as on the last line
+    fn line_at_row(&self, row: usize) -> (usize, usize) {
+        let mut line_idx = 0;
+        let mut first_row = 0;
+        for (chunk_idx, chunk) in self.chunks.iter().enumerate() {
+            if row >= first_row + chunk.num_rows && chunk_idx + 1 < self.chunks.len() {
+                line_idx += chunk.line_breaks.len();
+                first_row += chunk.num_rows;
+                continue;
+            }
+            for (idx, breaks) in chunk.line_breaks.iter().enumerate() {
+                let num_rows = line_rows(breaks);
+                if row < first_row + num_rows || idx + 1 == chunk.line_breaks.len() {
+                    return (line_idx + idx, first_row);
+                }
+                first_row += num_rows;
+            }
+        }
+        (0, 0)
+    }
+
+    /*
+     * Replace a range of lines with a number of lines yet to be wrapped. The chunks touched are split up again,
+     * taking in the next chunk if they've become small, so chunks stay close to CHUNK_LINES lines
+     */
+    fn splice(&mut self, lines: Range<usize>, added: usize) {
+        let (first_chunk, start) = self.locate(lines.start);
+        let mut last_chunk = cmp::max(self.locate(lines.end).0, first_chunk);
+        let mut line_breaks = vec![];
+        for chunk in self
+            .chunks
+            .iter_mut()
+            .take(last_chunk + 1)
+            .skip(first_chunk)
+        {
+            line_breaks.append(&mut chunk.line_breaks);
+        }
+        line_breaks.splice(start..start + lines.len(), (0..added).map(|_| None));
+        if line_breaks.len() < CHUNK_LINES / 2 && last_chunk + 1 < self.chunks.len() {
+            last_chunk += 1;
+            line_breaks.append(&mut self.chunks[last_chunk].line_breaks);
+        }
+
+        let num_chunks = line_breaks.len().div_ceil(CHUNK_LINES);
+        let mut chunks = Vec::with_capacity(num_chunks);
+        for chunk_idx in (0..num_chunks).rev() {
+            // Share the lines out evenly, so an edit doesn't leave a tiny chunk behind
+            let chunk_len = line_breaks.len() / (chunk_idx + 1);
+            let chunk_breaks = line_breaks.split_off(line_breaks.len() - chunk_len);
+            chunks.push(Chunk {
+                num_rows: chunk_breaks.iter().map(line_rows).sum(),
+                line_breaks: chunk_breaks,
+            });
+        }
+        chunks.reverse();
+        let end = cmp::min(last_chunk + 1, self.chunks.len());
+        self.chunks.splice(first_chunk..end, chunks);
+    }
+}
+
+// Rows a line takes up, counting a line not yet wrapped as one row
+fn line_rows(breaks: &Option<Vec<usize>>) -> usize {
+    breaks.as_ref().map_or(1, |breaks| breaks.len() + 1)
+}
+
+/*
+ * Work out where a file line breaks into rows, returning the character index each row after the first starts at.
+ * Every row starts after the gutter holding the line number (if shown)
+ */
+fn wrap_line(line: RopeSlice, gutter: u16, max_line_len: u16) -> Vec<usize> {
+    let mut breaks = vec![];
+    // Only tabs take up more columns than bytes, so short lines without them fit without checking each character
+    if line.len_bytes() + gutter as usize <= max_line_len as usize
+        && line.chunks().all(|chunk| !chunk.contains('\t'))
+    {
+        return breaks;
+    }
+    let mut total_char_width = gutter;
+    let mut row_len = 0;
+    for (idx, character) in line.chars().take(line_len(line)).enumerate() {
+        let mut char_width = display_width(character, total_char_width);
+        // Character doesn't fit, so start a new row
+        if total_char_width + char_width > max_line_len && row_len > 0 {
+            breaks.push(idx);
+            total_char_width = gutter;
+            row_len = 0;
+            char_width = display_width(character, total_char_width);
+        }
+        total_char_width += char_width;
+        row_len += 1;
+    }
+    breaks
+}
+
+// Number of columns needed to display a character (ex. some emoticons take 2 columns), when starting at the given column
+fn display_width(character: char, total_char_width: u16) -> u16 {
+    match character {
+        // Tabs stretch to the next tab stop
+        '\t' => TAB_SIZE - (total_char_width % TAB_SIZE),
+        ' '..='~' => 1,
+        _ => width(&character.to_string()) as u16,
+    }
+}
+
+// Number of characters in a file line, leaving out its newline
+fn line_len(line: RopeSlice) -> usize {
+    match line.chars_at(line.len_chars()).prev() {
+        Some('\n') => line.len_chars() - 1,
+        _ => line.len_chars(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "fn main() {\n\tlet greeting = \"hello, world\";\n    println!(\"{}\", greeting);\n\n    // a comment long enough to wrap onto a few rows of a narrow window\n}\n";
+
+    // What every row of a cache shows, wrapping any lines it hasn't yet
+    fn rows(cache: &WrapCache) -> Vec<(usize, usize, String)> {
+        let mut rows = vec![];
+        let mut row = 0;
+        while row < cache.num_rows() {
+            let line = cache.display_line(row, &[]);
+            rows.push((line.line_num, line.infile_index, line.line_content));
+            row += 1;
+        }
+        rows
+    }
+
+    // A cache of the model wrapped from scratch, to compare one caught up with edits against
+    fn wrapped(
+        model: &EditorModel,
+        width: u16,
+        show_line_nums: bool,
+    ) -> Vec<(usize, usize, String)> {
+        let mut cache = WrapCache::new();
+        cache.update(model, 0, width, show_line_nums, 0);
+        rows(&cache)
+    }
+
+    #[test]
+    fn catches_up_with_edits() {
+        let mut model = EditorModel::unnamed(TEXT.repeat(3).as_bytes());
+        let mut cache = WrapCache::new();
+        cache.update(&model, 0, 20, false, 0);
+        // Only some of the lines have been wrapped before the edits
+        cache.wrap_rows(0, 5);
+
+        model.insert_char('x', 15);
+        model.insert_char('\n', 40);
+        model.delete_range(10, 60);
+        model.replace_range(5, 8, "one\ntwo\nthree");
+        cache.update(&model, 0, 20, false, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 20, false));
+
+        model.delete_to_end(30);
+        cache.update(&model, 0, 20, false, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 20, false));
+    }
+
+    #[test]
+    fn wraps_again_after_resizing() {
+        let model = EditorModel::unnamed(TEXT.as_bytes());
+        let mut cache = WrapCache::new();
+        cache.update(&model, 0, 40, false, 0);
+        let wide_rows = rows(&cache);
+        assert_eq!(wide_rows, wrapped(&model, 40, false));
+
+        cache.update(&model, 0, 16, false, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 16, false));
+        assert!(rows(&cache).len() > wide_rows.len());
+        cache.update(&model, 0, 16, true, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 16, true));
+    }
+
+    #[test]
+    fn wraps_again_when_line_numbers_gain_or_lose_a_digit() {
+        // At this width, the lines only fit beside a single digit line number
+        let mut model = EditorModel::unnamed("abcdefgh\n".repeat(9).as_bytes());
+        let mut cache = WrapCache::new();
+        cache.update(&model, 0, 12, true, 0);
+        assert_eq!(rows(&cache).len(), 10);
+
+        model.insert_char('\n', 0);
+        cache.update(&model, 0, 12, true, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 12, true));
+        assert_eq!(rows(&cache).len(), 12);
+
+        model.replace_range(0, 1, "\n\n\n");
+        cache.update(&model, 0, 12, true, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 12, true));
+
+        model.delete_range(0, 4);
+        cache.update(&model, 0, 12, true, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 12, true));
+        assert_eq!(rows(&cache).len(), 10);
+    }
+
+    #[test]
+    fn only_wraps_lines_asked_about() {
+        let mut model = EditorModel::unnamed(
+            "a line which needs more than one row\n"
+                .repeat(2000)
+                .as_bytes(),
+        );
+        let mut cache = WrapCache::new();
+        cache.update(&model, 0, 20, false, 0);
+        cache.wrap_rows(0, 10);
+        let num_wrapped = |cache: &WrapCache| {
+            let rows = cache.rows.borrow();
+            (0..rows.len())
+                .filter(|line_idx| rows.get(*line_idx).is_some())
+                .count()
+        };
+        assert_eq!(num_wrapped(&cache), 5);
+        assert_eq!(cache.num_rows(), 2001 + 5);
+
+        // Rows further down are worked out when asked for, without wrapping the lines before them
+        let row = cache.row_of_line(1500);
+        assert_eq!(cache.display_line(row, &[]).line_num, 1500);
+        assert_eq!(num_wrapped(&cache), 6);
+
+        // Typing across chunks of lines keeps the rows counted right
+        for idx in (0..2000).step_by(97) {
+            let line_start = model.rope.line_to_char(idx);
+            model.insert_char('\n', line_start);
+        }
+        cache.update(&model, 0, 20, false, 0);
+        assert_eq!(rows(&cache), wrapped(&model, 20, false));
+    }
+}